@@ -0,0 +1,485 @@
+use crate::opcode::Opcode;
+use crate::symbols::Symbols;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+const LONG_I_OPCODE: u16 = 0xF000;
+
+//Where in the source something went wrong, both 1 based
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl Error for AsmError {}
+
+#[derive(Debug, Clone, Copy)]
+struct Token<'a> {
+    text: &'a str,
+    line: usize,
+    column: usize,
+}
+
+impl Token<'_> {
+    fn error(&self, message: String) -> AsmError {
+        AsmError {
+            line: self.line,
+            column: self.column,
+            message,
+        }
+    }
+}
+
+enum Statement<'a> {
+    Instruction {
+        mnemonic: Token<'a>,
+        operands: Vec<Token<'a>>,
+    },
+    Bytes(Vec<Token<'a>>),
+    Words(Vec<Token<'a>>),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Operand<'a> {
+    V(u8),
+    I,
+    //[I]
+    IndirectI,
+    Dt,
+    St,
+    K,
+    F,
+    Hf,
+    B,
+    R,
+    Long,
+    Value(Token<'a>),
+}
+
+//Assembles Cowgod style source, the same syntax the disassembler writes:
+//  LD V0, 0x10 ; comments start with a semicolon
+//  loop: JP loop
+//  :const SPEED 4
+//  :byte 0x3C 0b01000010 ..####..
+//Labels can be used before they are defined, constants only after.
+pub fn assemble(source: &str, origin: u16) -> Result<Vec<u8>, AsmError> {
+    assemble_with_symbols(source, origin).map(|(rom, _)| rom)
+}
+
+//assemble, plus where each label and line went for a debugger
+pub fn assemble_with_symbols(source: &str, origin: u16) -> Result<(Vec<u8>, Symbols), AsmError> {
+    let mut symbols = HashMap::new();
+    let mut debug_symbols = Symbols::default();
+    let mut statements = Vec::new();
+    let mut addr = origin as usize;
+
+    //first pass finds out where everything goes
+    for (index, line) in source.lines().enumerate() {
+        let mut tokens = tokenize(line, index + 1);
+        if let Some(label) = tokens.first().and_then(|t| t.text.strip_suffix(':')) {
+            if !tokens[0].text.starts_with(':') {
+                define(&mut symbols, tokens[0], label, addr as u16)?;
+                debug_symbols.labels.insert(label.to_string(), addr as u16);
+                tokens.remove(0);
+            }
+        }
+        let Some(first) = tokens.first().copied() else {
+            continue;
+        };
+        let operands = tokens.split_off(1);
+        let (statement, size) = match first.text.to_ascii_uppercase().as_str() {
+            ":CONST" => {
+                let [name, value] = operands[..] else {
+                    return Err(first.error(":const needs a name and a value".to_string()));
+                };
+                let value = evaluate(&symbols, value)?;
+                define(&mut symbols, name, name.text, value)?;
+                continue;
+            }
+            ":BYTE" | "DB" => {
+                let size = operands.iter().map(|t| data_size(t.text)).sum();
+                (Statement::Bytes(operands), size)
+            }
+            "DW" => {
+                let size = operands.len() * 2;
+                (Statement::Words(operands), size)
+            }
+            _ => {
+                let long = operands.iter().any(|t| t.text.eq_ignore_ascii_case("LONG"));
+                let instruction = Statement::Instruction {
+                    mnemonic: first,
+                    operands,
+                };
+                (instruction, if long { 4 } else { 2 })
+            }
+        };
+        statements.push(statement);
+        if size > 0 {
+            debug_symbols.lines.push((index + 1, addr as u16));
+        }
+        addr += size;
+        if addr > u16::MAX as usize + 1 {
+            return Err(first.error("program doesn't fit in memory".to_string()));
+        }
+    }
+
+    //second pass encodes with every label known
+    let mut rom = Vec::new();
+    for statement in statements {
+        match statement {
+            Statement::Instruction { mnemonic, operands } => {
+                let operands: Vec<Operand> = operands.into_iter().map(classify).collect();
+                encode(&symbols, mnemonic, &operands, &mut rom)?;
+            }
+            Statement::Bytes(values) => {
+                for token in values {
+                    if let Some(row) = sprite_row(token.text) {
+                        rom.extend_from_slice(&row);
+                    } else {
+                        rom.push(fit(evaluate(&symbols, token)?, 0xFF, token)? as u8);
+                    }
+                }
+            }
+            Statement::Words(values) => {
+                for token in values {
+                    rom.extend_from_slice(&evaluate(&symbols, token)?.to_be_bytes());
+                }
+            }
+        }
+    }
+    Ok((rom, debug_symbols))
+}
+
+//splits on whitespace and commas, dropping ; comments
+fn tokenize(line: &str, line_number: usize) -> Vec<Token<'_>> {
+    let code = line.split(';').next().unwrap_or("");
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in code.char_indices().chain([(code.len(), ' ')]) {
+        let separator = c.is_whitespace() || c == ',';
+        match start {
+            Some(s) if separator => {
+                tokens.push(Token {
+                    text: &code[s..i],
+                    line: line_number,
+                    column: s + 1,
+                });
+                start = None;
+            }
+            None if !separator => start = Some(i),
+            _ => (),
+        }
+    }
+    tokens
+}
+
+fn define(
+    symbols: &mut HashMap<String, u16>,
+    token: Token,
+    name: &str,
+    value: u16,
+) -> Result<(), AsmError> {
+    let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    //register names and keywords like DT would be read as operands instead
+    let reserved = !matches!(
+        classify(Token {
+            text: name,
+            ..token
+        }),
+        Operand::Value(_)
+    );
+    if !valid || reserved {
+        return Err(token.error(format!("'{}' can't be used as a name", name)));
+    }
+    if symbols.insert(name.to_string(), value).is_some() {
+        return Err(token.error(format!("'{}' is already defined", name)));
+    }
+    Ok(())
+}
+
+fn classify(token: Token) -> Operand {
+    let upper = token.text.to_ascii_uppercase();
+    match upper.as_str() {
+        "I" => Operand::I,
+        "[I]" => Operand::IndirectI,
+        "DT" => Operand::Dt,
+        "ST" => Operand::St,
+        "K" => Operand::K,
+        "F" => Operand::F,
+        "HF" => Operand::Hf,
+        "B" => Operand::B,
+        "R" => Operand::R,
+        "LONG" => Operand::Long,
+        _ => match upper.strip_prefix('V') {
+            Some(x) if x.len() == 1 && x.chars().all(|c| c.is_ascii_hexdigit()) => {
+                Operand::V(u8::from_str_radix(x, 16).unwrap())
+            }
+            _ => Operand::Value(token),
+        },
+    }
+}
+
+//a number in hex, binary or decimal, or the name of a label or constant
+fn evaluate(symbols: &HashMap<String, u16>, token: Token) -> Result<u16, AsmError> {
+    let text = token.text;
+    let lower = text.to_ascii_lowercase();
+    let number = if let Some(hex) = lower.strip_prefix("0x") {
+        u16::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        u16::from_str_radix(binary, 2).ok()
+    } else if text.starts_with(|c: char| c.is_ascii_digit()) {
+        text.parse().ok()
+    } else {
+        return symbols
+            .get(text)
+            .copied()
+            .ok_or_else(|| token.error(format!("undefined name '{}'", text)));
+    };
+    number.ok_or_else(|| token.error(format!("invalid number '{}'", text)))
+}
+
+fn fit(value: u16, max: u16, token: Token) -> Result<u16, AsmError> {
+    if value > max {
+        return Err(token.error(format!(
+            "{:#X} is too big, the most this can be is {:#X}",
+            value, max
+        )));
+    }
+    Ok(value)
+}
+
+//sprite rows are drawn with . and #, 8 or 16 pixels wide
+fn sprite_row(text: &str) -> Option<Vec<u8>> {
+    if !matches!(text.len(), 8 | 16) || !text.chars().all(|c| c == '.' || c == '#') {
+        return None;
+    }
+    let bits = text
+        .chars()
+        .fold(0u16, |bits, c| bits << 1 | (c == '#') as u16);
+    if text.len() == 8 {
+        Some(vec![bits as u8])
+    } else {
+        Some(bits.to_be_bytes().to_vec())
+    }
+}
+
+fn data_size(text: &str) -> usize {
+    sprite_row(text).map_or(1, |row| row.len())
+}
+
+fn encode(
+    symbols: &HashMap<String, u16>,
+    mnemonic: Token,
+    operands: &[Operand],
+    rom: &mut Vec<u8>,
+) -> Result<(), AsmError> {
+    let addr = |token| evaluate(symbols, token).and_then(|v| fit(v, 0xFFF, token));
+    let byte = |token| Ok(evaluate(symbols, token).and_then(|v| fit(v, 0xFF, token))? as u8);
+    let nibble = |token| Ok(evaluate(symbols, token).and_then(|v| fit(v, 0xF, token))? as u8);
+
+    use Operand::*;
+    let opcode = match (mnemonic.text.to_ascii_uppercase().as_str(), operands) {
+        ("CLS", []) => Opcode::ClearScreen,
+        ("RET", []) => Opcode::Ret,
+        ("SYS", [Value(a)]) => Opcode::Sys(addr(*a)?),
+        ("JP", [Value(a)]) => Opcode::Jump(addr(*a)?),
+        ("JP", [V(0), Value(a)]) => Opcode::JumpPlusV0(addr(*a)?),
+        ("CALL", [Value(a)]) => Opcode::Call(addr(*a)?),
+        ("SE", [V(x), Value(kk)]) => Opcode::SkipIfEqualAtX {
+            x: *x,
+            kk: byte(*kk)?,
+        },
+        ("SE", [V(x), V(y)]) => Opcode::SkipIfBothValuesEqual { x: *x, y: *y },
+        ("SNE", [V(x), Value(kk)]) => Opcode::SkipIfNotEqualAtX {
+            x: *x,
+            kk: byte(*kk)?,
+        },
+        ("SNE", [V(x), V(y)]) => Opcode::SkipIfBothValuesNotEqual { x: *x, y: *y },
+        ("LD", [V(x), Value(kk)]) => Opcode::LoadValueToRegister {
+            x: *x,
+            kk: byte(*kk)?,
+        },
+        ("LD", [V(x), V(y)]) => Opcode::LoadYIntoX { x: *x, y: *y },
+        ("LD", [I, Value(a)]) => Opcode::SetIRegister(addr(*a)?),
+        ("LD", [I, Long, Value(a)]) => {
+            rom.extend_from_slice(&LONG_I_OPCODE.to_be_bytes());
+            rom.extend_from_slice(&evaluate(symbols, *a)?.to_be_bytes());
+            return Ok(());
+        }
+        ("LD", [V(x), Dt]) => Opcode::LoadDelayTimerToVx { x: *x },
+        ("LD", [V(x), K]) => Opcode::WaitForKeyPressAndStoreVx { x: *x },
+        ("LD", [Dt, V(x)]) => Opcode::SetDelayTimer { x: *x },
+        ("LD", [St, V(x)]) => Opcode::SetSoundTimer { x: *x },
+        ("LD", [F, V(x)]) => Opcode::SetICorrespondingFontAddressFromVx { x: *x },
+        ("LD", [Hf, V(x)]) => Opcode::SetICorrespondingBigFontAddressFromVx { x: *x },
+        ("LD", [B, V(x)]) => Opcode::LoadVxAsDecimalIntoMemoryAtIRegister { x: *x },
+        ("LD", [IndirectI, V(x)]) => Opcode::LoadRegistersV0ToVxIntoMemoryAtI { x: *x },
+        ("LD", [V(x), IndirectI]) => Opcode::FillRegistersV0ToVxFromMmoryAtI { x: *x },
+        ("LD", [R, V(x)]) => Opcode::StoreRegistersV0ToVxInRplFlags { x: *x },
+        ("LD", [V(x), R]) => Opcode::LoadRegistersV0ToVxFromRplFlags { x: *x },
+        ("ADD", [V(x), Value(kk)]) => Opcode::AddToValueInRegister {
+            x: *x,
+            kk: byte(*kk)?,
+        },
+        ("ADD", [V(x), V(y)]) => Opcode::AddXY { x: *x, y: *y },
+        ("ADD", [I, V(x)]) => Opcode::AddVxToIRegister { x: *x },
+        ("OR", [V(x), V(y)]) => Opcode::BitwiseOrXY { x: *x, y: *y },
+        ("AND", [V(x), V(y)]) => Opcode::BitwiseAndXY { x: *x, y: *y },
+        ("XOR", [V(x), V(y)]) => Opcode::BitwiseXorXY { x: *x, y: *y },
+        ("SUB", [V(x), V(y)]) => Opcode::SubXfromY { x: *x, y: *y },
+        ("SUBN", [V(x), V(y)]) => Opcode::SubYfromX { x: *x, y: *y },
+        //without vy shift vx itself, so it does the same with or without the shift quirk
+        ("SHR", [V(x)]) => Opcode::ShiftRight { x: *x, y: *x },
+        ("SHR", [V(x), V(y)]) => Opcode::ShiftRight { x: *x, y: *y },
+        ("SHL", [V(x)]) => Opcode::ShiftLeft { x: *x, y: *x },
+        ("SHL", [V(x), V(y)]) => Opcode::ShiftLeft { x: *x, y: *y },
+        ("RND", [V(x), Value(kk)]) => Opcode::RandomNumberToRegisterX {
+            x: *x,
+            kk: byte(*kk)?,
+        },
+        ("DRW", [V(x), V(y), Value(n)]) => Opcode::Draw {
+            x: *x,
+            y: *y,
+            n: nibble(*n)?,
+        },
+        ("SKP", [V(x)]) => Opcode::SkipIfKeyAtVxPressed { x: *x },
+        ("SKNP", [V(x)]) => Opcode::SkipIfKeyAtVxNotPressed { x: *x },
+        ("SCD", [Value(n)]) => Opcode::ScrollDown { n: nibble(*n)? },
+        ("SCU", [Value(n)]) => Opcode::ScrollUp { n: nibble(*n)? },
+        ("SCR", []) => Opcode::ScrollRight,
+        ("SCL", []) => Opcode::ScrollLeft,
+        ("EXIT", []) => Opcode::Exit,
+        ("LOW", []) => Opcode::LowResolution,
+        ("HIGH", []) => Opcode::HighResolution,
+        ("PLANE", [Value(n)]) => Opcode::SelectPlanes { n: nibble(*n)? },
+        ("SAVE", [V(x), V(y)]) => Opcode::LoadRegistersVxToVyIntoMemoryAtI { x: *x, y: *y },
+        ("LOAD", [V(x), V(y)]) => Opcode::FillRegistersVxToVyFromMemoryAtI { x: *x, y: *y },
+        ("AUDIO", []) => Opcode::LoadAudioPatternFromI,
+        ("PITCH", [V(x)]) => Opcode::SetPitchFromVx { x: *x },
+        (name, _) if is_mnemonic(name) => {
+            return Err(mnemonic.error(format!("invalid operands for {}", name)));
+        }
+        _ => {
+            return Err(mnemonic.error(format!("unknown instruction '{}'", mnemonic.text)));
+        }
+    };
+    rom.extend_from_slice(&opcode.encode().to_be_bytes());
+    Ok(())
+}
+
+fn is_mnemonic(name: &str) -> bool {
+    const MNEMONICS: [&str; 33] = [
+        "CLS", "RET", "SYS", "JP", "CALL", "SE", "SNE", "LD", "ADD", "OR", "AND", "XOR", "SUB",
+        "SUBN", "SHR", "SHL", "RND", "DRW", "SKP", "SKNP", "SCD", "SCU", "SCR", "SCL", "EXIT",
+        "LOW", "HIGH", "PLANE", "SAVE", "LOAD", "AUDIO", "PITCH", "DW",
+    ];
+    MNEMONICS.contains(&name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler::{disassemble, Syntax};
+
+    const ORIGIN: u16 = 0x200;
+
+    #[test]
+    fn test_instructions() {
+        let source = "
+            CLS
+            LD V0, 0x10     ; hex
+            ld va, 0b101    ; binary, any case
+            DRW V1, VB, 15
+            LD [I], V3
+            LD I, LONG 0x1234
+            SHR V2
+        ";
+        assert_eq!(
+            Ok(vec![
+                0x00, 0xE0, 0x60, 0x10, 0x6A, 0x05, 0xD1, 0xBF, 0xF3, 0x55, 0xF0, 0x00, 0x12, 0x34,
+                0x82, 0x26
+            ]),
+            assemble(source, ORIGIN)
+        );
+    }
+
+    #[test]
+    fn test_labels_and_constants() {
+        let source = "
+            :const SPEED 3
+            start:  LD I, sprite
+                    ADD V0, SPEED
+                    JP start
+            sprite:
+            :byte ..####.. 0x42 255
+        ";
+        assert_eq!(
+            Ok(vec![0xA2, 0x06, 0x70, 0x03, 0x12, 0x00, 0x3C, 0x42, 0xFF]),
+            assemble(source, ORIGIN)
+        );
+        let (_, symbols) = assemble_with_symbols(source, ORIGIN).unwrap();
+        assert_eq!(
+            vec![("sprite", 0x206), ("start", 0x200)],
+            symbols
+                .labels
+                .iter()
+                .map(|(name, addr)| (name.as_str(), *addr))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![(3, 0x200), (4, 0x202), (5, 0x204), (7, 0x206)],
+            symbols.lines
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |source| assemble(source, ORIGIN).unwrap_err();
+        assert_eq!(
+            AsmError {
+                line: 2,
+                column: 8,
+                message: "0x100 is too big, the most this can be is 0xFF".to_string(),
+            },
+            error("CLS\nLD V0, 0x100")
+        );
+        assert_eq!((1, 1), {
+            let e = error("MOV V0, V1");
+            (e.line, e.column)
+        });
+        assert_eq!("1:1: invalid operands for LD", error("LD V0").to_string());
+        assert_eq!(
+            "1:4: undefined name 'nowhere'",
+            error("JP nowhere").to_string()
+        );
+        assert!(error("a: CLS\na: CLS").message.contains("already defined"));
+        assert!(error(":const V1 2").message.contains("can't be used"));
+    }
+
+    fn round_trip(rom: &[u8]) {
+        let source: Vec<String> = disassemble(rom, ORIGIN, Syntax::Cowgod)
+            .into_iter()
+            .map(|line| line.text)
+            .collect();
+        assert_eq!(Ok(rom.to_vec()), assemble(&source.join("\n"), ORIGIN));
+    }
+
+    #[test]
+    fn test_round_trip_roms() {
+        round_trip(include_bytes!("../../roms/PONG2"));
+        round_trip(include_bytes!("../../roms/BRIX"));
+        round_trip(include_bytes!("../../roms/INVADERS"));
+        round_trip(include_bytes!("../../roms/test_opcode.ch8"));
+        round_trip(include_bytes!("../../roms/5-quirks.ch8"));
+    }
+}
@@ -0,0 +1,150 @@
+use std::f32::consts::TAU;
+use std::str::FromStr;
+
+//time to fade in and out, long enough to avoid clicks and short enough to sound instant
+const RAMP_SECONDS: f32 = 0.005;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Waveform {
+    Square,
+    Triangle,
+    Sawtooth,
+    Sine,
+}
+
+impl Waveform {
+    //phase is 0 to 1, output is -1 to 1
+    fn sample(&self, phase: f32) -> f32 {
+        match self {
+            Waveform::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Sawtooth => 2.0 * phase - 1.0,
+            Waveform::Sine => (phase * TAU).sin(),
+        }
+    }
+}
+
+impl FromStr for Waveform {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "square" => Ok(Waveform::Square),
+            "triangle" => Ok(Waveform::Triangle),
+            "sawtooth" | "saw" => Ok(Waveform::Sawtooth),
+            "sine" => Ok(Waveform::Sine),
+            _ => Err(format!(
+                "unknown waveform '{}', expected one of square, triangle, sawtooth, sine",
+                name
+            )),
+        }
+    }
+}
+
+//Generates the beep played while the sound timer is running.
+//Phase carries over between buffers and the volume ramps on start and stop,
+//so toggling the tone every frame doesn't click.
+#[derive(Debug, Clone)]
+pub struct ToneGenerator {
+    waveform: Waveform,
+    //cycles per sample
+    phase_step: f32,
+    phase: f32,
+    volume: f32,
+    //current volume envelope, 0 silent to 1 full
+    gain: f32,
+    ramp_step: f32,
+}
+
+impl ToneGenerator {
+    //volume is 0 to 1
+    pub fn new(sample_rate: u32, frequency: f32, volume: f32, waveform: Waveform) -> Self {
+        ToneGenerator {
+            waveform,
+            phase_step: frequency / sample_rate as f32,
+            phase: 0.0,
+            volume: volume.clamp(0.0, 1.0),
+            gain: 0.0,
+            ramp_step: 1.0 / (RAMP_SECONDS * sample_rate as f32).max(1.0),
+        }
+    }
+
+    //fills out with mono samples, fading towards active
+    pub fn fill(&mut self, out: &mut [f32], active: bool) {
+        let target = if active { 1.0 } else { 0.0 };
+        for sample in out.iter_mut() {
+            if self.gain < target {
+                self.gain = (self.gain + self.ramp_step).min(target);
+            } else if self.gain > target {
+                self.gain = (self.gain - self.ramp_step).max(target);
+            }
+
+            if self.gain == 0.0 {
+                //restart the wave so every beep starts the same
+                self.phase = 0.0;
+                *sample = 0.0;
+                continue;
+            }
+
+            *sample = self.waveform.sample(self.phase) * self.volume * self.gain;
+            self.phase = (self.phase + self.phase_step).fract();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 44100;
+
+    #[test]
+    fn test_silent_when_inactive() {
+        let mut tone = ToneGenerator::new(SAMPLE_RATE, 440.0, 1.0, Waveform::Square);
+        let mut out = [1.0; 256];
+        tone.fill(&mut out, false);
+        assert!(out.iter().all(|s| *s == 0.0));
+    }
+
+    #[test]
+    fn test_square_wave_frequency() {
+        let mut tone = ToneGenerator::new(SAMPLE_RATE, 441.0, 0.5, Waveform::Square);
+        let mut out = vec![0.0; SAMPLE_RATE as usize];
+        tone.fill(&mut out, true);
+
+        //count rising edges through zero over one second
+        let rising = out.windows(2).filter(|w| w[0] <= 0.0 && w[1] > 0.0).count();
+        assert!((440..=442).contains(&rising));
+        //once ramped up it sits at volume
+        assert_eq!(0.5, out[SAMPLE_RATE as usize / 2].abs());
+    }
+
+    #[test]
+    fn test_start_and_stop_are_ramped() {
+        let mut tone = ToneGenerator::new(SAMPLE_RATE, 440.0, 1.0, Waveform::Square);
+        let mut out = vec![0.0; 1024];
+        tone.fill(&mut out, true);
+        //square wave magnitude is the envelope, it grows a little each sample
+        assert!(out[0].abs() < 0.01);
+        assert!(out[..300]
+            .windows(2)
+            .all(|w| w[1].abs() >= w[0].abs() && w[1].abs() - w[0].abs() < 0.01));
+
+        tone.fill(&mut out, false);
+        assert!(out[0].abs() > 0.9);
+        assert_eq!(0.0, out[1023]);
+    }
+
+    #[test]
+    fn test_waveform_from_str() {
+        assert_eq!(Ok(Waveform::Sine), "sine".parse());
+        assert_eq!(Ok(Waveform::Sawtooth), "SAW".parse());
+        assert!("noise".parse::<Waveform>().is_err());
+    }
+}
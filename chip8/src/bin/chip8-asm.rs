@@ -0,0 +1,117 @@
+use chip8::assembler::assemble_with_symbols;
+use chip8::debug::parse_value;
+use chip8::disassembler::Syntax;
+use chip8::octo;
+use std::path::{Path, PathBuf};
+use std::process;
+
+const USAGE: &str = "\
+Usage: chip8-asm [OPTIONS] <SOURCE>
+
+Assembles CHIP-8 source, either Cowgod style like chip8-dis writes or Octo.
+
+Options:
+      --syntax <NAME>   cowgod or octo [default: octo for .8o files, otherwise cowgod]
+  -o, --output <PATH>   Where to write the program [default: SOURCE with a .ch8 extension]
+      --origin <ADDR>   Address the program is loaded at [default: 0x200]
+      --symbols <PATH>  Also write where each label and source line went, for chip8-dap
+  -h, --help            Print this help
+";
+
+const DEFAULT_ORIGIN: u16 = 0x200;
+
+fn main() {
+    let mut syntax = None;
+    let mut origin = DEFAULT_ORIGIN;
+    let mut output = None;
+    let mut symbols_path = None;
+    let mut source_path = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let result = match arg.as_str() {
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                return;
+            }
+            "--syntax" => value(&arg, args.next())
+                .and_then(|name| name.parse())
+                .map(|parsed| syntax = Some(parsed)),
+            "-o" | "--output" => value(&arg, args.next()).map(|path| output = Some(path)),
+            "--symbols" => value(&arg, args.next()).map(|path| symbols_path = Some(path)),
+            "--origin" => value(&arg, args.next())
+                .and_then(|raw| parse_value(&raw))
+                .map(|parsed| origin = parsed),
+            _ if arg.starts_with('-') => Err(format!("unknown option '{}'", arg)),
+            _ if source_path.is_some() => Err(format!("unexpected argument '{}'", arg)),
+            _ => {
+                source_path = Some(arg);
+                Ok(())
+            }
+        };
+        if let Err(e) = result {
+            usage_error(&e);
+        }
+    }
+    let Some(source_path) = source_path else {
+        usage_error("missing source path");
+    };
+    let output = output
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(&source_path).with_extension("ch8"));
+    let syntax = syntax.unwrap_or_else(|| {
+        if Path::new(&source_path)
+            .extension()
+            .is_some_and(|e| e == "8o")
+        {
+            Syntax::Octo
+        } else {
+            Syntax::Cowgod
+        }
+    });
+
+    let source = match std::fs::read_to_string(&source_path) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", source_path, e);
+            process::exit(1);
+        }
+    };
+    let compiled = match syntax {
+        Syntax::Cowgod => assemble_with_symbols(&source, origin),
+        Syntax::Octo => octo::compile_with_symbols(&source, origin),
+    };
+    let (rom, mut symbols) = match compiled {
+        Ok(compiled) => compiled,
+        Err(e) => {
+            //path:line:column: message, like a compiler
+            eprintln!("{}:{}", source_path, e);
+            process::exit(1);
+        }
+    };
+    if let Err(e) = std::fs::write(&output, &rom) {
+        eprintln!("Failed to write {}: {}", output.display(), e);
+        process::exit(1);
+    }
+    println!("Wrote {} bytes to {}", rom.len(), output.display());
+
+    if let Some(symbols_path) = symbols_path {
+        //absolute, so the debugger finds the source wherever it's started from
+        let source = std::fs::canonicalize(&source_path).unwrap_or_else(|_| source_path.into());
+        symbols.source = Some(source.display().to_string());
+        if let Err(e) = std::fs::write(&symbols_path, symbols.to_string()) {
+            eprintln!("Failed to write {}: {}", symbols_path, e);
+            process::exit(1);
+        }
+        println!("Wrote symbols to {}", symbols_path);
+    }
+}
+
+fn value(option: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("{} needs a value", option))
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("error: {}\n\n{}", message, USAGE);
+    process::exit(2);
+}
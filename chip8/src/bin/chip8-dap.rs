@@ -0,0 +1,35 @@
+use chip8::dap::DapServer;
+use std::io;
+use std::process;
+
+const USAGE: &str = "\
+Usage: chip8-dap
+
+A Debug Adapter Protocol server on stdin and stdout, started by an editor.
+The launch request takes:
+  program       Path of the ROM
+  symbols       Labels and source lines from chip8-asm --symbols, for breakpoints by line
+  quirks        vip, chip-48, schip or xo-chip [default: vip]
+  ipf           Instructions per 60hz frame when running [default: 10]
+  seed          Seed for the random number generator
+  stopOnEntry   Stop before the first instruction [default: false]
+
+Options:
+  -h, --help    Print this help
+";
+
+fn main() {
+    if let Some(arg) = std::env::args().nth(1) {
+        if arg == "-h" || arg == "--help" {
+            print!("{}", USAGE);
+            return;
+        }
+        eprintln!("error: unexpected argument '{}'\n\n{}", arg, USAGE);
+        process::exit(2);
+    }
+    //stdout is the protocol, so errors go to stderr where editors log them
+    if let Err(e) = DapServer::new().serve(io::stdin(), io::stdout()) {
+        eprintln!("Debug adapter stopped: {}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,465 @@
+use chip8::chip::{Chip8, DEFAULT_LOAD_ADDR};
+use chip8::debug::{parse_value, DebugEvent, Register, StopCondition};
+use chip8::monitor::{self, Command};
+use chip8::quirks::Quirks;
+use chip8::scheduler::{Scheduler, TIMER_HZ};
+use chip8::screen;
+use chip8::terminal::{keypad_key, HeldKeys, DEFAULT_HOLD};
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use std::io::{self, Write};
+use std::process;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "\
+Usage: chip8-debug [OPTIONS] <ROM>
+
+A full screen terminal debugger. Starts paused, type help for the commands.
+While running the keypad is 1234 QWER ASDF ZXCV and F6 or Esc pauses.
+
+Options:
+      --ipf <N>         Instructions per 60hz frame when running [default: 10]
+      --quirks <NAME>   Quirks preset: vip, chip-48, schip, xo-chip [default: vip]
+      --seed <N>        Seed for the random number generator
+      --load-addr <ADDR>
+                        Where the program is loaded and starts, 0x600 for ETI-660 [default: 0x200]
+      --hold <MS>       How long a key stays down after its last press or repeat [default: 250]
+  -h, --help            Print this help
+";
+
+const DEFAULT_IPF: u32 = 10;
+//how often the screen is redrawn while running
+const FRAME_TIME: Duration = Duration::from_nanos(1_000_000_000 / TIMER_HZ as u64);
+
+//pane positions, the top row is the status line
+const PANE_Y: u16 = 1;
+const DISASSEMBLY_X: u16 = 0;
+const DISASSEMBLY_WIDTH: usize = 36;
+//lines shown before pc, and in all
+const DISASSEMBLY_BEFORE: u16 = 8;
+const DISASSEMBLY_LINES: u16 = 17;
+const REGISTERS_X: u16 = 38;
+const REGISTERS_WIDTH: usize = 14;
+const STACK_X: u16 = 54;
+const STACK_WIDTH: usize = 10;
+const MEMORY_X: u16 = 66;
+const MEMORY_ROWS: u16 = 16;
+const MEMORY_ROW_BYTES: u16 = 8;
+//rows of memory shown before the one holding I
+const MEMORY_ROWS_BEFORE: u16 = 2;
+const DISPLAY_Y: u16 = PANE_Y + DISASSEMBLY_LINES + 2;
+const MAX_MESSAGES: usize = 10;
+
+fn main() {
+    let mut ipf = DEFAULT_IPF;
+    let mut quirks = Quirks::default();
+    let mut seed = None;
+    let mut load_addr = DEFAULT_LOAD_ADDR;
+    let mut hold = DEFAULT_HOLD;
+    let mut rom_path = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let result = match arg.as_str() {
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                return;
+            }
+            "--ipf" => number(&arg, args.next()).map(|parsed| ipf = parsed),
+            "--quirks" => value(&arg, args.next())
+                .and_then(|name| name.parse())
+                .map(|parsed| quirks = parsed),
+            "--seed" => number(&arg, args.next()).map(|parsed| seed = Some(parsed)),
+            "--load-addr" => value(&arg, args.next())
+                .and_then(|raw| parse_value(&raw))
+                .map(|parsed| load_addr = parsed),
+            "--hold" => number(&arg, args.next()).map(|ms| hold = Duration::from_millis(ms)),
+            _ if arg.starts_with('-') => Err(format!("unknown option '{}'", arg)),
+            _ if rom_path.is_some() => Err(format!("unexpected argument '{}'", arg)),
+            _ => {
+                rom_path = Some(arg);
+                Ok(())
+            }
+        };
+        if let Err(e) = result {
+            usage_error(&e);
+        }
+    }
+    let Some(rom_path) = rom_path else {
+        usage_error("missing ROM path");
+    };
+    if ipf == 0 {
+        usage_error("--ipf must be at least 1");
+    }
+
+    let mut chip = match seed {
+        Some(seed) => Chip8::with_seed(quirks, seed),
+        None => Chip8::new(quirks),
+    };
+    if let Err(e) = chip.load_rom_file(&rom_path, load_addr) {
+        eprintln!("Failed to load {}: {}", rom_path, e);
+        process::exit(1);
+    }
+
+    let mut debugger = Debugger {
+        chip,
+        scheduler: Scheduler::new(ipf.saturating_mul(TIMER_HZ)),
+        keys: HeldKeys::new(hold),
+        running: false,
+        line: String::new(),
+        last: None,
+        messages: vec!["Paused, type help for the commands".to_string()],
+        drawn_size: None,
+    };
+    let result = match RawTerminal::enter() {
+        Ok(_terminal) => debugger.run(),
+        Err(e) => {
+            eprintln!("Failed to set up the terminal: {}", e);
+            process::exit(1);
+        }
+    };
+    if let Err(e) = result {
+        eprintln!("Debugger stopped: {}", e);
+        process::exit(1);
+    }
+}
+
+//raw mode on the alternate screen, undone on drop so errors and panics leave a usable terminal
+struct RawTerminal;
+
+impl RawTerminal {
+    fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(
+            io::stdout(),
+            EnterAlternateScreen,
+            Hide,
+            Clear(ClearType::All)
+        )?;
+        Ok(RawTerminal)
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+struct Debugger {
+    chip: Chip8,
+    scheduler: Scheduler,
+    keys: HeldKeys,
+    running: bool,
+    //the command being typed
+    line: String,
+    //run again by an empty line
+    last: Option<Command>,
+    messages: Vec<String>,
+    //clears the screen when the display or terminal changes size
+    drawn_size: Option<(u16, u16, usize)>,
+}
+
+impl Debugger {
+    //faults in the program are shown and leave it paused, only terminal errors end the run
+    fn run(&mut self) -> io::Result<()> {
+        loop {
+            self.draw()?;
+            if self.running {
+                self.run_frame()?;
+                continue;
+            }
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind == KeyEventKind::Release {
+                continue;
+            }
+            if is_ctrl_c(&key) {
+                return Ok(());
+            }
+            match key.code {
+                KeyCode::Char(c) => self.line.push(c),
+                KeyCode::Backspace => {
+                    self.line.pop();
+                }
+                KeyCode::Esc => self.line.clear(),
+                KeyCode::Up => {
+                    if let Some(last) = &self.last {
+                        self.line = last.to_string();
+                    }
+                }
+                KeyCode::Enter => {
+                    let line = std::mem::take(&mut self.line);
+                    if !self.execute(&line) {
+                        return Ok(());
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    //false once the user quits
+    fn execute(&mut self, line: &str) -> bool {
+        let command = if line.trim().is_empty() {
+            match self.last.clone() {
+                Some(last) => last,
+                None => return true,
+            }
+        } else {
+            match line.parse::<Command>() {
+                Ok(command) => command,
+                Err(e) => {
+                    self.say(e);
+                    return true;
+                }
+            }
+        };
+        self.last = Some(command.clone());
+        match command {
+            Command::Quit => return false,
+            Command::Help => {
+                self.messages = monitor::HELP.lines().map(str::to_string).collect();
+            }
+            Command::Continue => {
+                self.running = true;
+                self.scheduler.reset();
+                self.scheduler.update(Instant::now());
+                self.say("Running, F6 or Esc pauses".to_string());
+            }
+            command => match monitor::apply(&mut self.chip, &command) {
+                Ok(message) => self.say(message),
+                Err(e) => self.say(format!("Emulation stopped: {}", e)),
+            },
+        }
+        true
+    }
+
+    //one 60hz frame of input, instructions and timers
+    fn run_frame(&mut self) -> io::Result<()> {
+        let started = Instant::now();
+        while event::poll(FRAME_TIME.saturating_sub(started.elapsed()))? {
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if is_ctrl_c(&key) || matches!(key.code, KeyCode::Esc | KeyCode::F(6)) {
+                if key.kind == KeyEventKind::Press {
+                    self.pause("Paused".to_string());
+                    return Ok(());
+                }
+                continue;
+            }
+            if let KeyCode::Char(c) = key.code {
+                if let Some(k) = keypad_key(c) {
+                    let pressed = key.kind != KeyEventKind::Release;
+                    if pressed && self.keys.press(k, Instant::now()) {
+                        //keypad_key only hands out 0x0 through 0xF
+                        self.chip.key_press(k).unwrap();
+                    } else if !pressed && self.keys.release(k) {
+                        self.chip.key_release(k).unwrap();
+                    }
+                }
+            }
+        }
+        for key in self.keys.expire(Instant::now()) {
+            self.chip.key_release(key).unwrap();
+        }
+
+        let due = self.scheduler.update(Instant::now());
+        match self
+            .chip
+            .run_until(StopCondition::Breakpoint, due.instructions)
+        {
+            Ok(DebugEvent::InstructionLimit) => {
+                for _ in 0..due.timer_ticks {
+                    self.chip.tick_timers();
+                }
+            }
+            Ok(event) => {
+                let message = monitor::describe(&self.chip, &event);
+                self.pause(message);
+            }
+            Err(e) => self.pause(format!("Emulation stopped: {}", e)),
+        }
+        Ok(())
+    }
+
+    //keys can't be released while typing commands, so let go of them all
+    fn pause(&mut self, message: String) {
+        self.running = false;
+        for key in 0..16 {
+            if self.keys.release(key) {
+                self.chip.key_release(key).unwrap();
+            }
+        }
+        self.say(message);
+    }
+
+    fn say(&mut self, message: String) {
+        self.messages.push(message);
+        let excess = self.messages.len().saturating_sub(MAX_MESSAGES);
+        self.messages.drain(..excess);
+    }
+
+    fn draw(&mut self) -> io::Result<()> {
+        let mut out = io::stdout().lock();
+        let frame = self.chip.get_display();
+        let (columns, rows) = terminal::size()?;
+        if self.drawn_size != Some((columns, rows, frame.width)) {
+            self.drawn_size = Some((columns, rows, frame.width));
+            queue!(out, Clear(ClearType::All))?;
+        }
+        let chip = &self.chip;
+        let pc = chip.register(Register::Pc);
+        let status = if self.running { "running" } else { "paused" };
+        put(&mut out, 0, 0, 80, &format!("chip8-debug - {}", status))?;
+
+        put(
+            &mut out,
+            DISASSEMBLY_X,
+            PANE_Y,
+            DISASSEMBLY_WIDTH,
+            "Disassembly",
+        )?;
+        let lines = monitor::disassembly(chip, pc, DISASSEMBLY_BEFORE, DISASSEMBLY_LINES);
+        for (row, line) in (PANE_Y + 1..).zip(lines) {
+            let marker = if line.addr == pc { '>' } else { ' ' };
+            let breakpoint = chip.breakpoints().iter().any(|b| b.addr == line.addr);
+            let breakpoint = if breakpoint { '*' } else { ' ' };
+            let text = format!("{}{}{}", marker, breakpoint, line);
+            put(&mut out, DISASSEMBLY_X, row, DISASSEMBLY_WIDTH, &text)?;
+        }
+
+        put(&mut out, REGISTERS_X, PANE_Y, REGISTERS_WIDTH, "Registers")?;
+        for x in 0..8u8 {
+            let text = format!(
+                "V{:X} {:02X}  V{:X} {:02X}",
+                x,
+                chip.register(Register::V(x)),
+                x + 8,
+                chip.register(Register::V(x + 8))
+            );
+            put(
+                &mut out,
+                REGISTERS_X,
+                PANE_Y + 1 + x as u16,
+                REGISTERS_WIDTH,
+                &text,
+            )?;
+        }
+        let i = chip.register(Register::I);
+        let registers = [
+            format!("I  {:04X}", i),
+            format!("PC {:04X}", pc),
+            format!("SP {}", chip.register(Register::Sp)),
+            String::new(),
+            "Timers".to_string(),
+            format!(
+                "DT {:02X}  ST {:02X}",
+                chip.register(Register::DelayTimer),
+                chip.register(Register::SoundTimer)
+            ),
+        ];
+        for (row, text) in (PANE_Y + 9..).zip(&registers) {
+            put(&mut out, REGISTERS_X, row, REGISTERS_WIDTH, text)?;
+        }
+
+        put(&mut out, STACK_X, PANE_Y, STACK_WIDTH, "Stack")?;
+        for row in 0..16 {
+            let text = match chip.stack().get(row) {
+                Some(addr) => format!("{:X}: {:03X}", row, addr),
+                None => String::new(),
+            };
+            put(
+                &mut out,
+                STACK_X,
+                PANE_Y + 1 + row as u16,
+                STACK_WIDTH,
+                &text,
+            )?;
+        }
+
+        //the bytes the next draw reads from I are highlighted
+        let sprite = i as usize..i as usize + monitor::next_sprite(chip).unwrap_or(0);
+        put(&mut out, MEMORY_X, PANE_Y, 30, "Memory at I")?;
+        let start =
+            (i & !(MEMORY_ROW_BYTES - 1)).saturating_sub(MEMORY_ROWS_BEFORE * MEMORY_ROW_BYTES);
+        for row in 0..MEMORY_ROWS {
+            let addr = start.wrapping_add(row * MEMORY_ROW_BYTES);
+            queue!(
+                out,
+                MoveTo(MEMORY_X, PANE_Y + 1 + row),
+                Print(format!("{:04X}:", addr))
+            )?;
+            for offset in 0..MEMORY_ROW_BYTES {
+                let addr = addr.wrapping_add(offset) as usize;
+                let byte = chip.memory()[addr % chip.memory().len()];
+                queue!(out, Print(' '))?;
+                if sprite.contains(&addr) {
+                    queue!(
+                        out,
+                        SetAttribute(Attribute::Reverse),
+                        Print(format!("{:02X}", byte)),
+                        SetAttribute(Attribute::NoReverse)
+                    )?;
+                } else {
+                    queue!(out, Print(format!("{:02X}", byte)))?;
+                }
+            }
+        }
+
+        put(&mut out, 0, DISPLAY_Y, frame.width, "Display")?;
+        let display = screen::half_blocks(&frame);
+        let mut row = DISPLAY_Y + 1;
+        for line in display.lines() {
+            queue!(out, MoveTo(0, row), Print(line))?;
+            row += 1;
+        }
+
+        let prompt = if self.running {
+            "F6 or Esc pauses".to_string()
+        } else {
+            format!("> {}_", self.line)
+        };
+        put(&mut out, 0, row + 1, columns as usize, &prompt)?;
+        for (row, index) in (row + 2..).zip(0..MAX_MESSAGES) {
+            let message = self.messages.get(index).map_or("", String::as_str);
+            put(&mut out, 0, row, columns as usize, message)?;
+        }
+        out.flush()
+    }
+}
+
+//text at x, y padded out to width so nothing is left over from the last draw
+fn put(out: &mut impl Write, x: u16, y: u16, width: usize, text: &str) -> io::Result<()> {
+    queue!(
+        out,
+        MoveTo(x, y),
+        Print(format!("{:<width$.width$}", text, width = width))
+    )
+}
+
+//raw mode turns Ctrl-C into a key press
+fn is_ctrl_c(key: &KeyEvent) -> bool {
+    key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL)
+}
+
+fn value(option: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("{} needs a value", option))
+}
+
+fn number<T: std::str::FromStr>(option: &str, raw: Option<String>) -> Result<T, String> {
+    let raw = value(option, raw)?;
+    raw.parse()
+        .map_err(|_| format!("invalid value '{}' for {}", raw, option))
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("error: {}\n\n{}", message, USAGE);
+    process::exit(2);
+}
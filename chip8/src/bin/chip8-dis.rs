@@ -0,0 +1,83 @@
+use chip8::debug::parse_value;
+use chip8::disassembler::{disassemble, source, Syntax};
+use std::process;
+
+const USAGE: &str = "\
+Usage: chip8-dis [OPTIONS] <ROM>
+
+Disassembles a CHIP-8 program, tracing the code from its entry point so sprite data
+isn't listed as instructions.
+
+Options:
+      --syntax <NAME>   cowgod or octo [default: cowgod]
+      --origin <ADDR>   Address the program is loaded at [default: 0x200]
+      --source          Leave out addresses and bytes, so chip8-asm can assemble it again
+  -h, --help            Print this help
+";
+
+const DEFAULT_ORIGIN: u16 = 0x200;
+
+fn main() {
+    let mut syntax = Syntax::default();
+    let mut origin = DEFAULT_ORIGIN;
+    let mut source_only = false;
+    let mut rom_path = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let result = match arg.as_str() {
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                return;
+            }
+            "--syntax" => value(&arg, args.next())
+                .and_then(|name| name.parse())
+                .map(|parsed| syntax = parsed),
+            "--origin" => value(&arg, args.next())
+                .and_then(|raw| parse_value(&raw))
+                .map(|parsed| origin = parsed),
+            "--source" => {
+                source_only = true;
+                Ok(())
+            }
+            _ if arg.starts_with('-') => Err(format!("unknown option '{}'", arg)),
+            _ if rom_path.is_some() => Err(format!("unexpected argument '{}'", arg)),
+            _ => {
+                rom_path = Some(arg);
+                Ok(())
+            }
+        };
+        if let Err(e) = result {
+            usage_error(&e);
+        }
+    }
+    let Some(rom_path) = rom_path else {
+        usage_error("missing ROM path");
+    };
+
+    let rom = match std::fs::read(&rom_path) {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", rom_path, e);
+            process::exit(1);
+        }
+    };
+    if source_only {
+        for line in source(&rom, origin, syntax) {
+            println!("{}", line);
+        }
+    } else {
+        for line in disassemble(&rom, origin, syntax) {
+            println!("{}", line);
+        }
+    }
+}
+
+fn value(option: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("{} needs a value", option))
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("error: {}\n\n{}", message, USAGE);
+    process::exit(2);
+}
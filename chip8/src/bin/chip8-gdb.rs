@@ -0,0 +1,120 @@
+use chip8::chip::{Chip8, DEFAULT_LOAD_ADDR};
+use chip8::debug::parse_value;
+use chip8::gdb::GdbStub;
+use chip8::quirks::Quirks;
+use chip8::scheduler::TIMER_HZ;
+use std::net::TcpListener;
+use std::process;
+
+const USAGE: &str = "\
+Usage: chip8-gdb [OPTIONS] <ROM>
+
+Serves a CHIP-8 program to GDB, or any client of its remote serial protocol, on a local port.
+Registers are v0-vf, i, pc, sp, dt and st, sent big endian. Clients are served one after
+another, kill restarts the program.
+
+Options:
+      --port <N>        Port to listen on, on 127.0.0.1 [default: 1234]
+      --ipf <N>         Instructions per 60hz frame when continuing [default: 10]
+      --quirks <NAME>   Quirks preset: vip, chip-48, schip, xo-chip [default: vip]
+      --seed <N>        Seed for the random number generator
+      --load-addr <ADDR>
+                        Where the program is loaded and starts, 0x600 for ETI-660 [default: 0x200]
+  -h, --help            Print this help
+";
+
+const DEFAULT_PORT: u16 = 1234;
+const DEFAULT_IPF: u32 = 10;
+
+fn main() {
+    let mut port = DEFAULT_PORT;
+    let mut ipf = DEFAULT_IPF;
+    let mut quirks = Quirks::default();
+    let mut seed = None;
+    let mut load_addr = DEFAULT_LOAD_ADDR;
+    let mut rom_path = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let result = match arg.as_str() {
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                return;
+            }
+            "--port" => number(&arg, args.next()).map(|parsed| port = parsed),
+            "--ipf" => number(&arg, args.next()).map(|parsed| ipf = parsed),
+            "--quirks" => value(&arg, args.next())
+                .and_then(|name| name.parse())
+                .map(|parsed| quirks = parsed),
+            "--seed" => number(&arg, args.next()).map(|parsed| seed = Some(parsed)),
+            "--load-addr" => value(&arg, args.next())
+                .and_then(|raw| parse_value(&raw))
+                .map(|parsed| load_addr = parsed),
+            _ if arg.starts_with('-') => Err(format!("unknown option '{}'", arg)),
+            _ if rom_path.is_some() => Err(format!("unexpected argument '{}'", arg)),
+            _ => {
+                rom_path = Some(arg);
+                Ok(())
+            }
+        };
+        if let Err(e) = result {
+            usage_error(&e);
+        }
+    }
+    let Some(rom_path) = rom_path else {
+        usage_error("missing ROM path");
+    };
+    if ipf == 0 {
+        usage_error("--ipf must be at least 1");
+    }
+
+    let mut chip = match seed {
+        Some(seed) => Chip8::with_seed(quirks, seed),
+        None => Chip8::new(quirks),
+    };
+    if let Err(e) = chip.load_rom_file(&rom_path, load_addr) {
+        eprintln!("Failed to load {}: {}", rom_path, e);
+        process::exit(1);
+    }
+
+    let listener = match TcpListener::bind(("127.0.0.1", port)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Failed to listen on port {}: {}", port, e);
+            process::exit(1);
+        }
+    };
+    eprintln!(
+        "Listening on 127.0.0.1:{}, e.g. gdb -ex 'target remote :{}'",
+        port, port
+    );
+    let mut stub = GdbStub::new(chip, ipf.saturating_mul(TIMER_HZ));
+    for stream in listener.incoming() {
+        let result = stream.and_then(|stream| {
+            let peer = stream.peer_addr()?;
+            eprintln!("{} connected", peer);
+            stub.serve(stream)?;
+            eprintln!("{} left", peer);
+            Ok(())
+        });
+        //a client dropping mid packet shouldn't take the server down
+        if let Err(e) = result {
+            eprintln!("Connection failed: {}", e);
+        }
+    }
+}
+
+fn value(option: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("{} needs a value", option))
+}
+
+fn number<T: std::str::FromStr>(option: &str, raw: Option<String>) -> Result<T, String> {
+    let raw = value(option, raw)?;
+    raw.parse()
+        .map_err(|_| format!("invalid value '{}' for {}", raw, option))
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("error: {}\n\n{}", message, USAGE);
+    process::exit(2);
+}
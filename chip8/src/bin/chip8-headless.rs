@@ -0,0 +1,231 @@
+use chip8::chip::Chip8;
+use chip8::debug::parse_value;
+use chip8::headless::{self, parse_key_script, HeadlessConfig};
+use chip8::movie::{Movie, Player, Recorder, DEFAULT_CHECKSUM_INTERVAL};
+use chip8::quirks::Quirks;
+use chip8::screen::{self, DEFAULT_PALETTE};
+use std::process;
+
+const USAGE: &str = "\
+Usage: chip8-headless [OPTIONS] <ROM>
+
+Runs a CHIP-8 program without a window as fast as possible, then prints how it stopped
+and a hash of the screen. Runs are reproducible, the same options give the same screen.
+
+Options:
+      --frames <N>      Stop after N 60hz frames [default: 600]
+      --ipf <N>         Instructions per frame [default: 10]
+      --quirks <NAME>   Quirks preset: vip, chip-48, schip, xo-chip [default: vip]
+      --seed <N>        Seed for the random number generator [default: 0]
+      --until-pc <ADDR> Stop before running the instruction at ADDR
+      --until-loop      Stop when the program jumps to itself
+      --keys <SCRIPT>   Key presses and releases by frame, e.g. '30:+5 34:-5'
+      --ascii           Print the screen, . for off and # for on
+      --png <PATH>      Save the screen as a PNG
+      --scale <N>       PNG pixels per CHIP-8 pixel [default: 4]
+      --record <PATH>   Save the run as a movie, runs all of --frames
+      --replay <PATH>   Replay a movie instead, reporting frames where it desyncs.
+                        The rom, quirks, seed and speed come from the movie
+  -h, --help            Print this help
+";
+
+const DEFAULT_SEED: u64 = 0;
+const DEFAULT_SCALE: usize = 4;
+
+fn main() {
+    let mut config = HeadlessConfig::default();
+    let mut quirks = Quirks::default();
+    let mut seed = DEFAULT_SEED;
+    let mut ascii = false;
+    let mut png_path = None;
+    let mut scale = DEFAULT_SCALE;
+    let mut record_path = None;
+    let mut replay_path = None;
+    let mut rom_path = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let result = match arg.as_str() {
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                return;
+            }
+            "--frames" => number(&arg, args.next()).map(|parsed| config.max_frames = parsed),
+            "--ipf" => {
+                number(&arg, args.next()).map(|parsed| config.instructions_per_frame = parsed)
+            }
+            "--quirks" => value(&arg, args.next())
+                .and_then(|name| name.parse())
+                .map(|parsed| quirks = parsed),
+            "--seed" => number(&arg, args.next()).map(|parsed| seed = parsed),
+            "--until-pc" => value(&arg, args.next())
+                .and_then(|raw| parse_value(&raw))
+                .map(|parsed| config.stop_pc = Some(parsed)),
+            "--until-loop" => {
+                config.stop_on_loop = true;
+                Ok(())
+            }
+            "--keys" => value(&arg, args.next())
+                .and_then(|script| parse_key_script(&script))
+                .map(|parsed| config.keys = parsed),
+            "--ascii" => {
+                ascii = true;
+                Ok(())
+            }
+            "--png" => value(&arg, args.next()).map(|path| png_path = Some(path)),
+            "--scale" => number(&arg, args.next()).map(|parsed| scale = parsed),
+            "--record" => value(&arg, args.next()).map(|path| record_path = Some(path)),
+            "--replay" => value(&arg, args.next()).map(|path| replay_path = Some(path)),
+            _ if arg.starts_with('-') => Err(format!("unknown option '{}'", arg)),
+            _ if rom_path.is_some() => Err(format!("unexpected argument '{}'", arg)),
+            _ => {
+                rom_path = Some(arg);
+                Ok(())
+            }
+        };
+        if let Err(e) = result {
+            usage_error(&e);
+        }
+    }
+    let Some(rom_path) = rom_path else {
+        usage_error("missing ROM path");
+    };
+    if record_path.is_some() && (config.stop_pc.is_some() || config.stop_on_loop) {
+        usage_error("--record runs for --frames, it can't be used with --until-pc or --until-loop");
+    }
+    if record_path.is_some() && replay_path.is_some() {
+        usage_error("use either --record or --replay, not both");
+    }
+
+    let rom = match std::fs::read(&rom_path) {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", rom_path, e);
+            process::exit(1);
+        }
+    };
+    let (chip, status) = if let Some(path) = replay_path {
+        replay(&path, &rom)
+    } else {
+        let mut chip = Chip8::with_seed(quirks, seed);
+        if let Err(e) = chip.load_rom(&rom) {
+            eprintln!("Failed to load {}: {}", rom_path, e);
+            process::exit(1);
+        }
+        let status = match record_path {
+            Some(path) => record(&mut chip, &rom, &config, &path),
+            None => run(&mut chip, &config),
+        };
+        (chip, status)
+    };
+
+    let frame = chip.get_display();
+    println!("Screen hash: {:08x}", screen::hash(&frame));
+    if ascii {
+        print!("{}", screen::ascii(&frame));
+    }
+    if let Some(path) = png_path {
+        if let Err(e) = std::fs::write(&path, screen::png(&frame, &DEFAULT_PALETTE, scale)) {
+            eprintln!("Failed to write {}: {}", path, e);
+            process::exit(1);
+        }
+    }
+    process::exit(status);
+}
+
+//the screen is still worth seeing when the program faults, so errors give an exit status
+fn run(chip: &mut Chip8, config: &HeadlessConfig) -> i32 {
+    match headless::run(chip, config) {
+        Ok(run) => {
+            println!("Stopped after {} frames: {}", run.frames, run.reason);
+            0
+        }
+        Err(e) => {
+            eprintln!("Emulation stopped: {}", e);
+            1
+        }
+    }
+}
+
+fn record(chip: &mut Chip8, rom: &[u8], config: &HeadlessConfig, path: &str) -> i32 {
+    let mut recorder = Recorder::new(
+        chip,
+        rom,
+        config.instructions_per_frame,
+        DEFAULT_CHECKSUM_INTERVAL,
+    );
+    let mut keys = config.keys.iter().peekable();
+    let mut status = 0;
+    for frame in 0..config.max_frames {
+        while let Some(event) = keys.next_if(|event| event.frame <= frame) {
+            recorder.key(event.key, event.pressed);
+        }
+        if let Err(e) = recorder.frame(chip) {
+            eprintln!("Emulation stopped: {}", e);
+            status = 1;
+            break;
+        }
+    }
+    println!("Recorded {} frames", recorder.frames());
+    if let Err(e) = std::fs::write(path, recorder.finish().to_string()) {
+        eprintln!("Failed to write {}: {}", path, e);
+        process::exit(1);
+    }
+    status
+}
+
+fn replay(path: &str, rom: &[u8]) -> (Chip8, i32) {
+    let movie = std::fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| text.parse::<Movie>().map_err(|e| e.to_string()));
+    let movie = match movie {
+        Ok(movie) => movie,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", path, e);
+            process::exit(1);
+        }
+    };
+    let mut chip = match movie.start(rom) {
+        Ok(chip) => chip,
+        Err(e) => {
+            eprintln!("Failed to replay {}: {}", path, e);
+            process::exit(1);
+        }
+    };
+    let mut player = Player::new(movie);
+    let mut status = 0;
+    while !player.is_finished() {
+        match player.frame(&mut chip) {
+            Ok(None) => (),
+            Ok(Some(desync)) => {
+                println!("{}", desync);
+                status = 1;
+            }
+            Err(e) => {
+                eprintln!(
+                    "Emulation stopped at frame {}: {}",
+                    player.frame_number(),
+                    e
+                );
+                return (chip, 1);
+            }
+        }
+    }
+    println!("Replayed {} frames", player.frame_number());
+    (chip, status)
+}
+
+fn value(option: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("{} needs a value", option))
+}
+
+fn number<T: std::str::FromStr>(option: &str, raw: Option<String>) -> Result<T, String> {
+    let raw = value(option, raw)?;
+    raw.parse()
+        .map_err(|_| format!("invalid value '{}' for {}", raw, option))
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("error: {}\n\n{}", message, USAGE);
+    process::exit(2);
+}
@@ -0,0 +1,285 @@
+use chip8::chip::{Chip8, Frame, DEFAULT_LOAD_ADDR};
+use chip8::debug::{parse_value, Register};
+use chip8::machine::{AudioSink, FrameSink, Input, InputSource, Machine, MachineEvent};
+use chip8::quirks::Quirks;
+use chip8::scheduler::TIMER_HZ;
+use chip8::screen;
+use chip8::terminal::{keypad_key, HeldKeys, DEFAULT_HOLD};
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::Print;
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use std::io::{self, Write};
+use std::process;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "\
+Usage: chip8-tui [OPTIONS] <ROM>
+
+Runs a CHIP-8 program in the terminal, two rows of pixels to a line.
+The keypad is 1234 QWER ASDF ZXCV, F6 pauses and Esc or Ctrl-C quits.
+
+Options:
+      --ipf <N>         Instructions per 60hz frame [default: 10]
+      --quirks <NAME>   Quirks preset: vip, chip-48, schip, xo-chip [default: vip]
+      --seed <N>        Seed for the random number generator
+      --load-addr <ADDR>
+                        Where the program is loaded and starts, 0x600 for ETI-660 [default: 0x200]
+      --hold <MS>       Terminals don't report key releases, so a key counts as released
+                        once it hasn't pressed or repeated for MS milliseconds [default: 250]
+      --bell            Ring the terminal bell when the sound timer starts
+  -h, --help            Print this help
+";
+
+const DEFAULT_IPF: u32 = 10;
+
+fn main() {
+    let mut ipf = DEFAULT_IPF;
+    let mut quirks = Quirks::default();
+    let mut seed = None;
+    let mut load_addr = DEFAULT_LOAD_ADDR;
+    let mut hold = DEFAULT_HOLD;
+    let mut bell = false;
+    let mut rom_path = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let result = match arg.as_str() {
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                return;
+            }
+            "--ipf" => number(&arg, args.next()).map(|parsed| ipf = parsed),
+            "--quirks" => value(&arg, args.next())
+                .and_then(|name| name.parse())
+                .map(|parsed| quirks = parsed),
+            "--seed" => number(&arg, args.next()).map(|parsed| seed = Some(parsed)),
+            "--load-addr" => value(&arg, args.next())
+                .and_then(|raw| parse_value(&raw))
+                .map(|parsed| load_addr = parsed),
+            "--hold" => number(&arg, args.next()).map(|ms| hold = Duration::from_millis(ms)),
+            "--bell" => {
+                bell = true;
+                Ok(())
+            }
+            _ if arg.starts_with('-') => Err(format!("unknown option '{}'", arg)),
+            _ if rom_path.is_some() => Err(format!("unexpected argument '{}'", arg)),
+            _ => {
+                rom_path = Some(arg);
+                Ok(())
+            }
+        };
+        if let Err(e) = result {
+            usage_error(&e);
+        }
+    }
+    let Some(rom_path) = rom_path else {
+        usage_error("missing ROM path");
+    };
+    if ipf == 0 {
+        usage_error("--ipf must be at least 1");
+    }
+
+    let mut chip = match seed {
+        Some(seed) => Chip8::with_seed(quirks, seed),
+        None => Chip8::new(quirks),
+    };
+    if let Err(e) = chip.load_rom_file(&rom_path, load_addr) {
+        eprintln!("Failed to load {}: {}", rom_path, e);
+        process::exit(1);
+    }
+
+    let mut machine = Machine::new(chip, ipf.saturating_mul(TIMER_HZ));
+    let result = match RawTerminal::enter() {
+        Ok(_terminal) => machine.run(
+            &mut TerminalInput {
+                keys: HeldKeys::new(hold),
+            },
+            &mut TerminalScreen::default(),
+            &mut bell.then_some(Bell::default()),
+        ),
+        Err(e) => {
+            eprintln!("Failed to set up the terminal: {}", e);
+            process::exit(1);
+        }
+    };
+    if let Err(e) = result {
+        eprintln!("Emulation stopped: {}", e);
+        process::exit(1);
+    }
+}
+
+//raw mode on the alternate screen, undone on drop so errors and panics leave a usable terminal
+struct RawTerminal;
+
+impl RawTerminal {
+    fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(
+            io::stdout(),
+            EnterAlternateScreen,
+            Hide,
+            Clear(ClearType::All)
+        )?;
+        Ok(RawTerminal)
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+struct TerminalInput {
+    keys: HeldKeys,
+}
+
+impl InputSource for TerminalInput {
+    fn poll(&mut self, _chip: &Chip8) -> Vec<Input> {
+        let now = Instant::now();
+        let mut inputs = Vec::new();
+        loop {
+            //a terminal we can't read from can't quit either, so stop
+            let event = match event::poll(Duration::ZERO) {
+                Ok(true) => event::read(),
+                Ok(false) => break,
+                Err(e) => Err(e),
+            };
+            match event {
+                Ok(event) => inputs.extend(self.translate(event, now)),
+                Err(_) => {
+                    inputs.push(Input::Quit);
+                    break;
+                }
+            }
+        }
+        for key in self.keys.expire(now) {
+            inputs.push(Input::Key {
+                key,
+                pressed: false,
+            });
+        }
+        inputs
+    }
+}
+
+impl TerminalInput {
+    fn translate(&mut self, event: Event, now: Instant) -> Option<Input> {
+        let Event::Key(event) = event else {
+            return None;
+        };
+        let pressed = event.kind != KeyEventKind::Release;
+        match event.code {
+            KeyCode::Esc if pressed => Some(Input::Quit),
+            //raw mode turns Ctrl-C into a key press
+            KeyCode::Char('c') if pressed && event.modifiers.contains(KeyModifiers::CONTROL) => {
+                Some(Input::Quit)
+            }
+            KeyCode::F(6) if event.kind == KeyEventKind::Press => Some(Input::Pause),
+            KeyCode::Char(c) => {
+                let key = keypad_key(c)?;
+                //repeats only keep a held key down
+                let changed = if pressed {
+                    self.keys.press(key, now)
+                } else {
+                    self.keys.release(key)
+                };
+                changed.then_some(Input::Key { key, pressed })
+            }
+            _ => None,
+        }
+    }
+}
+
+//Redraws only when the screen or status line changed, it's a lot to send over ssh 60 times a
+//second. Write errors are ignored, a terminal that went away stops the run from the input side
+#[derive(Default)]
+struct TerminalScreen {
+    drawn: Option<String>,
+    size: Option<(u16, u16)>,
+    status: String,
+}
+
+impl FrameSink for TerminalScreen {
+    fn present(&mut self, frame: &Frame) {
+        let mut out = io::stdout().lock();
+        let size = terminal::size().ok();
+        if size != self.size {
+            self.size = size;
+            self.drawn = None;
+            let _ = queue!(out, Clear(ClearType::All));
+        }
+        let text = screen::half_blocks(frame);
+        if self.drawn.as_ref() == Some(&text) {
+            return;
+        }
+        let mut row = 0;
+        for line in text.lines() {
+            let _ = queue!(out, MoveTo(0, row), Print(line));
+            row += 1;
+        }
+        let _ = queue!(
+            out,
+            MoveTo(0, row),
+            Clear(ClearType::CurrentLine),
+            Print(&self.status)
+        );
+        let _ = out.flush();
+        self.drawn = Some(text);
+    }
+
+    fn event(&mut self, chip: &Chip8, event: &MachineEvent) {
+        self.status = match event {
+            MachineEvent::Paused => {
+                format!("Paused at {:#05X}, F6 resumes", chip.register(Register::Pc))
+            }
+            MachineEvent::Resumed => String::new(),
+            MachineEvent::Breakpoint(pc) => format!("Breakpoint at {:#05X}", pc),
+            MachineEvent::Stopped(pc) => format!("Stopped at {:#05X}", pc),
+            MachineEvent::Watchpoint(hits) => match hits.first() {
+                Some(hit) => format!("Watchpoint {}", hit),
+                None => "Watchpoint".to_string(),
+            },
+            MachineEvent::StateLoaded => "Loaded state".to_string(),
+            MachineEvent::StateRejected(e) => format!("Failed to load state: {}", e),
+            MachineEvent::Desync(desync) => desync.to_string(),
+            MachineEvent::ReplayFinished => "Replay finished".to_string(),
+        };
+        //the status line is drawn with the screen
+        self.drawn = None;
+    }
+}
+
+//rings once each time the sound timer starts, terminals can't hold a tone
+#[derive(Default)]
+struct Bell {
+    active: bool,
+}
+
+impl AudioSink for Bell {
+    fn set_active(&mut self, active: bool) {
+        if active && !self.active {
+            let mut out = io::stdout();
+            let _ = out.write_all(b"\x07").and_then(|_| out.flush());
+        }
+        self.active = active;
+    }
+}
+
+fn value(option: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("{} needs a value", option))
+}
+
+fn number<T: std::str::FromStr>(option: &str, raw: Option<String>) -> Result<T, String> {
+    let raw = value(option, raw)?;
+    raw.parse()
+        .map_err(|_| format!("invalid value '{}' for {}", raw, option))
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("error: {}\n\n{}", message, USAGE);
+    process::exit(2);
+}
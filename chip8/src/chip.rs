@@ -1,16 +1,48 @@
+use crate::debug::{
+    Access, Breakpoint, Condition, DebugEvent, Register, StopCondition, WatchHit, Watchpoint,
+};
+use crate::error::{ChipError, RomError, StateError};
 use crate::opcode::Opcode;
-use rand::{thread_rng, Rng};
+use crate::quirks::Quirks;
+use crate::rng::{RandomSource, SplitMix64};
+use crate::state::{StateReader, StateWriter};
+use crate::trace::{Snapshot, TraceRecord, Tracer};
+use std::io::Read;
+use std::mem;
+use std::path::Path;
 
 const NUM_KEYS: usize = 16;
 const NUM_REGISTERS: usize = 16;
-const RAM: usize = 0x1000;
+//XO-CHIP addresses a full 64 KiB
+const RAM: usize = 0x10000;
 const STACK_SIZE: usize = 16;
 pub const DISPLAY_MAX_Y: usize = 32;
 pub const DISPLAY_MAX_X: usize = 64;
+//SUPER-CHIP high resolution mode
+pub const HIRES_MAX_Y: usize = 64;
+pub const HIRES_MAX_X: usize = 128;
 const FONTSET_SIZE: usize = 80;
+const BIG_FONTSET_SIZE: usize = 160;
+const NUM_RPL_FLAGS: usize = 16;
+//XO-CHIP draws to two bitplanes, giving 4 colors
+const NUM_PLANES: u8 = 2;
+const AUDIO_PATTERN_SIZE: usize = 16;
+//pitch register value for the default 4000hz playback rate
+const DEFAULT_PITCH: u8 = 64;
+//F000 NNNN is the only 4 byte instruction
+const LONG_I_OPCODE: u16 = 0xF000;
 const START_ADDR: usize = 0x200;
 const SPRITE_WIDTH: usize = 8;
 const FONTSET_ADDR: usize = 0x50;
+//big font sits right after the small one
+const BIG_FONTSET_ADDR: usize = FONTSET_ADDR + FONTSET_SIZE;
+const BIG_SPRITE_SIZE: usize = 16;
+const SCROLL_PIXELS: usize = 4;
+pub const MAX_ROM_SIZE: usize = RAM - START_ADDR;
+//where load_rom puts programs
+pub const DEFAULT_LOAD_ADDR: u16 = START_ADDR as u16;
+//ETI-660 programs start here instead
+pub const ETI_660_LOAD_ADDR: u16 = 0x600;
 
 const FONTSET: [u8; FONTSET_SIZE] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
@@ -31,6 +63,35 @@ const FONTSET: [u8; FONTSET_SIZE] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
+//SUPER-CHIP 8x10 font, A-F from Octo
+const BIG_FONTSET: [u8; BIG_FONTSET_SIZE] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+//Borrowed view of the screen, pixels are row major
+//each pixel is a color index 0-3, bit 0 is plane 1 and bit 1 is plane 2
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frame<'a> {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: &'a [u8],
+}
+
 #[derive(Debug)]
 pub struct Chip8 {
     //16 registers
@@ -50,11 +111,49 @@ pub struct Chip8 {
     delay_timer_register: u8,
     sound_timer_register: u8,
     keys: [bool; NUM_KEYS],
-    display: [bool; DISPLAY_MAX_X * DISPLAY_MAX_Y],
+    //sized for the current resolution, one bit per plane
+    display: Vec<u8>,
+    //bitmask of planes that draw, clear and scroll affect
+    selected_planes: u8,
+    audio_pattern: [u8; AUDIO_PATTERN_SIZE],
+    pitch: u8,
+    hires: bool,
+    //SUPER-CHIP HP48 flag registers, survive a restart
+    rpl_flags: [u8; NUM_RPL_FLAGS],
+    //set by 00FD, the program has exited
+    halted: bool,
+    quirks: Quirks,
+    //where the rom went and execution starts, restart goes back here
+    load_addr: usize,
+    //Cxkk draws from here, seed is the state it starts from on restart
+    rng: Box<dyn RandomSource>,
+    seed: u64,
+    //set by tick_timers, lets draw wait for the next frame with display_wait quirk
+    vblank: bool,
+    //debugger state, not part of save states
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    //watched accesses made by the last instruction
+    watch_hits: Vec<WatchHit>,
+    //pc and opcode of the instruction being executed, for watch hits
+    executing: (u16, u16),
+    //off unless set_tracer is called, survives load_state like breakpoints
+    tracer: Option<Tracer>,
+}
+
+impl Default for Chip8 {
+    fn default() -> Self {
+        Self::new(Quirks::default())
+    }
 }
 
 impl Chip8 {
-    pub fn new() -> Self {
+    //seeded randomly, use with_seed for reproducible runs
+    pub fn new(quirks: Quirks) -> Self {
+        Chip8::with_seed(quirks, rand::random())
+    }
+
+    pub fn with_seed(quirks: Quirks, seed: u64) -> Self {
         let mut chip = Chip8 {
             position_in_memory: 0,
             registers: [0; NUM_REGISTERS],
@@ -65,28 +164,47 @@ impl Chip8 {
             delay_timer_register: 0,
             sound_timer_register: 0,
             keys: [false; NUM_KEYS],
-            display: [false; DISPLAY_MAX_X * DISPLAY_MAX_Y],
+            display: vec![0; DISPLAY_MAX_X * DISPLAY_MAX_Y],
+            selected_planes: 1,
+            audio_pattern: [0; AUDIO_PATTERN_SIZE],
+            pitch: DEFAULT_PITCH,
+            hires: false,
+            rpl_flags: [0; NUM_RPL_FLAGS],
+            halted: false,
+            quirks,
+            load_addr: START_ADDR,
+            rng: Box::new(SplitMix64::new(seed)),
+            seed,
+            vblank: false,
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            watch_hits: Vec::new(),
+            executing: (0, 0),
+            tracer: None,
         };
         //load fontset
         chip.memory[FONTSET_ADDR..=FONTSET_ADDR + FONTSET_SIZE - 1].copy_from_slice(&FONTSET);
+        chip.memory[BIG_FONTSET_ADDR..BIG_FONTSET_ADDR + BIG_FONTSET_SIZE]
+            .copy_from_slice(&BIG_FONTSET);
         //start addr
         chip.position_in_memory = START_ADDR;
         chip
     }
 
-    pub fn new_with_rom(rom: Box<[u8]>) -> Self {
-        let mut chip = Chip8::new();
-        chip.load_rom(rom);
-        chip
+    pub fn new_with_rom(rom: &[u8], quirks: Quirks) -> Result<Self, ChipError> {
+        let mut chip = Chip8::new(quirks);
+        chip.load_rom(rom)?;
+        Ok(chip)
     }
 
     pub fn reset(&mut self) {
         self.memory = [0; RAM];
+        self.rpl_flags = [0; NUM_RPL_FLAGS];
         self.restart();
     }
 
     pub fn restart(&mut self) {
-        self.position_in_memory = START_ADDR;
+        self.position_in_memory = self.load_addr;
         self.registers = [0; NUM_REGISTERS];
         self.stack = [0; STACK_SIZE];
         self.stack_pointer = 0;
@@ -94,23 +212,424 @@ impl Chip8 {
         self.delay_timer_register = 0;
         self.sound_timer_register = 0;
         self.keys = [false; NUM_KEYS];
-        self.display = [false; DISPLAY_MAX_X * DISPLAY_MAX_Y];
+        self.hires = false;
+        self.display = vec![0; DISPLAY_MAX_X * DISPLAY_MAX_Y];
+        self.selected_planes = 1;
+        self.audio_pattern = [0; AUDIO_PATTERN_SIZE];
+        self.pitch = DEFAULT_PITCH;
+        self.halted = false;
+        self.vblank = false;
+        self.rng.set_state(self.seed);
+    }
+
+    //swap in a different random source, it is reset to seed
+    pub fn set_random_source(&mut self, mut rng: Box<dyn RandomSource>) {
+        rng.set_state(self.seed);
+        self.rng = rng;
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn get_display(&self) -> Frame<'_> {
+        Frame {
+            width: self.display_width(),
+            height: self.display_height(),
+            pixels: &self.display,
+        }
+    }
+
+    //the buzzer sounds for as long as the sound timer is above zero
+    pub fn is_sound_active(&self) -> bool {
+        self.sound_timer_register > 0
+    }
+
+    //XO-CHIP 1 bit audio pattern, played back at the rate set by pitch
+    pub fn audio_pattern(&self) -> &[u8; AUDIO_PATTERN_SIZE] {
+        &self.audio_pattern
+    }
+
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    //samples per second the audio pattern is played at
+    pub fn playback_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - DEFAULT_PITCH as f32) / 48.0)
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    //true once the program has run 00FD
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    fn display_width(&self) -> usize {
+        if self.hires {
+            HIRES_MAX_X
+        } else {
+            DISPLAY_MAX_X
+        }
+    }
+
+    fn display_height(&self) -> usize {
+        if self.hires {
+            HIRES_MAX_Y
+        } else {
+            DISPLAY_MAX_Y
+        }
     }
 
-    pub fn get_display(&self) -> &[bool; DISPLAY_MAX_X * DISPLAY_MAX_Y] {
-        &self.display
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), ChipError> {
+        self.load_rom_at(rom, DEFAULT_LOAD_ADDR)
     }
 
-    pub fn load_rom(&mut self, rom: Box<[u8]>) {
-        self.memory[START_ADDR..=START_ADDR + rom.len() - 1].copy_from_slice(&rom);
+    //Copies rom to addr and starts execution there, now and on every restart
+    pub fn load_rom_at(&mut self, rom: &[u8], addr: u16) -> Result<(), ChipError> {
+        let start = addr as usize;
+        if start < START_ADDR {
+            return Err(ChipError::InvalidLoadAddress(addr));
+        }
+        if rom.is_empty() {
+            return Err(ChipError::EmptyRom);
+        }
+        if rom.len() > RAM - start {
+            return Err(ChipError::RomTooLarge {
+                size: rom.len(),
+                max: RAM - start,
+            });
+        }
+        self.memory[start..start + rom.len()].copy_from_slice(rom);
+        self.load_addr = start;
+        self.position_in_memory = start;
+        Ok(())
+    }
+
+    pub fn load_rom_from(&mut self, mut reader: impl Read, addr: u16) -> Result<(), RomError> {
+        let mut rom = Vec::new();
+        reader.read_to_end(&mut rom)?;
+        Ok(self.load_rom_at(&rom, addr)?)
+    }
+
+    pub fn load_rom_file(&mut self, path: impl AsRef<Path>, addr: u16) -> Result<(), RomError> {
+        self.load_rom_from(std::fs::File::open(path)?, addr)
     }
 
-    pub fn tick(&mut self) {
+    //Snapshot of the whole machine, see state.rs for the container format
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+        state.u8(self.quirks.to_bits());
+        state.bytes(&self.registers);
+        state.u32(self.position_in_memory as u32);
+        state.bytes(&self.memory);
+        for addr in self.stack {
+            state.u16(addr);
+        }
+        state.u8(self.stack_pointer as u8);
+        state.u16(self.i_register);
+        state.u8(self.delay_timer_register);
+        state.u8(self.sound_timer_register);
+        let keys = (0..NUM_KEYS).fold(0u16, |keys, k| keys | (self.keys[k] as u16) << k);
+        state.u16(keys);
+        state.bool(self.hires);
+        state.bytes(&self.display);
+        state.u8(self.selected_planes);
+        state.bytes(&self.audio_pattern);
+        state.u8(self.pitch);
+        state.bytes(&self.rpl_flags);
+        state.bool(self.halted);
+        state.bool(self.vblank);
+        state.u64(self.seed);
+        state.u64(self.rng.state());
+        state.finish()
+    }
+
+    //Restores a snapshot from save_state, the chip is untouched if it fails
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), ChipError> {
+        let mut reader = StateReader::open(state)?;
+        let mut chip = Chip8::new(Quirks::from_bits(reader.u8()?));
+        chip.registers.copy_from_slice(reader.bytes(NUM_REGISTERS)?);
+        chip.position_in_memory = reader.u32()? as usize;
+        chip.memory.copy_from_slice(reader.bytes(RAM)?);
+        for addr in chip.stack.iter_mut() {
+            *addr = reader.u16()?;
+        }
+        chip.stack_pointer = reader.u8()? as usize;
+        chip.i_register = reader.u16()?;
+        chip.delay_timer_register = reader.u8()?;
+        chip.sound_timer_register = reader.u8()?;
+        let keys = reader.u16()?;
+        for (k, key) in chip.keys.iter_mut().enumerate() {
+            *key = keys & 1 << k != 0;
+        }
+        chip.hires = reader.bool()?;
+        let display_size = chip.display_width() * chip.display_height();
+        chip.display = reader.bytes(display_size)?.to_vec();
+        chip.selected_planes = reader.u8()?;
+        chip.audio_pattern
+            .copy_from_slice(reader.bytes(AUDIO_PATTERN_SIZE)?);
+        chip.pitch = reader.u8()?;
+        chip.rpl_flags.copy_from_slice(reader.bytes(NUM_RPL_FLAGS)?);
+        chip.halted = reader.bool()?;
+        chip.vblank = reader.bool()?;
+        chip.seed = reader.u64()?;
+        let rng_state = reader.u64()?;
+        reader.finish()?;
+
+        let colors = 1 << NUM_PLANES;
+        if chip.position_in_memory >= RAM
+            || chip.stack_pointer > STACK_SIZE
+            || chip.selected_planes >= colors
+            || chip.display.iter().any(|pixel| *pixel >= colors)
+        {
+            return Err(StateError::Corrupt.into());
+        }
+
+        //keep whatever random source was injected, just rewind it
+        mem::swap(&mut chip.rng, &mut self.rng);
+        chip.rng.set_state(rng_state);
+        mem::swap(&mut chip.breakpoints, &mut self.breakpoints);
+        mem::swap(&mut chip.watchpoints, &mut self.watchpoints);
+        mem::swap(&mut chip.tracer, &mut self.tracer);
+        //states don't record where the rom went, the chip loading them does
+        chip.load_addr = self.load_addr;
+        *self = chip;
+        Ok(())
+    }
+
+    pub fn tick(&mut self) -> Result<(), ChipError> {
+        if self.halted {
+            return Ok(());
+        }
+        self.watch_hits.clear();
+        let pc = self.position_in_memory as u16;
         let opcode = self.fetch();
-        self.execute(opcode);
+        self.executing = (pc, opcode);
+        let before = self.tracer.is_some().then(|| self.snapshot());
+        //the address F000 loads is part of the instruction, so it's traced with it
+        let operand = (before.is_some() && opcode == LONG_I_OPCODE)
+            .then(|| self.opcode_at(pc.wrapping_add(2)));
+        let result = self.execute(pc, opcode);
+        //faulting instructions are traced too, they are usually the interesting ones
+        if let Some(before) = before {
+            let record = TraceRecord::new(pc, opcode, operand, &before, &self.snapshot());
+            if let Some(tracer) = self.tracer.as_mut() {
+                tracer.record(&record);
+            }
+        }
+        result
+    }
+
+    //Traces every instruction run from now on, replacing any previous tracer
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    //Stops tracing, call finish on the result to flush it
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            registers: self.registers,
+            i: self.i_register,
+            sp: self.stack_pointer as u16,
+        }
+    }
+
+    //Runs up to max_instructions, stopping early at a breakpoint, when stop is met or on 00FD.
+    //The first instruction always runs, so continuing from a breakpoint moves past it.
+    pub fn run_until(
+        &mut self,
+        stop: StopCondition,
+        max_instructions: u32,
+    ) -> Result<DebugEvent, ChipError> {
+        for _ in 0..max_instructions {
+            if self.halted {
+                return Ok(DebugEvent::Halted);
+            }
+            let pc = self.position_in_memory;
+            self.tick()?;
+            if let Some(hit) = self.watch_hits.first() {
+                return Ok(DebugEvent::Watchpoint(*hit));
+            }
+            let next = self.position_in_memory as u16;
+            let stopped = match stop {
+                StopCondition::Breakpoint => false,
+                StopCondition::Step => true,
+                StopCondition::Address { addr, depth } => {
+                    next == addr && self.stack_pointer <= depth
+                }
+                StopCondition::Return { depth } => self.stack_pointer < depth,
+            };
+            if stopped {
+                return Ok(DebugEvent::Stopped(next));
+            }
+            //a draw waiting for vblank stays put, don't break on it again
+            if self.position_in_memory != pc && self.is_breakpoint_hit(next) {
+                return Ok(DebugEvent::Breakpoint(next));
+            }
+        }
+        if self.halted {
+            return Ok(DebugEvent::Halted);
+        }
+        Ok(DebugEvent::InstructionLimit)
+    }
+
+    pub fn step(&mut self) -> Result<DebugEvent, ChipError> {
+        self.run_until(StopCondition::Step, 1)
+    }
+
+    pub fn step_over(&mut self, max_instructions: u32) -> Result<DebugEvent, ChipError> {
+        self.run_until(self.step_over_condition(), max_instructions)
+    }
+
+    pub fn step_out(&mut self, max_instructions: u32) -> Result<DebugEvent, ChipError> {
+        self.run_until(self.step_out_condition(), max_instructions)
+    }
+
+    pub fn run_to(&mut self, addr: u16, max_instructions: u32) -> Result<DebugEvent, ChipError> {
+        self.run_until(self.run_to_condition(addr), max_instructions)
+    }
+
+    //These take a snapshot of the stack depth, keep the result to resume
+    //a step over or step out that runs across several frames.
+
+    //a call runs until it returns to the next instruction, anything else is a single step
+    pub fn step_over_condition(&self) -> StopCondition {
+        let pc = self.position_in_memory as u16;
+        match Opcode::decode(self.opcode_at(pc)) {
+            Opcode::Call(_) => StopCondition::Address {
+                addr: pc.wrapping_add(2),
+                depth: self.stack_pointer,
+            },
+            _ => StopCondition::Step,
+        }
+    }
+
+    pub fn step_out_condition(&self) -> StopCondition {
+        StopCondition::Return {
+            depth: self.stack_pointer,
+        }
+    }
+
+    pub fn run_to_condition(&self, addr: u16) -> StopCondition {
+        StopCondition::Address {
+            addr,
+            depth: STACK_SIZE,
+        }
+    }
+
+    //replaces any breakpoint already at addr
+    pub fn add_breakpoint(&mut self, addr: u16, condition: Option<Condition>) {
+        self.remove_breakpoint(addr);
+        self.breakpoints.push(Breakpoint { addr, condition });
+    }
+
+    //false if there was no breakpoint at addr
+    pub fn remove_breakpoint(&mut self, addr: u16) -> bool {
+        let before = self.breakpoints.len();
+        self.breakpoints.retain(|b| b.addr != addr);
+        self.breakpoints.len() != before
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    //false if there was no such watchpoint
+    pub fn remove_watchpoint(&mut self, watchpoint: &Watchpoint) -> bool {
+        let before = self.watchpoints.len();
+        self.watchpoints.retain(|w| w != watchpoint);
+        self.watchpoints.len() != before
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    //every watched access made by the last instruction, in order
+    pub fn watch_hits(&self) -> &[WatchHit] {
+        &self.watch_hits
+    }
+
+    //whether a breakpoint at pc stops there, condition included
+    pub fn is_breakpoint_hit(&self, pc: u16) -> bool {
+        self.breakpoints.iter().any(|b| {
+            b.addr == pc
+                && b.condition
+                    .is_none_or(|c| c.comparison.compare(self.register(c.register), c.value))
+        })
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    //writes memory from outside the program, e.g. a debugger. Watchpoints don't see it
+    pub fn poke(&mut self, addr: u16, value: u8) {
+        self.memory[addr as usize % RAM] = value;
+    }
+
+    //return addresses of the calls in progress, outermost first
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.stack_pointer]
+    }
+
+    //the two bytes at addr, without running anything
+    pub fn opcode_at(&self, addr: u16) -> u16 {
+        let addr = addr as usize;
+        (self.memory[addr] as u16) << 8 | self.memory[(addr + 1) % RAM] as u16
+    }
+
+    //timers, sp and the V registers widened to u16
+    pub fn register(&self, register: Register) -> u16 {
+        match register {
+            Register::V(x) => self.registers[x as usize & 0xF] as u16,
+            Register::I => self.i_register,
+            Register::Pc => self.position_in_memory as u16,
+            Register::Sp => self.stack_pointer as u16,
+            Register::DelayTimer => self.delay_timer_register as u16,
+            Register::SoundTimer => self.sound_timer_register as u16,
+        }
+    }
+
+    //for debuggers, values are cut down to the register's width and sp to the stack depth
+    pub fn set_register(&mut self, register: Register, value: u16) {
+        match register {
+            Register::V(x) => self.registers[x as usize & 0xF] = value as u8,
+            Register::I => self.i_register = value,
+            Register::Pc => self.position_in_memory = value as usize,
+            Register::Sp => self.stack_pointer = (value as usize).min(STACK_SIZE),
+            Register::DelayTimer => self.delay_timer_register = value as u8,
+            Register::SoundTimer => self.sound_timer_register = value as u8,
+        }
     }
 
     pub fn tick_timers(&mut self) {
+        self.vblank = true;
+
         if self.delay_timer_register > 0 {
             self.delay_timer_register -= 1;
         }
@@ -120,23 +639,23 @@ impl Chip8 {
         }
     }
 
-    pub fn key_press(&mut self, key: u8) {
+    pub fn key_press(&mut self, key: u8) -> Result<(), ChipError> {
         //runtime check here, responsibilty falls on keyboard provider
-        //should never happen
-        if key > 15 {
-            panic!("Invalid Key Provided, key must be hexadecmal value of 0x0 through 0xF");
+        if key as usize >= NUM_KEYS {
+            return Err(ChipError::InvalidKey(key));
         }
 
         self.keys[key as usize] = true;
+        Ok(())
     }
 
-    pub fn key_release(&mut self, key: u8) {
+    pub fn key_release(&mut self, key: u8) -> Result<(), ChipError> {
         //runtime check here, responsibilty falls on keyboard provider
-        //should never happen
-        if key > 15 {
-            panic!("Invalid Key Provided, key must be hexadecmal value of 0x0 through 0xF");
+        if key as usize >= NUM_KEYS {
+            return Err(ChipError::InvalidKey(key));
         }
         self.keys[key as usize] = false;
+        Ok(())
     }
 
     fn fetch(&mut self) -> u16 {
@@ -144,33 +663,88 @@ impl Chip8 {
         //byte at p
         let op_byte1 = self.memory[p] as u16;
         //byte at p + 1
-        let op_byte2 = self.memory[p + 1] as u16;
-        //shift position in memory by 2 bytes
-        self.position_in_memory += 2;
+        let op_byte2 = self.memory[(p + 1) % RAM] as u16;
+        //shift position in memory by 2 bytes, wrapping at the end of ram like I does
+        self.position_in_memory = (p + 2) % RAM;
         //combine into a single 16 bit opcode
-        op_byte1 << 8 | op_byte2
+        let opcode = op_byte1 << 8 | op_byte2;
+        if !self.watchpoints.is_empty() {
+            self.watch(WatchHit {
+                access: Access::Execute,
+                addr: p as u16,
+                pc: p as u16,
+                opcode,
+                old: op_byte1 as u8,
+                new: op_byte1 as u8,
+            });
+        }
+        opcode
+    }
+
+    //memory access for instructions, wraps at the end of ram and reports to watchpoints
+    fn read_memory(&mut self, addr: usize) -> u8 {
+        let addr = addr % RAM;
+        let value = self.memory[addr];
+        if !self.watchpoints.is_empty() {
+            self.watch_access(Access::Read, addr, value, value);
+        }
+        value
+    }
+
+    fn write_memory(&mut self, addr: usize, value: u8) {
+        let addr = addr % RAM;
+        let old = self.memory[addr];
+        self.memory[addr] = value;
+        if !self.watchpoints.is_empty() {
+            self.watch_access(Access::Write, addr, old, value);
+        }
     }
 
-    fn execute(&mut self, opcode: u16) {
+    fn watch_access(&mut self, access: Access, addr: usize, old: u8, new: u8) {
+        let (pc, opcode) = self.executing;
+        self.watch(WatchHit {
+            access,
+            addr: addr as u16,
+            pc,
+            opcode,
+            old,
+            new,
+        });
+    }
+
+    fn watch(&mut self, hit: WatchHit) {
+        if self
+            .watchpoints
+            .iter()
+            .any(|w| w.matches(hit.access, hit.addr))
+        {
+            self.watch_hits.push(hit);
+        }
+    }
+
+    //pc is the address opcode was fetched from, used to report faults
+    fn execute(&mut self, pc: u16, opcode: u16) -> Result<(), ChipError> {
         match Opcode::decode(opcode) {
-            Opcode::Sys(_) => panic!("Syscall not supported"),
+            Opcode::Sys(_) => return Err(ChipError::SysOpcodeNotSupported { pc, opcode }),
             Opcode::Jump(nnn) => self.jump(nnn),
             Opcode::JumpPlusV0(nnn) => self.jump_plus_v0(nnn),
-            Opcode::Call(nnn) => self.call(nnn),
-            Opcode::SkipIfEqualAtX { x, kk } => self.skip_if_equal_at_x(x, kk), 
+            Opcode::Call(nnn) => self.call(nnn)?,
+            Opcode::SkipIfEqualAtX { x, kk } => self.skip_if_equal_at_x(x, kk),
             Opcode::SkipIfNotEqualAtX { x, kk } => self.skip_if_not_equal_at_x(x, kk),
             Opcode::LoadValueToRegister { x, kk } => self.load_value_to_register(x, kk),
             Opcode::AddToValueInRegister { x, kk } => self.add_to_value_in_register(x, kk),
             Opcode::SkipIfBothValuesEqual { x, y } => self.skip_if_both_values_are_equal(x, y),
-            Opcode::SkipIfBothValuesNotEqual { x, y } => self.skip_if_both_values_are_not_equal(x, y),
+            Opcode::SkipIfBothValuesNotEqual { x, y } => {
+                self.skip_if_both_values_are_not_equal(x, y)
+            }
             Opcode::LoadYIntoX { x, y } => self.load_y_into_x(x, y),
             Opcode::BitwiseOrXY { x, y } => self.bitwise_or_xy(x, y),
             Opcode::BitwiseAndXY { x, y } => self.bitwise_and_xy(x, y),
             Opcode::BitwiseXorXY { x, y } => self.bitwise_xor_xy(x, y),
             Opcode::AddXY { x, y } => self.add_xy(x, y),
-            Opcode::SubYfromX { x, y } => self.sub_y_from_x(x, y),
-            Opcode::SubXfromY { x, y } => self.sub_x_from_y(x, y),
-            Opcode::Ret => self.ret(),
+            Opcode::SubXfromY { x, y } => self.sub_y_from_x(x, y),
+            Opcode::SubYfromX { x, y } => self.sub_x_from_y(x, y),
+            Opcode::Ret => self.ret()?,
             Opcode::ShiftRight { x, y } => self.shift_right(x, y),
             Opcode::ShiftLeft { x, y } => self.shift_left(x, y),
             Opcode::SetIRegister(nnn) => self.set_i_register(nnn),
@@ -191,22 +765,61 @@ impl Chip8 {
             Opcode::WaitForKeyPressAndStoreVx { x } => self.wait_for_keypress_store_vx(x),
             Opcode::SkipIfKeyAtVxPressed { x } => self.skip_if_key_at_vx_pressed(x),
             Opcode::SkipIfKeyAtVxNotPressed { x } => self.skip_if_key_at_vx_not_pressed(x),
-            Opcode::Draw { x, y, n } => self.draw(x, y, n),
+            Opcode::Draw { x, y, n } => {
+                if self.quirks.display_wait && !self.vblank {
+                    //redo opcode until the next frame starts
+                    self.repeat_instruction();
+                } else {
+                    self.vblank = false;
+                    self.draw(x, y, n);
+                }
+            }
             Opcode::ClearScreen => self.clear_screen(),
+            Opcode::ScrollDown { n } => self.scroll_down(n),
+            Opcode::ScrollUp { n } => self.scroll_up(n),
+            Opcode::ScrollRight => self.scroll_right(),
+            Opcode::ScrollLeft => self.scroll_left(),
+            Opcode::Exit => self.halted = true,
+            Opcode::LowResolution => self.set_resolution(false),
+            Opcode::HighResolution => self.set_resolution(true),
+            Opcode::SetICorrespondingBigFontAddressFromVx { x } => {
+                self.set_big_font_address_for_value_in_vx(x)
+            }
+            Opcode::StoreRegistersV0ToVxInRplFlags { x } => {
+                self.store_registers_v0_to_vx_in_rpl_flags(x)
+            }
+            Opcode::LoadRegistersV0ToVxFromRplFlags { x } => {
+                self.load_registers_v0_to_vx_from_rpl_flags(x)
+            }
+            Opcode::SetIRegisterLong => self.set_i_register_long(),
+            Opcode::SelectPlanes { n } => self.select_planes(n),
+            Opcode::LoadRegistersVxToVyIntoMemoryAtI { x, y } => {
+                self.load_registers_vx_to_vy_into_memory_at_i(x, y)
+            }
+            Opcode::FillRegistersVxToVyFromMemoryAtI { x, y } => {
+                self.fill_registers_vx_to_vy_from_memory_at_i(x, y)
+            }
+            Opcode::LoadAudioPatternFromI => self.load_audio_pattern_from_i(),
+            Opcode::SetPitchFromVx { x } => self.set_pitch_from_vx(x),
             Opcode::SetICorrespondingFontAddressFromVx { x } => {
                 self.set_font_address_for_value_in_vx(x)
             }
-            Opcode::UnknownOpcode(op) => todo!("opcode {:04x}", op),
+            Opcode::UnknownOpcode(_) => return Err(ChipError::UnknownOpcode { pc, opcode }),
         }
+        Ok(())
     }
 
     //2nnn - CALL addr
-    fn call(&mut self, addr: u16) {
+    fn call(&mut self, addr: u16) -> Result<(), ChipError> {
         let sp = self.stack_pointer;
         let stack = &mut self.stack;
 
         if sp >= stack.len() {
-            panic!("Stack overflow");
+            //fetch already moved past the call, so it lives 2 bytes back
+            return Err(ChipError::StackOverflow {
+                pc: (self.position_in_memory as u16).wrapping_sub(2),
+                opcode: 0x2000 | addr,
+            });
         }
 
         //store current position in memory in stack
@@ -215,12 +828,16 @@ impl Chip8 {
         self.stack_pointer += 1;
         //set position in memory to addr provided
         self.position_in_memory = addr as usize;
+        Ok(())
     }
 
     //00EE - RET
-    fn ret(&mut self) {
+    fn ret(&mut self) -> Result<(), ChipError> {
         if self.stack_pointer == 0 {
-            panic!("Stack underflow");
+            return Err(ChipError::StackUnderflow {
+                pc: (self.position_in_memory as u16).wrapping_sub(2),
+                opcode: 0x00EE,
+            });
         }
 
         //decrement stack pointer
@@ -229,6 +846,7 @@ impl Chip8 {
         let call_addr = self.stack[self.stack_pointer];
         //set position in memory to call_addr
         self.position_in_memory = call_addr as usize;
+        Ok(())
     }
 
     //1nnn - JP addr
@@ -240,7 +858,7 @@ impl Chip8 {
     fn skip_if_equal_at_x(&mut self, x: u8, kk: u8) {
         let vx = self.registers[x as usize];
         if vx == kk {
-            self.position_in_memory += 2;
+            self.skip_next_instruction();
         }
     }
 
@@ -248,7 +866,7 @@ impl Chip8 {
     fn skip_if_not_equal_at_x(&mut self, x: u8, kk: u8) {
         let vx = self.registers[x as usize];
         if vx != kk {
-            self.position_in_memory += 2;
+            self.skip_next_instruction();
         }
     }
 
@@ -257,7 +875,7 @@ impl Chip8 {
         let vx = self.registers[x as usize];
         let vy = self.registers[y as usize];
         if vx == vy {
-            self.position_in_memory += 2;
+            self.skip_next_instruction();
         }
     }
 
@@ -283,6 +901,9 @@ impl Chip8 {
         let vx = self.registers[x as usize];
         let vy = self.registers[y as usize];
         self.registers[x as usize] = vx | vy;
+        if self.quirks.vf_reset {
+            self.registers[0xF] = 0;
+        }
     }
 
     //8xy2 AND Vx, Vy
@@ -290,6 +911,9 @@ impl Chip8 {
         let vx = self.registers[x as usize];
         let vy = self.registers[y as usize];
         self.registers[x as usize] = vx & vy;
+        if self.quirks.vf_reset {
+            self.registers[0xF] = 0;
+        }
     }
 
     //8xy3 XOR Vx, Vy
@@ -297,6 +921,9 @@ impl Chip8 {
         let vx = self.registers[x as usize];
         let vy = self.registers[y as usize];
         self.registers[x as usize] = vx ^ vy;
+        if self.quirks.vf_reset {
+            self.registers[0xF] = 0;
+        }
     }
 
     //8xy4 - ADD Vx, Vy
@@ -313,7 +940,7 @@ impl Chip8 {
         let vx = self.registers[x as usize];
         let vy = self.registers[y as usize];
         self.registers[x as usize] = vx.wrapping_sub(vy);
-        self.set_vf(!vx > vy);
+        self.set_vf(vx >= vy);
     }
 
     //8xy7 SUBN Vx, Vy
@@ -321,24 +948,27 @@ impl Chip8 {
         let vx = self.registers[x as usize];
         let vy = self.registers[y as usize];
         self.registers[x as usize] = vy.wrapping_sub(vx);
-        self.set_vf(!vy > vx);
+        self.set_vf(vy >= vx);
     }
 
     //8xy6 SHR Vx {, Vy}
     fn shift_right(&mut self, x: u8, y: u8) {
-        let vy = self.registers[y as usize];
+        //shift quirk operates on Vx directly
+        let src = if self.quirks.shift { x } else { y };
+        let value = self.registers[src as usize];
 
-        self.registers[x as usize] = vy >> 1;
+        self.registers[x as usize] = value >> 1;
         //lsb in VF
-        self.registers[0xF] = vy & 1
+        self.registers[0xF] = value & 1
     }
 
     //8xyE - SHL Vx {, Vy}
     fn shift_left(&mut self, x: u8, y: u8) {
-        let vy = self.registers[y as usize];
-        self.registers[x as usize] = vy << 1;
+        let src = if self.quirks.shift { x } else { y };
+        let value = self.registers[src as usize];
+        self.registers[x as usize] = value << 1;
         //shift last bit 7 and mask 1 to get msb
-        self.registers[0xF] = vy >> 7 & 1;
+        self.registers[0xF] = value >> 7 & 1;
     }
 
     //9xy0 - SNE Vx, Vy
@@ -346,7 +976,7 @@ impl Chip8 {
         let vx = self.registers[x as usize];
         let vy = self.registers[y as usize];
         if vx != vy {
-            self.position_in_memory += 2;
+            self.skip_next_instruction();
         }
     }
 
@@ -356,14 +986,16 @@ impl Chip8 {
     }
 
     //Bnnn - JP V0, addr
+    //with jump quirk this is Bxnn - JP Vx, addr
     fn jump_plus_v0(&mut self, nnn: u16) {
-        let v0 = self.registers[0];
-        self.jump(nnn + (v0 as u16));
+        let r = if self.quirks.jump { nnn >> 8 } else { 0 };
+        let offset = self.registers[r as usize];
+        self.jump(nnn + (offset as u16));
     }
 
     //Cxkk - RND Vx, byte
     fn random_number_to_x(&mut self, x: u8, kk: u8) {
-        let random_number: u8 = thread_rng().gen_range(0..=255);
+        let random_number = self.rng.next_u8();
         self.registers[x as usize] = random_number & kk;
     }
 
@@ -397,29 +1029,30 @@ impl Chip8 {
         let tens = (vx % 100) / 10;
         let ones = vx % 10;
         let i = self.i_register as usize;
-        self.memory[i] = hundreds;
-        self.memory[i + 1] = tens;
-        self.memory[i + 2] = ones;
+        self.write_memory(i, hundreds);
+        self.write_memory(i + 1, tens);
+        self.write_memory(i + 2, ones);
     }
 
     //Fx55 LD [I], Vx
     fn load_registers_v0_to_vx_into_memory_at_i(&mut self, x: u8) {
-        let mut i = self.i_register as usize;
-        for r in 0..=x {
-            let vr = self.registers[r as usize];
-            self.memory[i] = vr;
-            i += 1;
+        let i = self.i_register as usize;
+        for r in 0..=x as usize {
+            self.write_memory(i + r, self.registers[r]);
         }
-        // I is set to I + X + 1
-        self.i_register += x as u16 + 1;
+        // I is set to I + X + 1 on the VIP, wrapping like the addresses written
+        let increment = self.quirks.memory_increment.amount(x);
+        self.i_register = self.i_register.wrapping_add(increment);
     }
 
     //Fx65 LD Vx, [I]
     fn fill_registers_v0_to_vx_from_memory_at_i(&mut self, x: u8) {
-        for r in 0..=x {
-            self.registers[r as usize] = self.memory[(self.i_register + r as u16) as usize];
+        let i = self.i_register as usize;
+        for r in 0..=x as usize {
+            self.registers[r] = self.read_memory(i + r);
         }
-        self.i_register += x as u16 + 1;
+        let increment = self.quirks.memory_increment.amount(x);
+        self.i_register = self.i_register.wrapping_add(increment);
     }
 
     //Fx0A LD Vx, K
@@ -436,61 +1069,97 @@ impl Chip8 {
 
         //redo opcode
         if !pressed {
-            self.position_in_memory -= 2;
+            self.repeat_instruction();
         }
     }
 
+    //back to the instruction just fetched, which may have wrapped pc round to 0
+    fn repeat_instruction(&mut self) {
+        self.position_in_memory = (self.position_in_memory + RAM - 2) % RAM;
+    }
+
     //Ex9E SKP Vx
     fn skip_if_key_at_vx_pressed(&mut self, x: u8) {
-        let vx = self.registers[x as usize];
-        if self.keys[vx as usize] {
-            self.position_in_memory += 2;
+        //only the low nibble picks the key, like the VIP
+        let key = self.registers[x as usize] & 0xF;
+        if self.keys[key as usize] {
+            self.skip_next_instruction();
         }
     }
 
     //ExA1 SKP Vx
     fn skip_if_key_at_vx_not_pressed(&mut self, x: u8) {
-        let vx = self.registers[x as usize];
-        if !(self.keys[vx as usize]) {
-            self.position_in_memory += 2;
+        //only the low nibble picks the key, like the VIP
+        let key = self.registers[x as usize] & 0xF;
+        if !self.keys[key as usize] {
+            self.skip_next_instruction();
         }
     }
 
+    //Dxyn - DRW Vx, Vy, nibble
+    //Dxy0 draws a 16x16 sprite
     fn draw(&mut self, x: u8, y: u8, n: u8) {
-        let x_coord = self.registers[x as usize];
-        let y_coord = self.registers[y as usize];
+        let width = self.display_width();
+        let height = self.display_height();
+        //starting position always wraps, only the sprite itself is clipped
+        let x_coord = self.registers[x as usize] as usize % width;
+        let y_coord = self.registers[y as usize] as usize % height;
+        //big sprites are 2 bytes per row
+        let (rows, sprite_width) = if n == 0 {
+            (BIG_SPRITE_SIZE, BIG_SPRITE_SIZE)
+        } else {
+            (n as usize, SPRITE_WIDTH)
+        };
+        let bytes_per_row = sprite_width / 8;
         let mut flipped = false;
-        //n is num bytes at i for sprite
-        //Each byte represents one line of the sprite top down
-        //loop through each of the bytes at I gives us our y axis
-        for y_line in 0..n {
-            let addr = self.i_register + y_line as u16;
-            let pixels = self.memory[addr as usize];
-
-            //each bit in the byte represent the 8 bits that make up x axis
-            //sprites are always 8
-            for x_line in 0..SPRITE_WIDTH {
-                //this shifts through each bit, and checks if it needs to flip
-                //only flips when it is 1
-                if pixels & (0b10000000 >> x_line) != 0 {
-                    // Sprites should wrap around screen, so apply modulo
-                    let x = (x_coord + x_line as u8) as usize % DISPLAY_MAX_X;
-                    let y = (y_coord + y_line) as usize % DISPLAY_MAX_Y;
-
-                    // Get our pixel's index in the 1D screen array
-                    //index = y * width + x
-                    let idx = y * DISPLAY_MAX_X + x;
-                    // If a pixel on the screen is set to 01,
-                    //and the sprite to be drawn contains a 01 for this same pixel,
-                    //the screen pixel is turned off and VF is set to 01.
-                    //If the sprite is simply drawn on the screen without drawing over any pixels set to 01,
-                    //VF is set to 00
-                    //we want to flip if both are one
-                    flipped |= self.display[idx];
-                    //xor with true, since we only do this when the sprite wants a pixel drawn
-                    self.display[idx] ^= true;
+        //with both planes selected the sprite for plane 2 follows the one for plane 1
+        let mut sprite_addr = self.i_register as usize;
+        for plane in 0..NUM_PLANES {
+            let plane_bit = 1 << plane;
+            if self.selected_planes & plane_bit == 0 {
+                continue;
+            }
+            //n is num bytes at i for sprite
+            //Each byte represents one line of the sprite top down
+            //loop through each of the bytes at I gives us our y axis
+            for y_line in 0..rows {
+                let addr = sprite_addr + y_line * bytes_per_row;
+                let pixels = if bytes_per_row == 2 {
+                    (self.read_memory(addr) as u16) << 8 | self.read_memory(addr + 1) as u16
+                } else {
+                    (self.read_memory(addr) as u16) << 8
+                };
+
+                //each bit represents one pixel of the x axis
+                for x_line in 0..sprite_width {
+                    //this shifts through each bit, and checks if it needs to flip
+                    //only flips when it is 1
+                    if pixels & (0x8000 >> x_line) != 0 {
+                        let mut x = x_coord + x_line;
+                        let mut y = y_coord + y_line;
+                        if self.quirks.clipping && (x >= width || y >= height) {
+                            continue;
+                        }
+                        // Sprites should wrap around screen, so apply modulo
+                        x %= width;
+                        y %= height;
+
+                        // Get our pixel's index in the 1D screen array
+                        //index = y * width + x
+                        let idx = y * width + x;
+                        // If a pixel on the screen is set to 01,
+                        //and the sprite to be drawn contains a 01 for this same pixel,
+                        //the screen pixel is turned off and VF is set to 01.
+                        //If the sprite is simply drawn on the screen without drawing over any pixels set to 01,
+                        //VF is set to 00
+                        //we want to flip if both are one
+                        flipped |= self.display[idx] & plane_bit != 0;
+                        //xor the plane bit, since we only do this when the sprite wants a pixel drawn
+                        self.display[idx] ^= plane_bit;
+                    }
                 }
             }
+            sprite_addr += rows * bytes_per_row;
         }
 
         // Populate VF register
@@ -502,8 +1171,109 @@ impl Chip8 {
     }
 
     //00E0
+    //only clears the selected planes
     fn clear_screen(&mut self) {
-        self.display = [false; DISPLAY_MAX_X * DISPLAY_MAX_Y];
+        let planes = self.selected_planes;
+        for pixel in self.display.iter_mut() {
+            *pixel &= !planes;
+        }
+    }
+
+    //00Cn - SCD nibble
+    fn scroll_down(&mut self, n: u8) {
+        self.scroll(0, n as isize);
+    }
+
+    //00Dn - SCU nibble
+    fn scroll_up(&mut self, n: u8) {
+        self.scroll(0, -(n as isize));
+    }
+
+    //00FB - SCR
+    fn scroll_right(&mut self) {
+        self.scroll(SCROLL_PIXELS as isize, 0);
+    }
+
+    //00FC - SCL
+    fn scroll_left(&mut self) {
+        self.scroll(-(SCROLL_PIXELS as isize), 0);
+    }
+
+    //moves the selected planes by dx, dy
+    //pixels pushed off the edge are lost, blank pixels come in on the other side
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let width = self.display_width() as isize;
+        let height = self.display_height() as isize;
+        let planes = self.selected_planes;
+        let old = self.display.clone();
+        for y in 0..height {
+            for x in 0..width {
+                let (src_x, src_y) = (x - dx, y - dy);
+                let moved = if (0..width).contains(&src_x) && (0..height).contains(&src_y) {
+                    old[(src_y * width + src_x) as usize] & planes
+                } else {
+                    0
+                };
+                let idx = (y * width + x) as usize;
+                self.display[idx] = (self.display[idx] & !planes) | moved;
+            }
+        }
+    }
+
+    //00FE - LOW, 00FF - HIGH
+    //switching resolution clears the screen
+    fn set_resolution(&mut self, hires: bool) {
+        self.hires = hires;
+        self.display = vec![0; self.display_width() * self.display_height()];
+    }
+
+    //F000 NNNN - LD I, long addr
+    fn set_i_register_long(&mut self) {
+        //the address is the next word, fetching it moves pc past it
+        self.i_register = self.fetch();
+    }
+
+    //Fn01 - PLANE n
+    fn select_planes(&mut self, n: u8) {
+        self.selected_planes = n & 0b11;
+    }
+
+    //5xy2 - SAVE Vx - Vy
+    //I is not changed, x can be greater than y to store in reverse order
+    fn load_registers_vx_to_vy_into_memory_at_i(&mut self, x: u8, y: u8) {
+        let i = self.i_register as usize;
+        for (offset, r) in register_range(x, y).enumerate() {
+            self.write_memory(i + offset, self.registers[r]);
+        }
+    }
+
+    //5xy3 - LOAD Vx - Vy
+    fn fill_registers_vx_to_vy_from_memory_at_i(&mut self, x: u8, y: u8) {
+        let i = self.i_register as usize;
+        for (offset, r) in register_range(x, y).enumerate() {
+            self.registers[r] = self.read_memory(i + offset);
+        }
+    }
+
+    //F002 - AUDIO
+    fn load_audio_pattern_from_i(&mut self) {
+        let i = self.i_register as usize;
+        for offset in 0..AUDIO_PATTERN_SIZE {
+            self.audio_pattern[offset] = self.read_memory(i + offset);
+        }
+    }
+
+    //Fx3A - PITCH Vx
+    fn set_pitch_from_vx(&mut self, x: u8) {
+        self.pitch = self.registers[x as usize];
+    }
+
+    //skips are 4 bytes when stepping over F000 NNNN
+    fn skip_next_instruction(&mut self) {
+        let p = self.position_in_memory;
+        let next = (self.memory[p] as u16) << 8 | self.memory[(p + 1) % RAM] as u16;
+        let size = if next == LONG_I_OPCODE { 4 } else { 2 };
+        self.position_in_memory = (p + size) % RAM;
     }
 
     //Fx29
@@ -512,6 +1282,24 @@ impl Chip8 {
         self.i_register = FONTSET_ADDR as u16 + vx as u16 * 5;
     }
 
+    //Fx30 - LD HF, Vx
+    fn set_big_font_address_for_value_in_vx(&mut self, x: u8) {
+        let vx = self.registers[x as usize] & 0xF;
+        self.i_register = BIG_FONTSET_ADDR as u16 + vx as u16 * 10;
+    }
+
+    //Fx75 - LD R, Vx
+    fn store_registers_v0_to_vx_in_rpl_flags(&mut self, x: u8) {
+        let count = x as usize + 1;
+        self.rpl_flags[..count].copy_from_slice(&self.registers[..count]);
+    }
+
+    //Fx85 - LD Vx, R
+    fn load_registers_v0_to_vx_from_rpl_flags(&mut self, x: u8) {
+        let count = x as usize + 1;
+        self.registers[..count].copy_from_slice(&self.rpl_flags[..count]);
+    }
+
     fn set_vf(&mut self, set_to_one: bool) {
         if set_to_one {
             self.registers[0xF] = 1;
@@ -521,6 +1309,12 @@ impl Chip8 {
     }
 }
 
+//registers from x to y inclusive, counting down when x is greater than y
+fn register_range(x: u8, y: u8) -> impl Iterator<Item = usize> {
+    let (x, y) = (x as usize, y as usize);
+    (0..=x.abs_diff(y)).map(move |offset| if x <= y { x + offset } else { x - offset })
+}
+
 #[cfg(test)]
 mod tests {
     use std::{
@@ -530,23 +1324,25 @@ mod tests {
     };
 
     use super::*;
+    use crate::trace::tests::Shared;
+    use crate::trace::TraceFormat;
 
     #[test]
     fn test_tick() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
 
         //test load jump command and execute.
         //jump to 400
         chip.memory[0x300] = 0x14;
         chip.memory[0x301] = 0x00;
         chip.position_in_memory = 0x300;
-        chip.tick();
+        chip.tick().unwrap();
         assert_eq!(0x400, chip.position_in_memory);
     }
 
     #[test]
     fn test_tick_timers() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
 
         chip.delay_timer_register = 2;
         chip.sound_timer_register = 1;
@@ -564,7 +1360,7 @@ mod tests {
 
     #[test]
     fn test_fetch() {
-        let mut chip8 = Chip8::new();
+        let mut chip8 = Chip8::new(Quirks::default());
         chip8.memory[START_ADDR] = 0x12;
         chip8.memory[START_ADDR + 1] = 0x34;
         assert_eq!(chip8.fetch(), 0x1234);
@@ -573,50 +1369,239 @@ mod tests {
 
     #[test]
     fn test_call() {
-        let mut chip8 = Chip8::new();
+        let mut chip8 = Chip8::new(Quirks::default());
         chip8.position_in_memory = 0x200;
         chip8.stack_pointer = 0;
-        chip8.call(0x300);
+        chip8.call(0x300).unwrap();
         assert_eq!(chip8.stack_pointer, 1);
         assert_eq!(chip8.stack[0], 0x200);
         assert_eq!(chip8.position_in_memory, 0x300);
     }
 
     #[test]
-    #[should_panic(expected = "Stack overflow")]
     fn test_call_overflow() {
-        let mut chip8 = Chip8::new();
+        let mut chip8 = Chip8::new(Quirks::default());
+        chip8.memory[0x200] = 0x23;
+        chip8.memory[0x201] = 0x00;
         chip8.stack_pointer = 16;
-        chip8.call(0x300);
+        assert_eq!(
+            chip8.tick(),
+            Err(ChipError::StackOverflow {
+                pc: 0x200,
+                opcode: 0x2300
+            })
+        );
     }
 
     #[test]
     fn test_ret() {
-        let mut chip8 = Chip8::new();
+        let mut chip8 = Chip8::new(Quirks::default());
         chip8.stack_pointer = 1;
         chip8.stack[0] = 0x300;
-        chip8.ret();
+        chip8.ret().unwrap();
         assert_eq!(chip8.stack_pointer, 0);
         assert_eq!(chip8.position_in_memory, 0x300);
     }
 
     #[test]
-    #[should_panic(expected = "Stack underflow")]
     fn test_ret_underflow() {
-        let mut chip8 = Chip8::new();
-        chip8.ret();
+        let mut chip8 = Chip8::new(Quirks::default());
+        chip8.memory[0x200] = 0x00;
+        chip8.memory[0x201] = 0xEE;
+        assert_eq!(
+            chip8.tick(),
+            Err(ChipError::StackUnderflow {
+                pc: 0x200,
+                opcode: 0x00EE
+            })
+        );
+    }
+
+    #[test]
+    fn test_unknown_opcode() {
+        let mut chip8 = Chip8::new(Quirks::default());
+        chip8.memory[0x200] = 0xFF;
+        chip8.memory[0x201] = 0xFF;
+        assert_eq!(
+            chip8.tick(),
+            Err(ChipError::UnknownOpcode {
+                pc: 0x200,
+                opcode: 0xFFFF
+            })
+        );
+    }
+
+    #[test]
+    fn test_sys_opcode_not_supported() {
+        let mut chip8 = Chip8::new(Quirks::default());
+        chip8.memory[0x200] = 0x01;
+        chip8.memory[0x201] = 0x23;
+        assert_eq!(
+            chip8.tick(),
+            Err(ChipError::SysOpcodeNotSupported {
+                pc: 0x200,
+                opcode: 0x0123
+            })
+        );
+    }
+
+    #[test]
+    fn test_invalid_key() {
+        let mut chip8 = Chip8::new(Quirks::default());
+        assert_eq!(chip8.key_press(0x10), Err(ChipError::InvalidKey(0x10)));
+        assert_eq!(chip8.key_release(0x10), Err(ChipError::InvalidKey(0x10)));
+    }
+
+    #[test]
+    fn test_load_store_increment() {
+        //I = 0x300, store V0-V2
+        let rom = [0xA3, 0x00, 0xF2, 0x55];
+        for (quirks, i) in [
+            (Quirks::COSMAC_VIP, 0x303),
+            (Quirks::CHIP_48, 0x302),
+            (Quirks::SUPER_CHIP, 0x300),
+        ] {
+            let mut chip8 = Chip8::new(quirks);
+            chip8.load_rom(&rom).unwrap();
+            chip8.tick().unwrap();
+            chip8.tick().unwrap();
+            assert_eq!(chip8.i_register, i);
+        }
+    }
+
+    #[test]
+    fn test_load_store_wraps_i() {
+        let mut chip8 = Chip8::new(Quirks::XO_CHIP);
+        //I = 0xFFFF, store V0-V1, I = 0xFFFF, load V0-V2
+        chip8
+            .load_rom(&[
+                0x60, 0x0A, 0x61, 0x0B, 0xF0, 0x00, 0xFF, 0xFF, 0xF1, 0x55, 0xF0, 0x00, 0xFF, 0xFF,
+                0xF2, 0x65,
+            ])
+            .unwrap();
+        for _ in 0..4 {
+            chip8.tick().unwrap();
+        }
+        assert_eq!([0x0A, 0x0B], [chip8.memory[0xFFFF], chip8.memory[0]]);
+        assert_eq!(chip8.i_register, 1);
+        chip8.tick().unwrap();
+        chip8.tick().unwrap();
+        assert_eq!([0x0A, 0x0B, 0], chip8.registers[..3]);
+        assert_eq!(chip8.i_register, 2);
+    }
+
+    #[test]
+    fn test_pc_wraps() {
+        let mut chip8 = Chip8::new(Quirks::default());
+        //LD V0, 0 all the way to the end of ram
+        let rom: Vec<u8> = [0x60, 0x00].repeat(MAX_ROM_SIZE / 2);
+        chip8.load_rom(&rom).unwrap();
+        for _ in 0..rom.len() / 2 {
+            chip8.tick().unwrap();
+        }
+        assert_eq!(chip8.position_in_memory, 0);
+
+        //SE V0, 0 at 0xFFFC skips the long I load at 0xFFFE
+        chip8.memory[0xFFFC..].copy_from_slice(&[0x30, 0x00, 0xF0, 0x00]);
+        chip8.position_in_memory = 0xFFFC;
+        chip8.tick().unwrap();
+        assert_eq!(chip8.position_in_memory, 2);
+
+        //Fx0A at the end of ram waits there
+        chip8.memory[0xFFFE..].copy_from_slice(&[0xF0, 0x0A]);
+        chip8.position_in_memory = 0xFFFE;
+        chip8.tick().unwrap();
+        assert_eq!(chip8.position_in_memory, 0xFFFE);
+    }
+
+    #[test]
+    fn test_skip_key_out_of_range() {
+        let mut chip8 = Chip8::new(Quirks::default());
+        //V0 = 0x20, SKP V0, then SKNP V0
+        chip8
+            .load_rom(&[0x60, 0x20, 0xE0, 0x9E, 0xE0, 0xA1])
+            .unwrap();
+        chip8.tick().unwrap();
+        chip8.tick().unwrap();
+        assert_eq!(chip8.position_in_memory, 0x204);
+        chip8.tick().unwrap();
+        assert_eq!(chip8.position_in_memory, 0x208);
+
+        //0x20 is key 0
+        chip8.restart();
+        chip8.key_press(0).unwrap();
+        chip8.tick().unwrap();
+        chip8.tick().unwrap();
+        assert_eq!(chip8.position_in_memory, 0x206);
+    }
+
+    #[test]
+    fn test_load_rom_too_large() {
+        let mut chip8 = Chip8::new(Quirks::default());
+        let rom = vec![0; MAX_ROM_SIZE + 1];
+        assert_eq!(
+            chip8.load_rom(&rom),
+            Err(ChipError::RomTooLarge {
+                size: MAX_ROM_SIZE + 1,
+                max: MAX_ROM_SIZE
+            })
+        );
+        assert_eq!(
+            chip8.load_rom_at(&rom[..MAX_ROM_SIZE], ETI_660_LOAD_ADDR),
+            Err(ChipError::RomTooLarge {
+                size: MAX_ROM_SIZE,
+                max: RAM - 0x600
+            })
+        );
+        assert!(chip8.load_rom(&rom[..MAX_ROM_SIZE]).is_ok());
+        assert_eq!(chip8.load_rom(&[]), Err(ChipError::EmptyRom));
+        assert_eq!(
+            chip8.load_rom_at(&[0x00, 0xE0], 0x1FE),
+            Err(ChipError::InvalidLoadAddress(0x1FE))
+        );
+    }
+
+    #[test]
+    fn test_load_rom_at() {
+        let mut chip8 = Chip8::new(Quirks::default());
+        //6005 then jump to itself
+        chip8
+            .load_rom_from(&[0x60, 0x05, 0x16, 0x02][..], ETI_660_LOAD_ADDR)
+            .unwrap();
+        assert_eq!(chip8.position_in_memory, 0x600);
+        assert_eq!(chip8.memory[0x200], 0);
+        chip8.tick().unwrap();
+        chip8.tick().unwrap();
+        assert_eq!(chip8.registers[0], 5);
+        assert_eq!(chip8.position_in_memory, 0x602);
+
+        let state = chip8.save_state();
+        chip8.load_state(&state).unwrap();
+        chip8.restart();
+        assert_eq!(chip8.position_in_memory, 0x600);
+        chip8.reset();
+        assert_eq!(chip8.position_in_memory, 0x600);
+
+        assert!(matches!(
+            chip8.load_rom_file("/nonexistent/rom.ch8", DEFAULT_LOAD_ADDR),
+            Err(RomError::Io(_))
+        ));
+        assert!(matches!(
+            chip8.load_rom_from(&[][..], DEFAULT_LOAD_ADDR),
+            Err(RomError::Chip(ChipError::EmptyRom))
+        ));
     }
 
     #[test]
     fn test_jump() {
-        let mut chip8 = Chip8::new();
+        let mut chip8 = Chip8::new(Quirks::default());
         chip8.jump(0x300);
         assert_eq!(chip8.position_in_memory, 0x300);
     }
 
     #[test]
     fn test_jump_plus_v0() {
-        let mut chip8 = Chip8::new();
+        let mut chip8 = Chip8::new(Quirks::default());
         chip8.registers[0] = 0x21;
         chip8.jump_plus_v0(0x300);
         assert_eq!(chip8.position_in_memory, 0x321);
@@ -625,7 +1610,7 @@ mod tests {
     #[test]
     fn test_skip_if_equal_at_x() {
         //position in memory is 0 on init
-        let mut chip8 = Chip8::new();
+        let mut chip8 = Chip8::new(Quirks::default());
         chip8.registers[0] = 0x12;
         //don't call tick, call method manually
         chip8.skip_if_equal_at_x(0, 0x12);
@@ -636,7 +1621,7 @@ mod tests {
     #[test]
     fn test_skip_if_not_equal_at_x() {
         //position in memory is 0 on init
-        let mut chip8 = Chip8::new();
+        let mut chip8 = Chip8::new(Quirks::default());
         chip8.registers[0] = 0x12;
         //don't call tick, call method manually
         chip8.skip_if_not_equal_at_x(0, 0x13);
@@ -647,7 +1632,7 @@ mod tests {
     #[test]
     fn test_skip_if_both_values_are_equal() {
         //position in memory is 0 on init
-        let mut chip8 = Chip8::new();
+        let mut chip8 = Chip8::new(Quirks::default());
         chip8.registers[0] = 0x12;
         chip8.registers[1] = 0x12;
         //don't call tick, call method manually
@@ -658,14 +1643,14 @@ mod tests {
 
     #[test]
     fn test_load_value_to_register() {
-        let mut chip8 = Chip8::new();
+        let mut chip8 = Chip8::new(Quirks::default());
         chip8.load_value_to_register(0, 0x12);
         assert_eq!(chip8.registers[0], 0x12);
     }
 
     #[test]
     fn test_add_to_value_in_register() {
-        let mut chip8 = Chip8::new();
+        let mut chip8 = Chip8::new(Quirks::default());
         chip8.registers[0] = 0x12;
         chip8.add_to_value_in_register(0, 0x12);
         assert_eq!(chip8.registers[0], 0x24);
@@ -673,7 +1658,7 @@ mod tests {
 
     #[test]
     fn test_load_y_into_x() {
-        let mut chip8 = Chip8::new();
+        let mut chip8 = Chip8::new(Quirks::default());
         chip8.registers[1] = 0x12;
         chip8.load_y_into_x(0, 1);
         assert_eq!(chip8.registers[0], 0x12);
@@ -681,7 +1666,7 @@ mod tests {
 
     #[test]
     fn test_bitwise_or_xy() {
-        let mut chip8 = Chip8::new();
+        let mut chip8 = Chip8::new(Quirks::default());
         chip8.registers[0] = 0b1010;
         chip8.registers[1] = 0b1100;
         chip8.bitwise_or_xy(0, 1);
@@ -690,7 +1675,7 @@ mod tests {
 
     #[test]
     fn test_bitwise_and_xy() {
-        let mut chip8 = Chip8::new();
+        let mut chip8 = Chip8::new(Quirks::default());
         chip8.registers[0] = 0b1010;
         chip8.registers[1] = 0b1100;
         chip8.bitwise_and_xy(0, 1);
@@ -699,7 +1684,7 @@ mod tests {
 
     #[test]
     fn test_bitwise_xor_xy() {
-        let mut chip8 = Chip8::new();
+        let mut chip8 = Chip8::new(Quirks::default());
         chip8.registers[0] = 0b1010;
         chip8.registers[1] = 0b1100;
         chip8.bitwise_xor_xy(0, 1);
@@ -708,7 +1693,7 @@ mod tests {
 
     #[test]
     fn test_add_xy() {
-        let mut chip8 = Chip8::new();
+        let mut chip8 = Chip8::new(Quirks::default());
         chip8.registers[0] = 5;
         chip8.registers[1] = 10;
         chip8.add_xy(0, 1);
@@ -718,7 +1703,7 @@ mod tests {
 
     #[test]
     fn test_add_xy_no_overflow() {
-        let mut chip8 = Chip8::new();
+        let mut chip8 = Chip8::new(Quirks::default());
         chip8.registers[0] = 5;
         chip8.registers[1] = 10;
         chip8.add_xy(0, 1);
@@ -728,7 +1713,7 @@ mod tests {
 
     #[test]
     fn test_add_xy_with_overflow() {
-        let mut chip8 = Chip8::new();
+        let mut chip8 = Chip8::new(Quirks::default());
         chip8.registers[0] = 200;
         chip8.registers[1] = 100;
         chip8.add_xy(0, 1);
@@ -738,7 +1723,7 @@ mod tests {
 
     #[test]
     fn test_sub_y_from_x_no_borrow() {
-        let mut chip8 = Chip8::new();
+        let mut chip8 = Chip8::new(Quirks::default());
         chip8.registers[0] = 10;
         chip8.registers[1] = 5;
         chip8.sub_y_from_x(0, 1);
@@ -748,7 +1733,7 @@ mod tests {
 
     #[test]
     fn test_sub_y_from_x_with_borrow() {
-        let mut chip8 = Chip8::new();
+        let mut chip8 = Chip8::new(Quirks::default());
         chip8.registers[0] = 5;
         chip8.registers[1] = 10;
         chip8.sub_y_from_x(0, 1);
@@ -758,7 +1743,7 @@ mod tests {
 
     #[test]
     fn test_shift_right_lsb_1() {
-        let mut chip8 = Chip8::new();
+        let mut chip8 = Chip8::new(Quirks::default());
         chip8.registers[1] = 0b00001001;
         chip8.shift_right(0, 1);
         assert_eq!(1, chip8.registers[0xF]);
@@ -767,7 +1752,7 @@ mod tests {
 
     #[test]
     fn test_shift_right_lsb_0() {
-        let mut chip8 = Chip8::new();
+        let mut chip8 = Chip8::new(Quirks::default());
         chip8.registers[1] = 0b00000110;
         chip8.shift_right(0, 1);
         assert_eq!(0, chip8.registers[0xF]);
@@ -776,7 +1761,7 @@ mod tests {
 
     #[test]
     fn test_sub_x_from_y_no_borrow() {
-        let mut chip8 = Chip8::new();
+        let mut chip8 = Chip8::new(Quirks::default());
         chip8.registers[0] = 5;
         chip8.registers[1] = 10;
         chip8.sub_x_from_y(0, 1);
@@ -786,7 +1771,7 @@ mod tests {
 
     #[test]
     fn test_sub_x_from_y_with_borrow() {
-        let mut chip8 = Chip8::new();
+        let mut chip8 = Chip8::new(Quirks::default());
         chip8.registers[0] = 10;
         chip8.registers[1] = 5;
         chip8.sub_x_from_y(0, 1);
@@ -794,9 +1779,31 @@ mod tests {
         assert_eq!(chip8.registers[0xF], 0);
     }
 
+    #[test]
+    fn test_sub_opcodes() {
+        let mut chip8 = Chip8::new(Quirks::default());
+        //V0 = 5, V1 = 3, SUB V0, V1, SUBN V1, V0
+        chip8
+            .load_rom(&[0x60, 0x05, 0x61, 0x03, 0x80, 0x15, 0x81, 0x07])
+            .unwrap();
+        for _ in 0..3 {
+            chip8.tick().unwrap();
+        }
+        assert_eq!(
+            [2, 3, 1],
+            [chip8.registers[0], chip8.registers[1], chip8.registers[0xF]]
+        );
+        chip8.tick().unwrap();
+        //2 - 3 borrows
+        assert_eq!(
+            [2, 0xFF, 0],
+            [chip8.registers[0], chip8.registers[1], chip8.registers[0xF]]
+        );
+    }
+
     #[test]
     fn test_shift_left_msb_1() {
-        let mut chip8 = Chip8::new();
+        let mut chip8 = Chip8::new(Quirks::default());
         chip8.registers[1] = 0b10010000;
         chip8.shift_left(0, 1);
         assert_eq!(1, chip8.registers[0xF]);
@@ -805,17 +1812,103 @@ mod tests {
 
     #[test]
     fn test_shift_left_msb_0() {
-        let mut chip8 = Chip8::new();
+        let mut chip8 = Chip8::new(Quirks::default());
         chip8.registers[1] = 0b01100000;
         chip8.shift_left(0, 1);
         assert_eq!(0, chip8.registers[0xF]);
         assert_eq!(0b11000000, chip8.registers[0]);
     }
 
+    #[test]
+    fn test_shift_quirk_uses_vx() {
+        let mut chip8 = Chip8::new(Quirks::CHIP_48);
+        chip8.registers[0] = 0b00000011;
+        chip8.registers[1] = 0b10000000;
+        chip8.shift_right(0, 1);
+        assert_eq!(0b00000001, chip8.registers[0]);
+        assert_eq!(1, chip8.registers[0xF]);
+    }
+
+    #[test]
+    fn test_vf_reset_quirk() {
+        let mut chip8 = Chip8::new(Quirks::COSMAC_VIP);
+        chip8.registers[0xF] = 1;
+        chip8.bitwise_or_xy(0, 1);
+        assert_eq!(0, chip8.registers[0xF]);
+
+        let mut chip8 = Chip8::new(Quirks::CHIP_48);
+        chip8.registers[0xF] = 1;
+        chip8.bitwise_or_xy(0, 1);
+        assert_eq!(1, chip8.registers[0xF]);
+    }
+
+    #[test]
+    fn test_jump_quirk_uses_vx() {
+        let mut chip8 = Chip8::new(Quirks::SUPER_CHIP);
+        chip8.registers[0] = 0x10;
+        chip8.registers[3] = 0x21;
+        chip8.jump_plus_v0(0x300);
+        assert_eq!(chip8.position_in_memory, 0x321);
+    }
+
+    #[test]
+    fn test_memory_quirk_leaves_i() {
+        let mut chip = Chip8::new(Quirks::SUPER_CHIP);
+        chip.i_register = 0x300;
+        chip.load_registers_v0_to_vx_into_memory_at_i(0x3);
+        assert_eq!(0x300, chip.i_register);
+        chip.fill_registers_v0_to_vx_from_memory_at_i(0x3);
+        assert_eq!(0x300, chip.i_register);
+    }
+
+    #[test]
+    fn test_draw_clipping_and_wrapping() {
+        let mut clipped = Chip8::new(Quirks::COSMAC_VIP);
+        let mut wrapped = Chip8::new(Quirks::XO_CHIP);
+
+        for chip in [&mut clipped, &mut wrapped] {
+            //font 0 is 4 pixels wide, start 2 pixels from the bottom right corner
+            chip.i_register = FONTSET_ADDR as u16;
+            chip.registers[0] = DISPLAY_MAX_X as u8 - 2;
+            chip.registers[1] = DISPLAY_MAX_Y as u8 - 2;
+            chip.draw(0, 1, 5);
+        }
+
+        //top left corner only gets the wrapped part of the sprite
+        assert_eq!(0, clipped.display[1]);
+        assert_eq!(1, wrapped.display[1]);
+        assert_eq!(
+            1,
+            clipped.display[(DISPLAY_MAX_Y - 2) * DISPLAY_MAX_X + DISPLAY_MAX_X - 2]
+        );
+    }
+
+    #[test]
+    fn test_display_wait_quirk() {
+        let mut chip = Chip8::new(Quirks::COSMAC_VIP);
+        //D005 twice
+        chip.memory[0x200..0x204].copy_from_slice(&[0xD0, 0x05, 0xD0, 0x05]);
+        chip.i_register = FONTSET_ADDR as u16;
+
+        //no frame has started yet, so draw waits
+        chip.tick().unwrap();
+        assert_eq!(0x200, chip.position_in_memory);
+        assert!(!chip.display.iter().any(|p| *p != 0));
+
+        chip.tick_timers();
+        chip.tick().unwrap();
+        assert_eq!(0x202, chip.position_in_memory);
+        assert!(chip.display.iter().any(|p| *p != 0));
+
+        //second draw waits for another frame
+        chip.tick().unwrap();
+        assert_eq!(0x202, chip.position_in_memory);
+    }
+
     #[test]
     fn test_skip_if_both_values_are_not_equal() {
         //position in memory is 0 on init
-        let mut chip8 = Chip8::new();
+        let mut chip8 = Chip8::new(Quirks::default());
         chip8.registers[0] = 0x12;
         chip8.registers[1] = 0x13;
         //don't call tick, call method manually
@@ -826,14 +1919,14 @@ mod tests {
 
     #[test]
     fn test_set_i_register() {
-        let mut chip8 = Chip8::new();
+        let mut chip8 = Chip8::new(Quirks::default());
         chip8.set_i_register(1);
         assert_eq!(1, chip8.i_register);
     }
 
     #[test]
     fn test_load_delay_timer_to_vx() {
-        let mut chip8 = Chip8::new();
+        let mut chip8 = Chip8::new(Quirks::default());
         chip8.delay_timer_register = 1;
         assert_eq!(0, chip8.registers[0]);
         chip8.load_delay_timer_to_vx(0);
@@ -842,15 +1935,25 @@ mod tests {
 
     #[test]
     fn test_set_delay_timer() {
-        let mut chip8 = Chip8::new();
+        let mut chip8 = Chip8::new(Quirks::default());
         chip8.registers[0] = 1;
         chip8.set_delay_timer(0);
         assert_eq!(1, chip8.delay_timer_register);
     }
 
+    #[test]
+    fn test_is_sound_active() {
+        let mut chip8 = Chip8::new(Quirks::default());
+        assert!(!chip8.is_sound_active());
+        chip8.sound_timer_register = 1;
+        assert!(chip8.is_sound_active());
+        chip8.tick_timers();
+        assert!(!chip8.is_sound_active());
+    }
+
     #[test]
     fn test_set_sound_timer() {
-        let mut chip8 = Chip8::new();
+        let mut chip8 = Chip8::new(Quirks::default());
         chip8.registers[0] = 1;
         chip8.set_sound_timer(0);
         assert_eq!(1, chip8.sound_timer_register);
@@ -858,7 +1961,7 @@ mod tests {
 
     #[test]
     fn test_add_vx_to_i_register() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.registers[0] = 1;
         chip.i_register = 1;
         assert_eq!(1, chip.i_register);
@@ -868,9 +1971,9 @@ mod tests {
 
     #[test]
     fn test_load_vx_as_decimal_into_memory_at_i() {
-        let mut chip1 = Chip8::new();
-        let mut chip2 = Chip8::new();
-        let mut chip3 = Chip8::new();
+        let mut chip1 = Chip8::new(Quirks::default());
+        let mut chip2 = Chip8::new(Quirks::default());
+        let mut chip3 = Chip8::new(Quirks::default());
 
         chip1.registers[0] = 246;
         chip2.registers[0] = 82;
@@ -899,7 +2002,7 @@ mod tests {
 
     #[test]
     fn test_load_v0_to_vx_into_memory_at_i_all_registers() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.i_register = 0x300;
         for (i, register) in chip.registers.iter_mut().enumerate() {
             *register = i as u8;
@@ -907,11 +2010,9 @@ mod tests {
 
         chip.load_registers_v0_to_vx_into_memory_at_i(0xF);
 
-        let mut memory_counter = 0x300;
         //test all 15 registers
         for i in 0x0..=0xF {
-            assert_eq!(i, chip.memory[memory_counter]);
-            memory_counter += 1;
+            assert_eq!(i, chip.memory[0x300 + i as usize]);
         }
 
         //make sure i is squared away
@@ -920,7 +2021,7 @@ mod tests {
 
     #[test]
     fn test_load_v0_to_vx_into_memory_at_i_some_registers() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.i_register = 0x300;
         chip.registers[0] = 1;
         chip.registers[1] = 2;
@@ -941,7 +2042,7 @@ mod tests {
 
     #[test]
     fn test_fill_registers_v0_to_vx_from_memory_at_i_all_registers() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.i_register = 0x300;
 
         for i in 0x0..=0xF {
@@ -959,7 +2060,7 @@ mod tests {
 
     #[test]
     fn test_fill_registers_v0_to_vx_from_memory_at_i_some_registers() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.i_register = 0x300;
 
         chip.memory[0x300] = 12;
@@ -978,15 +2079,15 @@ mod tests {
 
     #[test]
     fn test_wait_for_key_press_and_store_at_vx() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.position_in_memory = 0x300;
         //press key
-        chip.key_press(0xF);
+        chip.key_press(0xF).unwrap();
         //wait for keypress
         chip.wait_for_keypress_store_vx(0);
         assert_eq!(0xF, chip.registers[0]);
         //release key
-        chip.key_release(0xF);
+        chip.key_release(0xF).unwrap();
 
         //check again no key is pressed
         chip.wait_for_keypress_store_vx(0);
@@ -996,7 +2097,7 @@ mod tests {
 
     #[test]
     fn test_wait_for_key_press_and_store_at_vx_threaded() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         //progam to wait for key and store in V0
         let program = [0xF0, 0x0A];
         //put program in RAM
@@ -1018,12 +2119,12 @@ mod tests {
                 //fetch and execute opcode
                 //if no key is pressed the program counter will move back 2,
                 //to retry until there is a key pressed
-                chip.tick();
+                chip.tick().unwrap();
             }
         });
 
         //press key
-        chip_arc.lock().unwrap().key_press(0xF);
+        chip_arc.lock().unwrap().key_press(0xF).unwrap();
         //wait for thread to wrap up
         thread::sleep(Duration::from_millis(500));
 
@@ -1035,21 +2136,21 @@ mod tests {
 
     #[test]
     fn test_skip_if_key_pressed_at_vx() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
 
         chip.registers[0] = 0xF;
         chip.position_in_memory = 0x300;
 
         //press key in V0
-        chip.key_press(0xF);
+        chip.key_press(0xF).unwrap();
         //run skip operation
         chip.skip_if_key_at_vx_pressed(0);
         //program counter move two places
         assert_eq!(0x302, chip.position_in_memory);
-        chip.key_release(0xF);
+        chip.key_release(0xF).unwrap();
 
         //press wrong key
-        chip.key_press(0xA);
+        chip.key_press(0xA).unwrap();
         //run skip
         chip.skip_if_key_at_vx_pressed(0);
         //should not have moved
@@ -1058,21 +2159,21 @@ mod tests {
 
     #[test]
     fn test_skip_if_key_not_pressed_at_vx() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
 
         chip.registers[0] = 0xF;
         chip.position_in_memory = 0x300;
 
         //press key in V0
-        chip.key_press(0xF);
+        chip.key_press(0xF).unwrap();
         //run skip operation
         chip.skip_if_key_at_vx_not_pressed(0);
         //should not skip
         assert_eq!(0x300, chip.position_in_memory);
-        chip.key_release(0xF);
+        chip.key_release(0xF).unwrap();
 
         //press wrong key
-        chip.key_press(0xA);
+        chip.key_press(0xA).unwrap();
         //run skip
         chip.skip_if_key_at_vx_not_pressed(0);
         //should skip
@@ -1081,7 +2182,7 @@ mod tests {
 
     #[test]
     fn test_clear_screen() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
 
         //font start
         chip.i_register = FONTSET_ADDR as u16;
@@ -1089,31 +2190,577 @@ mod tests {
         //draw first letter in font set 5 bytes at
         chip.draw(0, 0, 5);
 
-        assert!(chip.display.contains(&true));
+        assert!(chip.display.iter().any(|p| *p != 0));
 
         chip.clear_screen();
 
-        assert!(!(chip.display.contains(&true)));
+        assert!(!chip.display.iter().any(|p| *p != 0));
     }
 
     #[test]
     fn test_set_font_address_for_value_in_vx() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
 
-        let mut count = 0;
         for hex in 0x0..=0xF {
             chip.registers[0] = hex;
 
             chip.set_font_address_for_value_in_vx(0);
 
-            assert_eq!(FONTSET_ADDR as u16 + 5 * count, chip.i_register);
-            count += 1;
+            assert_eq!(FONTSET_ADDR as u16 + 5 * hex as u16, chip.i_register);
         }
     }
 
+    #[test]
+    fn test_set_resolution() {
+        let mut chip = Chip8::new(Quirks::SUPER_CHIP);
+        chip.display[0] = 1;
+
+        chip.set_resolution(true);
+        let frame = chip.get_display();
+        assert_eq!((HIRES_MAX_X, HIRES_MAX_Y), (frame.width, frame.height));
+        assert_eq!(HIRES_MAX_X * HIRES_MAX_Y, frame.pixels.len());
+        assert!(!frame.pixels.iter().any(|p| *p != 0));
+
+        chip.set_resolution(false);
+        let frame = chip.get_display();
+        assert_eq!((DISPLAY_MAX_X, DISPLAY_MAX_Y), (frame.width, frame.height));
+    }
+
+    #[test]
+    fn test_draw_big_sprite() {
+        let mut chip = Chip8::new(Quirks::SUPER_CHIP);
+        chip.set_resolution(true);
+        chip.i_register = 0x300;
+        //solid 16x16 block
+        chip.memory[0x300..0x320].fill(0xFF);
+
+        chip.draw(0, 0, 0);
+
+        let lit = chip.display.iter().filter(|p| **p != 0).count();
+        assert_eq!(16 * 16, lit);
+        assert_eq!(1, chip.display[15 * HIRES_MAX_X + 15]);
+        assert_eq!(0, chip.display[16]);
+        assert_eq!(0, chip.registers[0xF]);
+    }
+
+    #[test]
+    fn test_scroll() {
+        let mut chip = Chip8::new(Quirks::SUPER_CHIP);
+        chip.display[0] = 1;
+
+        chip.scroll_down(2);
+        assert_eq!(0, chip.display[0]);
+        assert_eq!(1, chip.display[2 * DISPLAY_MAX_X]);
+
+        chip.scroll_right();
+        assert_eq!(1, chip.display[2 * DISPLAY_MAX_X + 4]);
+
+        chip.scroll_left();
+        assert_eq!(1, chip.display[2 * DISPLAY_MAX_X]);
+
+        //scrolling off the left edge drops the pixel
+        chip.scroll_left();
+        assert!(!chip.display.iter().any(|p| *p != 0));
+    }
+
+    #[test]
+    fn test_exit_halts() {
+        let mut chip = Chip8::new(Quirks::SUPER_CHIP);
+        chip.memory[0x200] = 0x00;
+        chip.memory[0x201] = 0xFD;
+        chip.tick().unwrap();
+        assert!(chip.is_halted());
+
+        //further ticks do nothing
+        chip.tick().unwrap();
+        assert_eq!(0x202, chip.position_in_memory);
+
+        chip.restart();
+        assert!(!chip.is_halted());
+    }
+
+    #[test]
+    fn test_set_big_font_address_for_value_in_vx() {
+        let mut chip = Chip8::new(Quirks::SUPER_CHIP);
+        chip.registers[0] = 0x2;
+        chip.set_big_font_address_for_value_in_vx(0);
+        assert_eq!(BIG_FONTSET_ADDR as u16 + 20, chip.i_register);
+        assert_eq!(BIG_FONTSET[20], chip.memory[chip.i_register as usize]);
+    }
+
+    #[test]
+    fn test_rpl_flags() {
+        let mut chip = Chip8::new(Quirks::SUPER_CHIP);
+        chip.registers[..4].copy_from_slice(&[1, 2, 3, 4]);
+        chip.store_registers_v0_to_vx_in_rpl_flags(2);
+
+        chip.restart();
+        chip.load_registers_v0_to_vx_from_rpl_flags(3);
+        assert_eq!([1, 2, 3, 0], chip.registers[..4]);
+    }
+
+    #[test]
+    fn test_set_i_register_long() {
+        let mut chip = Chip8::new(Quirks::XO_CHIP);
+        //F000 ABCD
+        chip.memory[0x200..0x204].copy_from_slice(&[0xF0, 0x00, 0xAB, 0xCD]);
+        chip.tick().unwrap();
+        assert_eq!(0xABCD, chip.i_register);
+        assert_eq!(0x204, chip.position_in_memory);
+    }
+
+    #[test]
+    fn test_skip_over_long_instruction() {
+        let mut chip = Chip8::new(Quirks::XO_CHIP);
+        chip.memory[0x202..0x206].copy_from_slice(&[0xF0, 0x00, 0xAB, 0xCD]);
+        chip.position_in_memory = 0x202;
+        chip.skip_if_equal_at_x(0, 0);
+        assert_eq!(0x206, chip.position_in_memory);
+    }
+
+    #[test]
+    fn test_draw_planes() {
+        let mut chip = Chip8::new(Quirks::XO_CHIP);
+        chip.i_register = 0x300;
+        //one row for plane 1, one row for plane 2
+        chip.memory[0x300] = 0b11000000;
+        chip.memory[0x301] = 0b10000000;
+
+        chip.select_planes(0b11);
+        chip.draw(0, 0, 1);
+        assert_eq!([3, 1, 0], chip.display[..3]);
+
+        //clearing only plane 1 leaves plane 2
+        chip.select_planes(0b01);
+        chip.clear_screen();
+        assert_eq!([2, 0, 0], chip.display[..3]);
+
+        //no planes selected draws nothing
+        chip.select_planes(0);
+        chip.draw(0, 0, 1);
+        assert_eq!([2, 0, 0], chip.display[..3]);
+    }
+
+    #[test]
+    fn test_register_range_into_memory_and_back() {
+        let mut chip = Chip8::new(Quirks::XO_CHIP);
+        chip.i_register = 0x300;
+        chip.registers[2..5].copy_from_slice(&[7, 8, 9]);
+
+        chip.load_registers_vx_to_vy_into_memory_at_i(2, 4);
+        assert_eq!([7, 8, 9], chip.memory[0x300..0x303]);
+        assert_eq!(0x300, chip.i_register);
+
+        //reversed range fills backwards
+        chip.fill_registers_vx_to_vy_from_memory_at_i(4, 2);
+        assert_eq!([9, 8, 7], chip.registers[2..5]);
+    }
+
+    #[test]
+    fn test_audio_pattern_and_pitch() {
+        let mut chip = Chip8::new(Quirks::XO_CHIP);
+        chip.i_register = 0x300;
+        for i in 0..16 {
+            chip.memory[0x300 + i] = i as u8;
+        }
+        chip.load_audio_pattern_from_i();
+        assert_eq!(chip.memory[0x300..0x310], chip.audio_pattern()[..]);
+
+        assert_eq!(4000.0, chip.playback_rate());
+        chip.registers[1] = 112;
+        chip.set_pitch_from_vx(1);
+        assert_eq!(112, chip.pitch());
+        assert_eq!(8000.0, chip.playback_rate());
+    }
+
+    #[test]
+    fn test_scroll_up_selected_plane() {
+        let mut chip = Chip8::new(Quirks::XO_CHIP);
+        chip.display[DISPLAY_MAX_X] = 3;
+        chip.scroll_up(1);
+        //only plane 1 is selected by default
+        assert_eq!(1, chip.display[0]);
+        assert_eq!(2, chip.display[DISPLAY_MAX_X]);
+    }
+
+    #[test]
+    fn test_save_and_load_state() {
+        let mut chip = Chip8::new(Quirks::XO_CHIP);
+        chip.registers[3] = 0x42;
+        chip.memory[0x300] = 0xAB;
+        chip.memory[RAM - 1] = 0xCD;
+        chip.position_in_memory = 0x246;
+        chip.stack[0] = 0x222;
+        chip.stack_pointer = 1;
+        chip.i_register = 0x1234;
+        chip.delay_timer_register = 9;
+        chip.sound_timer_register = 7;
+        chip.keys[0xA] = true;
+        chip.set_resolution(true);
+        chip.select_planes(3);
+        chip.display[5] = 3;
+        chip.pitch = 100;
+        chip.audio_pattern[2] = 0xF0;
+        chip.rpl_flags[1] = 5;
+
+        let state = chip.save_state();
+        let mut restored = Chip8::new(Quirks::COSMAC_VIP);
+        restored.load_state(&state).unwrap();
+
+        assert_eq!(Quirks::XO_CHIP, restored.quirks());
+        assert_eq!(chip.registers, restored.registers);
+        assert_eq!(chip.memory, restored.memory);
+        assert_eq!(0x246, restored.position_in_memory);
+        assert_eq!(chip.stack, restored.stack);
+        assert_eq!(1, restored.stack_pointer);
+        assert_eq!(0x1234, restored.i_register);
+        assert_eq!(9, restored.delay_timer_register);
+        assert_eq!(7, restored.sound_timer_register);
+        assert_eq!(chip.keys, restored.keys);
+        assert_eq!(chip.get_display(), restored.get_display());
+        assert_eq!(3, restored.selected_planes);
+        assert_eq!(100, restored.pitch());
+        assert_eq!(chip.audio_pattern, restored.audio_pattern);
+        assert_eq!(chip.rpl_flags, restored.rpl_flags);
+        //saving again gives the exact same bytes
+        assert_eq!(state, restored.save_state());
+    }
+
+    #[test]
+    fn test_load_state_rejects_bad_state() {
+        let mut chip = Chip8::new(Quirks::default());
+        chip.registers[0] = 1;
+        let mut state = chip.save_state();
+        let last = state.len() - 1;
+        state[last] ^= 0xFF;
+
+        assert_eq!(
+            Err(ChipError::InvalidState(StateError::ChecksumMismatch)),
+            chip.load_state(&state)
+        );
+        //chip is left alone
+        assert_eq!(1, chip.registers[0]);
+    }
+
+    #[test]
+    fn test_random_is_reproducible() {
+        let mut a = Chip8::with_seed(Quirks::default(), 42);
+        let mut b = Chip8::with_seed(Quirks::default(), 42);
+        for _ in 0..16 {
+            a.random_number_to_x(0, 0xFF);
+            b.random_number_to_x(0, 0xFF);
+            assert_eq!(a.registers[0], b.registers[0]);
+        }
+        assert_eq!(42, a.seed());
+    }
+
+    #[test]
+    fn test_random_restart_reseeds() {
+        let mut chip = Chip8::with_seed(Quirks::default(), 7);
+        chip.random_number_to_x(0, 0xFF);
+        let first = chip.registers[0];
+        chip.random_number_to_x(0, 0xFF);
+        chip.restart();
+        chip.random_number_to_x(0, 0xFF);
+        assert_eq!(first, chip.registers[0]);
+    }
+
+    #[test]
+    fn test_random_survives_save_state() {
+        let mut chip = Chip8::with_seed(Quirks::default(), 7);
+        chip.random_number_to_x(0, 0xFF);
+        let state = chip.save_state();
+        chip.random_number_to_x(0, 0xFF);
+        let expected = chip.registers[0];
+
+        let mut restored = Chip8::with_seed(Quirks::default(), 1);
+        restored.load_state(&state).unwrap();
+        assert_eq!(7, restored.seed());
+        restored.random_number_to_x(0, 0xFF);
+        assert_eq!(expected, restored.registers[0]);
+    }
+
+    //always hands out the same byte
+    #[derive(Debug)]
+    struct FixedSource(u8);
+
+    impl RandomSource for FixedSource {
+        fn next_u8(&mut self) -> u8 {
+            self.0
+        }
+        fn state(&self) -> u64 {
+            0
+        }
+        fn set_state(&mut self, _: u64) {}
+    }
+
+    #[test]
+    fn test_set_random_source() {
+        let mut chip = Chip8::new(Quirks::default());
+        chip.set_random_source(Box::new(FixedSource(0b1010_1010)));
+        chip.random_number_to_x(0, 0x0F);
+        assert_eq!(0b1010, chip.registers[0]);
+    }
+
+    //program for the debugger tests:
+    //200 call 208, 202 add v0 1, 204 jump 202, 208 add v1 1, 20A call 20E, 20C ret, 20E ret
+    fn debug_chip() -> Chip8 {
+        let rom = [
+            0x22, 0x08, 0x70, 0x01, 0x12, 0x02, 0x00, 0x00, 0x71, 0x01, 0x22, 0x0E, 0x00, 0xEE,
+            0x00, 0xEE,
+        ];
+        let mut chip = Chip8::with_seed(Quirks::default(), 0);
+        chip.load_rom(&rom).unwrap();
+        chip
+    }
+
+    #[test]
+    fn test_breakpoint() {
+        let mut chip = debug_chip();
+        chip.add_breakpoint(0x202, None);
+        assert_eq!(
+            Ok(DebugEvent::Breakpoint(0x202)),
+            chip.run_until(StopCondition::Breakpoint, 100)
+        );
+        assert_eq!(1, chip.registers[1]);
+        //continuing runs the instruction under the breakpoint and loops back round to it
+        assert_eq!(
+            Ok(DebugEvent::Breakpoint(0x202)),
+            chip.run_until(StopCondition::Breakpoint, 100)
+        );
+        assert_eq!(1, chip.registers[0]);
+
+        assert!(chip.remove_breakpoint(0x202));
+        assert!(!chip.remove_breakpoint(0x202));
+        assert_eq!(
+            Ok(DebugEvent::InstructionLimit),
+            chip.run_until(StopCondition::Breakpoint, 100)
+        );
+    }
+
+    #[test]
+    fn test_conditional_breakpoint() {
+        let mut chip = debug_chip();
+        chip.add_breakpoint(0x202, Some("V0 == 0x10".parse().unwrap()));
+        assert_eq!(
+            Ok(DebugEvent::Breakpoint(0x202)),
+            chip.run_until(StopCondition::Breakpoint, 1000)
+        );
+        assert_eq!(0x10, chip.register(Register::V(0)));
+    }
+
+    #[test]
+    fn test_step_over_and_out() {
+        let mut chip = debug_chip();
+        //the whole call runs, including the nested one
+        assert_eq!(Ok(DebugEvent::Stopped(0x202)), chip.step_over(100));
+        assert_eq!(1, chip.registers[1]);
+        assert_eq!(0, chip.stack_pointer);
+        //not a call, so a single step
+        assert_eq!(Ok(DebugEvent::Stopped(0x204)), chip.step_over(100));
+
+        chip.restart();
+        assert_eq!(Ok(DebugEvent::Stopped(0x208)), chip.step());
+        assert_eq!(Ok(DebugEvent::Stopped(0x20A)), chip.step());
+        assert_eq!(Ok(DebugEvent::Stopped(0x20E)), chip.step());
+        //out of the nested call, then out of the first one
+        assert_eq!(Ok(DebugEvent::Stopped(0x20C)), chip.step_out(100));
+        assert_eq!(Ok(DebugEvent::Stopped(0x202)), chip.step_out(100));
+    }
+
+    #[test]
+    fn test_step_over_across_calls() {
+        let mut chip = debug_chip();
+        let stop = chip.step_over_condition();
+        //budget runs out inside the call, the same condition picks up where it left off
+        assert_eq!(Ok(DebugEvent::InstructionLimit), chip.run_until(stop, 2));
+        assert_eq!(Ok(DebugEvent::Stopped(0x202)), chip.run_until(stop, 100));
+    }
+
+    #[test]
+    fn test_run_to() {
+        let mut chip = debug_chip();
+        assert_eq!(Ok(DebugEvent::Stopped(0x20C)), chip.run_to(0x20C, 100));
+        //breakpoints still stop it on the way
+        chip.add_breakpoint(0x204, None);
+        assert_eq!(Ok(DebugEvent::Breakpoint(0x204)), chip.run_to(0x200, 100));
+    }
+
+    #[test]
+    fn test_run_until_halt_and_fault() {
+        let mut chip = Chip8::new(Quirks::SUPER_CHIP);
+        chip.load_rom(&[0x00, 0xFD]).unwrap();
+        assert_eq!(
+            Ok(DebugEvent::Halted),
+            chip.run_until(StopCondition::Breakpoint, 10)
+        );
+
+        let mut chip = Chip8::new(Quirks::default());
+        chip.load_rom(&[0x00, 0xEE]).unwrap();
+        assert_eq!(
+            Err(ChipError::StackUnderflow {
+                pc: 0x200,
+                opcode: 0x00EE
+            }),
+            chip.run_until(StopCondition::Breakpoint, 10)
+        );
+    }
+
+    #[test]
+    fn test_breakpoints_survive_load_state() {
+        let mut chip = debug_chip();
+        let state = chip.save_state();
+        chip.add_breakpoint(0x208, None);
+        chip.load_state(&state).unwrap();
+        assert_eq!(1, chip.breakpoints().len());
+        assert_eq!(
+            Ok(DebugEvent::Breakpoint(0x208)),
+            chip.run_until(StopCondition::Breakpoint, 10)
+        );
+    }
+
+    fn watch(raw: &str) -> Watchpoint {
+        raw.parse().unwrap()
+    }
+
+    #[test]
+    fn test_watch_write() {
+        let mut chip = Chip8::with_seed(Quirks::default(), 0);
+        //200 LD I, 300; 202 LD V0, 0x7B; 204 LD B, V0
+        chip.load_rom(&[0xA3, 0x00, 0x60, 0x7B, 0xF0, 0x33])
+            .unwrap();
+        chip.memory[0x301] = 0xAA;
+        chip.add_watchpoint(watch("0x301-0x302"));
+        assert_eq!(
+            Ok(DebugEvent::Watchpoint(WatchHit {
+                access: Access::Write,
+                addr: 0x301,
+                pc: 0x204,
+                opcode: 0xF033,
+                old: 0xAA,
+                new: 2,
+            })),
+            chip.run_until(StopCondition::Breakpoint, 10)
+        );
+        //the whole instruction ran and both watched writes were recorded
+        assert_eq!(2, chip.watch_hits().len());
+        assert_eq!(0x302, chip.watch_hits()[1].addr);
+        assert_eq!([1, 2, 3], chip.memory[0x300..0x303]);
+    }
+
+    #[test]
+    fn test_watch_registers_to_memory() {
+        let mut chip = Chip8::with_seed(Quirks::default(), 0);
+        //200 LD I, 300; 202 LD [I], V2; 204 LD V2, [I]
+        chip.load_rom(&[0xA3, 0x00, 0xF2, 0x55, 0xA3, 0x00, 0xF2, 0x65])
+            .unwrap();
+        chip.registers[..3].copy_from_slice(&[7, 8, 9]);
+        chip.add_watchpoint(watch("0x302,rw"));
+        chip.tick().unwrap();
+        chip.tick().unwrap();
+        assert_eq!(Access::Write, chip.watch_hits()[0].access);
+        assert_eq!((0, 9), (chip.watch_hits()[0].old, chip.watch_hits()[0].new));
+        chip.tick().unwrap();
+        assert!(chip.watch_hits().is_empty());
+        chip.tick().unwrap();
+        assert_eq!(Access::Read, chip.watch_hits()[0].access);
+        assert_eq!(0x206, chip.watch_hits()[0].pc);
+    }
+
+    #[test]
+    fn test_poke_and_stack() {
+        let mut chip = Chip8::with_seed(Quirks::default(), 0);
+        //200 CALL 0x204; 202 (data); 204 CALL 0x208
+        chip.load_rom(&[0x22, 0x04, 0x00, 0x00, 0x22, 0x08])
+            .unwrap();
+        chip.add_watchpoint(watch("0x300"));
+        chip.poke(0x300, 0xAB);
+        assert_eq!(0xAB, chip.memory()[0x300]);
+        assert!(chip.watch_hits().is_empty());
+        assert!(chip.stack().is_empty());
+        chip.tick().unwrap();
+        chip.tick().unwrap();
+        assert_eq!([0x202, 0x206], chip.stack());
+    }
+
+    #[test]
+    fn test_set_register() {
+        let mut chip = Chip8::with_seed(Quirks::default(), 0);
+        chip.set_register(Register::V(0xF), 0x1AB);
+        chip.set_register(Register::Pc, 0x300);
+        chip.set_register(Register::Sp, 40);
+        chip.set_register(Register::DelayTimer, 9);
+        assert_eq!(0xAB, chip.register(Register::V(0xF)));
+        assert_eq!(0x300, chip.register(Register::Pc));
+        assert_eq!(16, chip.register(Register::Sp));
+        assert_eq!(9, chip.register(Register::DelayTimer));
+    }
+
+    #[test]
+    fn test_watch_draw_and_execute() {
+        let mut chip = Chip8::with_seed(Quirks::default(), 0);
+        //200 LD I, 300; 202 DRW V0, V0, 2
+        chip.load_rom(&[0xA3, 0x00, 0xD0, 0x02]).unwrap();
+        chip.add_watchpoint(watch("0x301,r"));
+        chip.add_watchpoint(watch("0x202,x"));
+        //don't wait for vblank
+        chip.tick_timers();
+        assert_eq!(
+            Ok(DebugEvent::Watchpoint(WatchHit {
+                access: Access::Execute,
+                addr: 0x202,
+                pc: 0x202,
+                opcode: 0xD002,
+                old: 0xD0,
+                new: 0xD0,
+            })),
+            chip.run_until(StopCondition::Breakpoint, 10)
+        );
+        assert_eq!(2, chip.watch_hits().len());
+        assert_eq!(Access::Read, chip.watch_hits()[1].access);
+        assert_eq!(0x301, chip.watch_hits()[1].addr);
+
+        assert!(chip.remove_watchpoint(&watch("0x301,r")));
+        assert!(!chip.remove_watchpoint(&watch("0x301,r")));
+    }
+
+    #[test]
+    fn test_tracer() {
+        let output = Shared::default();
+        let mut chip = debug_chip();
+        chip.set_tracer(Tracer::new(output.clone(), TraceFormat::Text));
+        for _ in 0..3 {
+            chip.tick().unwrap();
+        }
+        chip.take_tracer().unwrap().finish().unwrap();
+        //untraced once the tracer is taken
+        chip.tick().unwrap();
+        let text = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            vec![
+                "200: 2208  CALL 0x208               SP 0->1",
+                "208: 7101  ADD V1, 0x01             V1 00->01",
+                "20A: 220E  CALL 0x20E               SP 1->2",
+            ],
+            text.lines().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_tracer_long_i() {
+        let output = Shared::default();
+        let mut chip = Chip8::new(Quirks::XO_CHIP);
+        chip.memory[0x200..0x204].copy_from_slice(&[0xF0, 0x00, 0xAB, 0xCD]);
+        chip.set_tracer(Tracer::new(output.clone(), TraceFormat::Text));
+        chip.tick().unwrap();
+        chip.take_tracer().unwrap().finish().unwrap();
+        let text = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        assert_eq!("200: F000 ABCD  LD I, LONG 0xABCD   I 0000->ABCD\n", text);
+    }
+
     #[test]
     fn test_draw() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
 
         //font start
         chip.i_register = 5 * 0xF + FONTSET_ADDR as u16;
@@ -1124,13 +2771,13 @@ mod tests {
         for y in 0..5 {
             for x in 0..8 {
                 let index = y * DISPLAY_MAX_X + x;
-                if chip.display[index] {
+                if chip.display[index] != 0 {
                     print!("*");
                 } else {
                     print!(" ");
                 }
             }
-            print!("\n");
+            println!();
         }
     }
 }
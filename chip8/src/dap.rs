@@ -0,0 +1,952 @@
+use crate::chip::Chip8;
+use crate::debug::{parse_value, DebugEvent, Register, StopCondition};
+use crate::error::ChipError;
+use crate::monitor::MAX_STEP_OVER;
+use crate::opcode::Opcode;
+use crate::quirks::Quirks;
+use crate::scheduler::{Scheduler, TIMER_HZ};
+use crate::symbols::Symbols;
+use serde_json::{json, Value};
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+//how long a running program waits for requests between frames
+const FRAME_TIME: Duration = Duration::from_nanos(1_000_000_000 / TIMER_HZ as u64);
+const DEFAULT_IPF: u32 = 10;
+//CHIP-8 has one thread of execution
+const THREAD_ID: i64 = 1;
+//variablesReference of each scope, every frame shares them since registers are global
+const REGISTERS: i64 = 1;
+const TIMERS: i64 = 2;
+const STACK: i64 = 3;
+
+//Content-Length: N, a blank line, then N bytes of JSON. None at the end of the input
+pub fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() && length.is_some() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            let value = value.trim().parse().map_err(|_| {
+                io::Error::new(ErrorKind::InvalidData, format!("bad header '{}'", header))
+            })?;
+            length = Some(value);
+        }
+    }
+    let mut body = vec![0; length.unwrap_or(0)];
+    reader.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+pub fn write_message(writer: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+//A Debug Adapter Protocol server for editors. Launch takes the program path plus optional
+//symbols (written by chip8-asm --symbols), quirks, ipf, seed and stopOnEntry. Breakpoints
+//go on addresses, or on source lines when there are symbols. Steps are single instructions,
+//next steps over calls
+pub struct DapServer {
+    chip: Option<Chip8>,
+    symbols: Option<Symbols>,
+    //kept apart because setBreakpoints and setInstructionBreakpoints each replace their own
+    line_breakpoints: Vec<u16>,
+    instruction_breakpoints: Vec<u16>,
+    stop_on_entry: bool,
+    running: bool,
+    scheduler: Scheduler,
+    //messages to send once the current request is handled
+    outbox: Vec<Value>,
+    seq: i64,
+}
+
+impl Default for DapServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DapServer {
+    pub fn new() -> Self {
+        DapServer {
+            chip: None,
+            symbols: None,
+            line_breakpoints: Vec::new(),
+            instruction_breakpoints: Vec::new(),
+            stop_on_entry: false,
+            running: false,
+            scheduler: Scheduler::new(DEFAULT_IPF * TIMER_HZ),
+            outbox: Vec::new(),
+            seq: 0,
+        }
+    }
+
+    //Serves one session until the client disconnects. Requests are read on their own
+    //thread, so a running program can still be paused
+    pub fn serve<R, W>(&mut self, reader: R, mut writer: W) -> io::Result<()>
+    where
+        R: Read + Send + 'static,
+        W: Write,
+    {
+        let (sender, messages) = mpsc::channel();
+        thread::spawn(move || {
+            let mut reader = BufReader::new(reader);
+            loop {
+                let message = read_message(&mut reader);
+                let done = !matches!(message, Ok(Some(_)));
+                if sender.send(message).is_err() || done {
+                    break;
+                }
+            }
+        });
+        loop {
+            let message = if self.running {
+                match messages.recv_timeout(FRAME_TIME) {
+                    Ok(message) => Some(message),
+                    Err(RecvTimeoutError::Timeout) => None,
+                    Err(RecvTimeoutError::Disconnected) => return Ok(()),
+                }
+            } else {
+                match messages.recv() {
+                    Ok(message) => Some(message),
+                    Err(_) => return Ok(()),
+                }
+            };
+            let mut open = true;
+            match message.transpose()? {
+                Some(Some(request)) => open = self.handle(&request),
+                Some(None) => open = false,
+                None => (),
+            }
+            if open && self.running {
+                self.run_frame();
+            }
+            for mut message in self.outbox.drain(..) {
+                self.seq += 1;
+                message["seq"] = json!(self.seq);
+                write_message(&mut writer, &message)?;
+            }
+            if !open {
+                return Ok(());
+            }
+        }
+    }
+
+    //false once the session is over
+    fn handle(&mut self, request: &Value) -> bool {
+        let command = request["command"].as_str().unwrap_or("");
+        let arguments = &request["arguments"];
+        let result = match command {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsInstructionBreakpoints": true,
+                "supportsReadMemoryRequest": true,
+                "supportsDisassembleRequest": true,
+                "supportsTerminateRequest": true,
+            })),
+            "launch" => self.launch(arguments),
+            "setBreakpoints" => self.set_breakpoints(arguments),
+            "setInstructionBreakpoints" => self.set_instruction_breakpoints(arguments),
+            "setExceptionBreakpoints" => Ok(json!({ "breakpoints": [] })),
+            "configurationDone" => Ok(Value::Null),
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "CHIP-8" }] })),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => Ok(json!({ "scopes": [
+                { "name": "Registers", "variablesReference": REGISTERS, "expensive": false },
+                { "name": "Timers", "variablesReference": TIMERS, "expensive": false },
+                { "name": "Stack", "variablesReference": STACK, "expensive": false },
+            ]})),
+            "variables" => self.variables(arguments),
+            "continue" => Ok(json!({ "allThreadsContinued": true })),
+            "next" | "stepIn" | "stepOut" | "pause" => Ok(Value::Null),
+            "readMemory" => self.read_memory(arguments),
+            "disassemble" => self.disassemble(arguments),
+            "disconnect" | "terminate" => Ok(Value::Null),
+            _ => Err(format!("unsupported request '{}'", command)),
+        };
+        let success = result.is_ok();
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": command,
+            "success": success,
+        });
+        match result {
+            Ok(Value::Null) => (),
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.outbox.push(response);
+        if !success {
+            return true;
+        }
+
+        //what happens after the response
+        match command {
+            "launch" => self.event("initialized", Value::Null),
+            "configurationDone" if self.stop_on_entry => self.stopped("entry", None),
+            "configurationDone" if self.at_breakpoint() => self.stopped("breakpoint", None),
+            "configurationDone" | "continue" => self.resume(),
+            "next" | "stepIn" | "stepOut" => self.step(command),
+            "pause" if self.running => {
+                self.running = false;
+                self.stopped("pause", None);
+            }
+            "disconnect" => return false,
+            "terminate" => {
+                self.event("terminated", Value::Null);
+                return false;
+            }
+            _ => (),
+        }
+        true
+    }
+
+    fn event(&mut self, name: &str, body: Value) {
+        let mut event = json!({ "type": "event", "event": name });
+        if !body.is_null() {
+            event["body"] = body;
+        }
+        self.outbox.push(event);
+    }
+
+    fn stopped(&mut self, reason: &str, text: Option<String>) {
+        let mut body = json!({
+            "reason": reason,
+            "threadId": THREAD_ID,
+            "allThreadsStopped": true,
+        });
+        if let Some(text) = text {
+            body["text"] = json!(text);
+        }
+        self.event("stopped", body);
+    }
+
+    //reports how a run or step ended, a step that gave up counts as a step
+    fn report(&mut self, result: Result<DebugEvent, ChipError>) {
+        self.running = false;
+        match result {
+            Ok(DebugEvent::Breakpoint(_)) => self.stopped("breakpoint", None),
+            Ok(DebugEvent::Watchpoint(hit)) => {
+                self.stopped("data breakpoint", Some(hit.to_string()))
+            }
+            Ok(DebugEvent::Halted) => {
+                self.event("exited", json!({ "exitCode": 0 }));
+                self.event("terminated", Value::Null);
+            }
+            Ok(DebugEvent::Stopped(_) | DebugEvent::InstructionLimit) => self.stopped("step", None),
+            Err(e) => {
+                self.event(
+                    "output",
+                    json!({ "category": "stderr", "output": format!("{}\n", e) }),
+                );
+                self.stopped("exception", Some(e.to_string()));
+            }
+        }
+    }
+
+    fn launch(&mut self, arguments: &Value) -> Result<Value, String> {
+        let program = arguments["program"]
+            .as_str()
+            .ok_or("launch needs a program")?;
+        let quirks = match arguments["quirks"].as_str() {
+            Some(name) => name.parse()?,
+            None => Quirks::default(),
+        };
+        let ipf = arguments["ipf"].as_u64().unwrap_or(DEFAULT_IPF as u64);
+        let ipf = u32::try_from(ipf)
+            .ok()
+            .filter(|ipf| *ipf > 0)
+            .ok_or("ipf must be at least 1")?;
+        let mut chip = match arguments["seed"].as_u64() {
+            Some(seed) => Chip8::with_seed(quirks, seed),
+            None => Chip8::new(quirks),
+        };
+        let rom =
+            std::fs::read(program).map_err(|e| format!("Failed to read {}: {}", program, e))?;
+        chip.load_rom(&rom)
+            .map_err(|e| format!("Failed to load {}: {}", program, e))?;
+        if let Some(path) = arguments["symbols"].as_str() {
+            let symbols = std::fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+            let symbols = symbols
+                .parse()
+                .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+            self.symbols = Some(symbols);
+        }
+        self.chip = Some(chip);
+        self.scheduler = Scheduler::new(ipf.saturating_mul(TIMER_HZ));
+        self.stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(false);
+        self.apply_breakpoints();
+        Ok(Value::Null)
+    }
+
+    fn chip(&self) -> Result<&Chip8, String> {
+        self.chip.as_ref().ok_or("no program launched".to_string())
+    }
+
+    fn apply_breakpoints(&mut self) {
+        let Some(chip) = &mut self.chip else {
+            return;
+        };
+        chip.clear_breakpoints();
+        for addr in self
+            .line_breakpoints
+            .iter()
+            .chain(&self.instruction_breakpoints)
+        {
+            chip.add_breakpoint(*addr, None);
+        }
+    }
+
+    //Lines without code move down to the next one that has some. Only the symbol file's
+    //source has lines, breakpoints anywhere else stay unverified
+    fn set_breakpoints(&mut self, arguments: &Value) -> Result<Value, String> {
+        let path = arguments["source"]["path"].as_str().unwrap_or("");
+        let symbols = self
+            .symbols
+            .as_ref()
+            .filter(|symbols| symbols.source.as_deref().is_none_or(|s| same_file(s, path)));
+        let requested = arguments["breakpoints"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        let mut addresses = Vec::new();
+        let mut breakpoints = Vec::new();
+        for breakpoint in requested {
+            let line = breakpoint["line"].as_u64().unwrap_or(0) as usize;
+            match symbols.and_then(|symbols| symbols.line_address(line)) {
+                Some((line, addr)) => {
+                    addresses.push(addr);
+                    breakpoints.push(json!({
+                        "verified": true,
+                        "line": line,
+                        "instructionReference": format!("{:#05X}", addr),
+                    }));
+                }
+                None => breakpoints.push(json!({
+                    "verified": false,
+                    "line": line,
+                    "message": "no code for this line in the symbols",
+                })),
+            }
+        }
+        //each source has its own breakpoints, only ours have addresses to replace
+        if symbols.is_some() {
+            self.line_breakpoints = addresses;
+            self.apply_breakpoints();
+        }
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn set_instruction_breakpoints(&mut self, arguments: &Value) -> Result<Value, String> {
+        let requested = arguments["breakpoints"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        let mut addresses = Vec::new();
+        let mut breakpoints = Vec::new();
+        for breakpoint in requested {
+            let reference = breakpoint["instructionReference"].as_str().unwrap_or("");
+            let offset = breakpoint["offset"].as_i64().unwrap_or(0);
+            let addr = parse_value(reference)
+                .ok()
+                .and_then(|addr| u16::try_from(addr as i64 + offset).ok());
+            match addr {
+                Some(addr) => {
+                    addresses.push(addr);
+                    breakpoints.push(json!({
+                        "verified": true,
+                        "instructionReference": format!("{:#05X}", addr),
+                    }));
+                }
+                None => breakpoints.push(json!({
+                    "verified": false,
+                    "message": format!("invalid address '{}'", reference),
+                })),
+            }
+        }
+        self.instruction_breakpoints = addresses;
+        self.apply_breakpoints();
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    //pc, then the call each return address on the stack came from, innermost first
+    fn stack_trace(&self) -> Result<Value, String> {
+        let chip = self.chip()?;
+        let calls = chip.stack().iter().rev().map(|ret| ret.wrapping_sub(2));
+        let frames: Vec<Value> = std::iter::once(chip.register(Register::Pc))
+            .chain(calls)
+            .enumerate()
+            .map(|(id, addr)| self.frame(id, addr))
+            .collect();
+        Ok(json!({ "stackFrames": frames, "totalFrames": frames.len() }))
+    }
+
+    fn frame(&self, id: usize, addr: u16) -> Value {
+        let name = match self.symbols.as_ref().and_then(|s| s.label_before(addr)) {
+            Some((label, 0)) => label.to_string(),
+            Some((label, offset)) => format!("{}+{:#X}", label, offset),
+            None => format!("{:#05X}", addr),
+        };
+        let mut frame = json!({
+            "id": id,
+            "name": name,
+            "line": 0,
+            "column": 0,
+            "instructionPointerReference": format!("{:#05X}", addr),
+        });
+        let symbols = self.symbols.as_ref();
+        if let (Some(source), Some(line)) = (
+            symbols.and_then(|s| s.source.as_deref()),
+            symbols.and_then(|s| s.address_line(addr)),
+        ) {
+            let name = Path::new(source)
+                .file_name()
+                .map_or(source.into(), |name| name.to_string_lossy());
+            frame["source"] = json!({ "name": name, "path": source });
+            frame["line"] = json!(line);
+            frame["column"] = json!(1);
+        }
+        frame
+    }
+
+    fn variables(&self, arguments: &Value) -> Result<Value, String> {
+        let chip = self.chip()?;
+        let variable = |name: String, value: String| {
+            json!({
+                "name": name,
+                "value": value,
+                "variablesReference": 0,
+            })
+        };
+        let address = |name: &str, addr: u16| {
+            let value = format!("{:#05X}", addr);
+            json!({
+                "name": name,
+                "value": value,
+                "variablesReference": 0,
+                "memoryReference": value,
+            })
+        };
+        let variables: Vec<Value> = match arguments["variablesReference"].as_i64() {
+            Some(REGISTERS) => (0..16)
+                .map(|x| {
+                    let value = chip.register(Register::V(x));
+                    variable(format!("V{:X}", x), format!("{:#04X}", value))
+                })
+                .chain([
+                    address("I", chip.register(Register::I)),
+                    address("PC", chip.register(Register::Pc)),
+                    variable("SP".to_string(), chip.register(Register::Sp).to_string()),
+                ])
+                .collect(),
+            Some(TIMERS) => vec![
+                variable(
+                    "DT".to_string(),
+                    chip.register(Register::DelayTimer).to_string(),
+                ),
+                variable(
+                    "ST".to_string(),
+                    chip.register(Register::SoundTimer).to_string(),
+                ),
+            ],
+            Some(STACK) => chip
+                .stack()
+                .iter()
+                .enumerate()
+                .map(|(depth, ret)| address(&depth.to_string(), *ret))
+                .collect(),
+            _ => return Err("unknown variables reference".to_string()),
+        };
+        Ok(json!({ "variables": variables }))
+    }
+
+    //memoryReference plus offset, count bytes. Reads stop at the end of memory
+    fn read_memory(&self, arguments: &Value) -> Result<Value, String> {
+        let memory = self.chip()?.memory();
+        let start = memory_reference(arguments)?;
+        let count = arguments["count"].as_u64().unwrap_or(0);
+        let Ok(start) = usize::try_from(start).map(|start| start.min(memory.len())) else {
+            return Ok(json!({ "address": format!("{:#05X}", start), "unreadableBytes": count }));
+        };
+        let end = start.saturating_add(count as usize).min(memory.len());
+        Ok(json!({
+            "address": format!("{:#05X}", start),
+            "data": base64(&memory[start..end]),
+            "unreadableBytes": count - (end - start) as u64,
+        }))
+    }
+
+    //two bytes to an instruction from the reference, addresses past memory are invalid
+    fn disassemble(&self, arguments: &Value) -> Result<Value, String> {
+        let chip = self.chip()?;
+        let start =
+            memory_reference(arguments)? + arguments["instructionOffset"].as_i64().unwrap_or(0) * 2;
+        let count = arguments["instructionCount"].as_i64().unwrap_or(0);
+        let instructions: Vec<Value> = (0..count)
+            .map(|i| {
+                let addr = start + i * 2;
+                match u16::try_from(addr) {
+                    Ok(addr) if addr < u16::MAX => {
+                        let opcode = chip.opcode_at(addr);
+                        let mut instruction = json!({
+                            "address": format!("{:#05X}", addr),
+                            "instructionBytes": format!("{:04X}", opcode),
+                            "instruction": Opcode::decode(opcode).to_string(),
+                        });
+                        let symbols = self.symbols.as_ref();
+                        if let Some(line) = symbols.and_then(|s| s.address_line(addr)) {
+                            instruction["line"] = json!(line);
+                        }
+                        instruction
+                    }
+                    _ => json!({
+                        "address": format!("{:#X}", addr),
+                        "instruction": "",
+                        "presentationHint": "invalid",
+                    }),
+                }
+            })
+            .collect();
+        Ok(json!({ "instructions": instructions }))
+    }
+
+    //run_until only checks breakpoints after each instruction, so one on the first
+    //instruction has to be checked before starting
+    fn at_breakpoint(&self) -> bool {
+        self.chip
+            .as_ref()
+            .is_some_and(|chip| chip.is_breakpoint_hit(chip.register(Register::Pc)))
+    }
+
+    fn resume(&mut self) {
+        self.running = true;
+        self.scheduler.reset();
+        self.scheduler.update(Instant::now());
+    }
+
+    fn step(&mut self, command: &str) {
+        let Some(chip) = &mut self.chip else {
+            return;
+        };
+        let stop = match command {
+            "next" => chip.step_over_condition(),
+            "stepOut" => chip.step_out_condition(),
+            _ => StopCondition::Step,
+        };
+        //the timers tick a frame's worth of instructions apart like when running,
+        //otherwise stepping over a wait on the delay timer never gets back
+        let ipf = (self.scheduler.instructions_per_second() / TIMER_HZ).max(1);
+        let mut ran = 0;
+        let result = loop {
+            let result = chip.run_until(stop, ipf);
+            ran += ipf;
+            if !matches!(result, Ok(DebugEvent::InstructionLimit)) || ran >= MAX_STEP_OVER {
+                break result;
+            }
+            chip.tick_timers();
+        };
+        self.report(result);
+    }
+
+    //one 60hz frame of instructions and timers
+    fn run_frame(&mut self) {
+        let Some(chip) = &mut self.chip else {
+            self.running = false;
+            return;
+        };
+        let due = self.scheduler.update(Instant::now());
+        match chip.run_until(StopCondition::Breakpoint, due.instructions) {
+            Ok(DebugEvent::InstructionLimit) => {
+                for _ in 0..due.timer_ticks {
+                    chip.tick_timers();
+                }
+            }
+            result => self.report(result),
+        }
+    }
+}
+
+//memoryReference plus offset, an address that can be out of range
+fn memory_reference(arguments: &Value) -> Result<i64, String> {
+    let reference = arguments["memoryReference"].as_str().unwrap_or("");
+    let addr = parse_value(reference)?;
+    Ok(addr as i64 + arguments["offset"].as_i64().unwrap_or(0))
+}
+
+//editors send absolute paths, the symbols could have a different spelling of the same one
+fn same_file(a: &str, b: &str) -> bool {
+    match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+//readMemory sends its data as base64
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, byte)| {
+            bits | (*byte as u32) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(bits >> (18 - 6 * i)) as usize & 0x3F] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::octo::compile_with_symbols;
+    use std::io::{PipeReader, PipeWriter};
+    use std::path::PathBuf;
+    use std::thread::JoinHandle;
+
+    //A scripted editor, keeps the events that come in while it waits for responses
+    struct Client {
+        reader: BufReader<PipeReader>,
+        writer: PipeWriter,
+        seq: i64,
+        events: Vec<Value>,
+        server: JoinHandle<()>,
+    }
+
+    impl Client {
+        fn start() -> Self {
+            let (server_reader, writer) = io::pipe().unwrap();
+            let (reader, server_writer) = io::pipe().unwrap();
+            let server = thread::spawn(move || {
+                DapServer::new()
+                    .serve(server_reader, server_writer)
+                    .unwrap();
+            });
+            Client {
+                reader: BufReader::new(reader),
+                writer,
+                seq: 0,
+                events: Vec::new(),
+                server,
+            }
+        }
+
+        //the response's body, panics if it failed
+        fn request(&mut self, command: &str, arguments: Value) -> Value {
+            let response = self.try_request(command, arguments);
+            assert_eq!(Some(true), response["success"].as_bool(), "{}", response);
+            response["body"].clone()
+        }
+
+        fn try_request(&mut self, command: &str, arguments: Value) -> Value {
+            self.seq += 1;
+            let request = json!({
+                "seq": self.seq,
+                "type": "request",
+                "command": command,
+                "arguments": arguments,
+            });
+            write_message(&mut self.writer, &request).unwrap();
+            loop {
+                let message = read_message(&mut self.reader).unwrap().unwrap();
+                if message["type"] == "event" {
+                    self.events.push(message);
+                } else {
+                    assert_eq!(json!(self.seq), message["request_seq"]);
+                    return message;
+                }
+            }
+        }
+
+        //the next event with this name
+        fn event(&mut self, name: &str) -> Value {
+            loop {
+                if let Some(i) = self.events.iter().position(|e| e["event"] == name) {
+                    return self.events.remove(i)["body"].clone();
+                }
+                let message = read_message(&mut self.reader).unwrap().unwrap();
+                self.events.push(message);
+            }
+        }
+
+        fn stopped(&mut self) -> String {
+            self.event("stopped")["reason"]
+                .as_str()
+                .unwrap()
+                .to_string()
+        }
+
+        fn variable(&mut self, scope: i64, name: &str) -> String {
+            let body = self.request("variables", json!({ "variablesReference": scope }));
+            let variables = body["variables"].as_array().unwrap();
+            let variable = variables.iter().find(|v| v["name"] == name).unwrap();
+            variable["value"].as_str().unwrap().to_string()
+        }
+
+        fn frames(&mut self) -> Vec<Value> {
+            let body = self.request("stackTrace", json!({ "threadId": THREAD_ID }));
+            body["stackFrames"].as_array().unwrap().clone()
+        }
+
+        fn finish(mut self) {
+            self.request("disconnect", json!({}));
+            drop(self.writer);
+            self.server.join().unwrap();
+        }
+    }
+
+    //a file that's gone once the test is over
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, contents: &[u8]) -> Self {
+            let name = format!("chip8-dap-{}-{}", std::process::id(), name);
+            let path = std::env::temp_dir().join(name);
+            std::fs::write(&path, contents).unwrap();
+            TempFile(path)
+        }
+
+        fn path(&self) -> &str {
+            self.0.to_str().unwrap()
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_message() {
+        let mut written = Vec::new();
+        write_message(&mut written, &json!({ "seq": 1 })).unwrap();
+        assert_eq!(b"Content-Length: 9\r\n\r\n{\"seq\":1}", &written[..]);
+        let mut reader = &written[..];
+        assert_eq!(
+            Some(json!({ "seq": 1 })),
+            read_message(&mut reader).unwrap()
+        );
+        assert_eq!(None, read_message(&mut reader).unwrap());
+        assert_eq!("cAEiCA==", base64(&[0x70, 0x01, 0x22, 0x08]));
+        assert_eq!("AAEC", base64(&[0, 1, 2]));
+    }
+
+    #[test]
+    fn test_addresses() {
+        //200 ADD V0, 1; 202 CALL 0x208; 204 JP 0x200; 208 LD I, 0x300; 20A RET
+        let rom = TempFile::new(
+            "addresses.ch8",
+            &[
+                0x70, 0x01, 0x22, 0x08, 0x12, 0x00, 0x00, 0x00, 0xA3, 0x00, 0x00, 0xEE,
+            ],
+        );
+        let mut client = Client::start();
+        let capabilities = client.request("initialize", json!({ "adapterID": "chip8" }));
+        assert_eq!(json!(true), capabilities["supportsInstructionBreakpoints"]);
+        client.request(
+            "launch",
+            json!({ "program": rom.path(), "stopOnEntry": true }),
+        );
+        client.event("initialized");
+        let body = client.request(
+            "setInstructionBreakpoints",
+            json!({ "breakpoints": [{ "instructionReference": "0x208" }] }),
+        );
+        assert_eq!(json!(true), body["breakpoints"][0]["verified"]);
+        client.request("configurationDone", json!({}));
+        assert_eq!("entry", client.stopped());
+        assert_eq!(json!("0x200"), client.frames()[0]["name"]);
+
+        client.request("continue", json!({ "threadId": THREAD_ID }));
+        assert_eq!("breakpoint", client.stopped());
+        let frames = client.frames();
+        assert_eq!(
+            vec![json!("0x208"), json!("0x202")],
+            frames.iter().map(|f| f["name"].clone()).collect::<Vec<_>>()
+        );
+        assert_eq!("0x01", client.variable(REGISTERS, "V0"));
+        assert_eq!("0x204", client.variable(STACK, "0"));
+        client.request("stepIn", json!({ "threadId": THREAD_ID }));
+        assert_eq!("step", client.stopped());
+        assert_eq!("0x300", client.variable(REGISTERS, "I"));
+        client.request("stepOut", json!({ "threadId": THREAD_ID }));
+        assert_eq!("step", client.stopped());
+        assert_eq!("0x204", client.variable(REGISTERS, "PC"));
+        assert_eq!("0", client.variable(TIMERS, "DT"));
+
+        let body = client.request(
+            "readMemory",
+            json!({ "memoryReference": "0x1FE", "offset": 2, "count": 4 }),
+        );
+        assert_eq!(json!("cAEiCA=="), body["data"]);
+        assert_eq!(json!(0), body["unreadableBytes"]);
+        let body = client.request(
+            "disassemble",
+            json!({ "memoryReference": "0x202", "instructionOffset": -1, "instructionCount": 2 }),
+        );
+        assert_eq!(
+            json!("ADD V0, 0x01"),
+            body["instructions"][0]["instruction"]
+        );
+        assert_eq!(json!("CALL 0x208"), body["instructions"][1]["instruction"]);
+
+        //nothing stops it now but a pause
+        client.request("setInstructionBreakpoints", json!({ "breakpoints": [] }));
+        client.request("continue", json!({ "threadId": THREAD_ID }));
+        client.request("pause", json!({ "threadId": THREAD_ID }));
+        assert_eq!("pause", client.stopped());
+        assert!(
+            !client.try_request("evaluate", json!({ "expression": "V0" }))["success"]
+                .as_bool()
+                .unwrap()
+        );
+        client.finish();
+    }
+
+    #[test]
+    fn test_source_lines() {
+        let source = TempFile::new(
+            "lines.8o",
+            b": sub\n  v1 += 1\n  return\n: main\n  v0 := 1\n  sub\n  loop again\n",
+        );
+        let (rom, mut symbols) =
+            compile_with_symbols(&std::fs::read_to_string(&source.0).unwrap(), 0x200).unwrap();
+        symbols.source = Some(source.path().to_string());
+        let rom = TempFile::new("lines.ch8", &rom);
+        let symbols = TempFile::new("lines.sym", symbols.to_string().as_bytes());
+
+        let mut client = Client::start();
+        client.request("initialize", json!({ "adapterID": "chip8" }));
+        client.request(
+            "launch",
+            json!({ "program": rom.path(), "symbols": symbols.path() }),
+        );
+        client.event("initialized");
+        //lines with only a label move down to their code
+        let body = client.request(
+            "setBreakpoints",
+            json!({
+                "source": { "path": source.path() },
+                "breakpoints": [{ "line": 1 }, { "line": 4 }, { "line": 9 }],
+            }),
+        );
+        assert_eq!(
+            vec![json!([true, 2]), json!([true, 5]), json!([false, 9])],
+            body["breakpoints"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|b| json!([b["verified"], b["line"]]))
+                .collect::<Vec<_>>()
+        );
+        let body = client.request(
+            "setBreakpoints",
+            json!({ "source": { "path": "/elsewhere.8o" }, "breakpoints": [{ "line": 2 }] }),
+        );
+        assert_eq!(json!(false), body["breakpoints"][0]["verified"]);
+        client.request("configurationDone", json!({}));
+
+        assert_eq!("breakpoint", client.stopped());
+        let frame = &client.frames()[0];
+        assert_eq!(
+            (json!("main"), json!(5)),
+            (frame["name"].clone(), frame["line"].clone())
+        );
+        assert_eq!(json!(source.path()), frame["source"]["path"]);
+        client.request("continue", json!({ "threadId": THREAD_ID }));
+        assert_eq!("breakpoint", client.stopped());
+        assert_eq!(
+            vec![json!(["sub", 2]), json!(["main+0x2", 6])],
+            client
+                .frames()
+                .iter()
+                .map(|f| json!([f["name"], f["line"]]))
+                .collect::<Vec<_>>()
+        );
+        client.request("next", json!({ "threadId": THREAD_ID }));
+        assert_eq!("step", client.stopped());
+        assert_eq!(json!(3), client.frames()[0]["line"]);
+        client.request("terminate", json!({}));
+        client.event("terminated");
+        client.server.join().unwrap();
+    }
+
+    #[test]
+    fn test_step_over_timer_wait() {
+        let source = TempFile::new(
+            "wait.8o",
+            b": wait\n  v1 := 30\n  delay := v1\n  loop\n    v1 := delay\n    \
+              if v1 == 0 then return\n  again\n: main\n  wait\n  v0 := 1\n  loop again\n",
+        );
+        let (rom, mut symbols) =
+            compile_with_symbols(&std::fs::read_to_string(&source.0).unwrap(), 0x200).unwrap();
+        symbols.source = Some(source.path().to_string());
+        let rom = TempFile::new("wait.ch8", &rom);
+        let symbols = TempFile::new("wait.sym", symbols.to_string().as_bytes());
+
+        let mut client = Client::start();
+        client.request("initialize", json!({ "adapterID": "chip8" }));
+        client.request(
+            "launch",
+            json!({ "program": rom.path(), "symbols": symbols.path(), "stopOnEntry": true }),
+        );
+        client.event("initialized");
+        client.request("configurationDone", json!({}));
+        assert_eq!("entry", client.stopped());
+        //past the jump to main, then over the call
+        client.request("stepIn", json!({ "threadId": THREAD_ID }));
+        assert_eq!("step", client.stopped());
+        assert_eq!(json!(9), client.frames()[0]["line"]);
+        client.request("next", json!({ "threadId": THREAD_ID }));
+        assert_eq!("step", client.stopped());
+        assert_eq!(json!(10), client.frames()[0]["line"]);
+        assert_eq!("0", client.variable(TIMERS, "DT"));
+        client.finish();
+    }
+
+    #[test]
+    fn test_breakpoint_on_entry() {
+        //main is first, so it's where the program starts
+        let source = TempFile::new("entry.8o", b": main\n  v0 := 1\n  loop again\n");
+        let (rom, mut symbols) =
+            compile_with_symbols(&std::fs::read_to_string(&source.0).unwrap(), 0x200).unwrap();
+        symbols.source = Some(source.path().to_string());
+        let rom = TempFile::new("entry.ch8", &rom);
+        let symbols = TempFile::new("entry.sym", symbols.to_string().as_bytes());
+
+        let mut client = Client::start();
+        client.request("initialize", json!({ "adapterID": "chip8" }));
+        client.request(
+            "launch",
+            json!({ "program": rom.path(), "symbols": symbols.path() }),
+        );
+        client.event("initialized");
+        client.request(
+            "setBreakpoints",
+            json!({ "source": { "path": source.path() }, "breakpoints": [{ "line": 2 }] }),
+        );
+        client.request("configurationDone", json!({}));
+        assert_eq!("breakpoint", client.stopped());
+        assert_eq!("0x200", client.variable(REGISTERS, "PC"));
+        assert_eq!("0x00", client.variable(REGISTERS, "V0"));
+        client.finish();
+    }
+}
@@ -0,0 +1,329 @@
+use std::fmt;
+use std::str::FromStr;
+
+//Anything a breakpoint condition or debugger view can read off the machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    V(u8),
+    I,
+    Pc,
+    Sp,
+    DelayTimer,
+    SoundTimer,
+}
+
+impl FromStr for Register {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let upper = name.to_ascii_uppercase();
+        match upper.as_str() {
+            "I" => Ok(Register::I),
+            "PC" => Ok(Register::Pc),
+            "SP" => Ok(Register::Sp),
+            "DT" => Ok(Register::DelayTimer),
+            "ST" => Ok(Register::SoundTimer),
+            _ => upper
+                .strip_prefix('V')
+                .filter(|x| x.len() == 1)
+                .and_then(|x| u8::from_str_radix(x, 16).ok())
+                .map(Register::V)
+                .ok_or_else(|| format!("unknown register '{}'", name)),
+        }
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Register::V(x) => write!(f, "V{:X}", x),
+            Register::I => write!(f, "I"),
+            Register::Pc => write!(f, "PC"),
+            Register::Sp => write!(f, "SP"),
+            Register::DelayTimer => write!(f, "DT"),
+            Register::SoundTimer => write!(f, "ST"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    //longest first so <= isn't read as <
+    const SYMBOLS: [(&'static str, Comparison); 6] = [
+        ("==", Comparison::Equal),
+        ("!=", Comparison::NotEqual),
+        ("<=", Comparison::LessOrEqual),
+        (">=", Comparison::GreaterOrEqual),
+        ("<", Comparison::Less),
+        (">", Comparison::Greater),
+    ];
+
+    pub fn compare(&self, left: u16, right: u16) -> bool {
+        match self {
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Greater => left > right,
+            Comparison::GreaterOrEqual => left >= right,
+        }
+    }
+
+    fn symbol(&self) -> &'static str {
+        Comparison::SYMBOLS
+            .iter()
+            .find(|(_, comparison)| comparison == self)
+            .map(|(symbol, _)| *symbol)
+            .unwrap()
+    }
+}
+
+//e.g. V3 == 0x10, checked before the instruction at the breakpoint runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Condition {
+    pub register: Register,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let (register, comparison, value) = Comparison::SYMBOLS
+            .iter()
+            .find_map(|(symbol, comparison)| {
+                raw.split_once(symbol)
+                    .map(|(register, value)| (register, *comparison, value))
+            })
+            .ok_or_else(|| format!("expected a comparison like V3 == 0x10, got '{}'", raw))?;
+        Ok(Condition {
+            register: register.trim().parse()?,
+            comparison,
+            value: parse_value(value.trim())?,
+        })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {:#X}",
+            self.register,
+            self.comparison.symbol(),
+            self.value
+        )
+    }
+}
+
+//0x prefixed hex or decimal
+pub fn parse_value(raw: &str) -> Result<u16, String> {
+    let parsed = match raw.strip_prefix("0x").or_else(|| raw.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => raw.parse(),
+    };
+    parsed.map_err(|_| format!("invalid number '{}'", raw))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub addr: u16,
+    //always stops when there is no condition
+    pub condition: Option<Condition>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    //fetched as an instruction
+    Execute,
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Access::Read => write!(f, "read"),
+            Access::Write => write!(f, "write"),
+            Access::Execute => write!(f, "execute"),
+        }
+    }
+}
+
+//Addresses start to end inclusive, watched for any of the enabled accesses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16,
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+impl Watchpoint {
+    pub fn matches(&self, access: Access, addr: u16) -> bool {
+        let enabled = match access {
+            Access::Read => self.read,
+            Access::Write => self.write,
+            Access::Execute => self.execute,
+        };
+        enabled && (self.start..=self.end).contains(&addr)
+    }
+}
+
+//e.g. 0x300, 0x300-0x30F or 0x300-0x30F,rw. Watches writes unless told otherwise
+impl FromStr for Watchpoint {
+    type Err = String;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let (range, flags) = raw.split_once(',').unwrap_or((raw, "w"));
+        let (start, end) = match range.split_once('-') {
+            Some((start, end)) => (parse_value(start.trim())?, parse_value(end.trim())?),
+            None => {
+                let addr = parse_value(range.trim())?;
+                (addr, addr)
+            }
+        };
+        if start > end {
+            return Err(format!("watch range '{}' ends before it starts", range));
+        }
+        let flags = flags.trim().to_ascii_lowercase();
+        if flags.is_empty() || flags.chars().any(|c| !"rwx".contains(c)) {
+            return Err(format!(
+                "watch flags must be some of r, w and x, got '{}'",
+                flags
+            ));
+        }
+        Ok(Watchpoint {
+            start,
+            end,
+            read: flags.contains('r'),
+            write: flags.contains('w'),
+            execute: flags.contains('x'),
+        })
+    }
+}
+
+//A watched access, old and new are the same unless it was a write.
+//pc and opcode are the instruction doing the access, or the fetch itself for executes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    pub access: Access,
+    pub addr: u16,
+    pub pc: u16,
+    pub opcode: u16,
+    pub old: u8,
+    pub new: u8,
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {:#05X} by {:#05X} ({:04X})",
+            self.access, self.addr, self.pc, self.opcode
+        )?;
+        if self.access == Access::Write {
+            write!(f, ": {:02X} -> {:02X}", self.old, self.new)
+        } else {
+            write!(f, ": {:02X}", self.new)
+        }
+    }
+}
+
+//When run_until should hand control back, besides breakpoints
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopCondition {
+    //only breakpoints stop it
+    Breakpoint,
+    //after a single instruction
+    Step,
+    //pc reaches addr with at most depth return addresses on the stack
+    Address { addr: u16, depth: usize },
+    //a return leaves fewer than depth return addresses on the stack
+    Return { depth: usize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugEvent {
+    //about to run the instruction at a breakpoint
+    Breakpoint(u16),
+    //the stop condition was met, pc is the next instruction to run
+    Stopped(u16),
+    //the instruction that just ran touched a watched address,
+    //Chip8::watch_hits has every access it made
+    Watchpoint(WatchHit),
+    //the program ran 00FD
+    Halted,
+    //ran the allowed number of instructions, call again to carry on
+    InstructionLimit,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_condition() {
+        assert_eq!(
+            Ok(Condition {
+                register: Register::V(3),
+                comparison: Comparison::Equal,
+                value: 0x10,
+            }),
+            "V3 == 0x10".parse()
+        );
+        assert_eq!(
+            Ok(Condition {
+                register: Register::I,
+                comparison: Comparison::GreaterOrEqual,
+                value: 768,
+            }),
+            "i>=768".parse()
+        );
+        assert!("V3 = 1".parse::<Condition>().is_err());
+        assert!("VG == 1".parse::<Condition>().is_err());
+        assert!("V3 == x".parse::<Condition>().is_err());
+    }
+
+    #[test]
+    fn test_condition_round_trip() {
+        let condition: Condition = "vf != 0x1".parse().unwrap();
+        assert_eq!("VF != 0x1", condition.to_string());
+        assert_eq!(Ok(condition), condition.to_string().parse());
+    }
+
+    #[test]
+    fn test_parse_watchpoint() {
+        let watch: Watchpoint = "0x300-0x30F,rX".parse().unwrap();
+        assert_eq!((0x300, 0x30F), (watch.start, watch.end));
+        assert!(watch.read && !watch.write && watch.execute);
+        assert!(watch.matches(Access::Read, 0x30F));
+        assert!(!watch.matches(Access::Read, 0x310));
+        assert!(!watch.matches(Access::Write, 0x300));
+
+        let watch: Watchpoint = "768".parse().unwrap();
+        assert!(watch.matches(Access::Write, 0x300));
+        assert!(!watch.matches(Access::Read, 0x300));
+
+        assert!("0x30F-0x300".parse::<Watchpoint>().is_err());
+        assert!("0x300,q".parse::<Watchpoint>().is_err());
+    }
+
+    #[test]
+    fn test_compare() {
+        assert!(Comparison::Less.compare(1, 2));
+        assert!(!Comparison::Greater.compare(1, 2));
+        assert!(Comparison::LessOrEqual.compare(2, 2));
+    }
+}
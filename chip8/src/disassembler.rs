@@ -0,0 +1,334 @@
+use crate::opcode::Opcode;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+const LONG_I_OPCODE: u16 = 0xF000;
+//octo programs start at this label
+const ENTRY_LABEL: &str = "main";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Syntax {
+    //LD V3, 0x10
+    #[default]
+    Cowgod,
+    //v3 := 0x10
+    Octo,
+}
+
+impl FromStr for Syntax {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "cowgod" => Ok(Syntax::Cowgod),
+            "octo" => Ok(Syntax::Octo),
+            _ => Err(format!(
+                "unknown syntax '{}', expected cowgod or octo",
+                name
+            )),
+        }
+    }
+}
+
+//One instruction or data byte of a listing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+    pub text: String,
+}
+
+//200: 6A02      LD VA, 0x02
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes: String = self.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        write!(f, "{:03X}: {:<8}  {}", self.addr, bytes, self.text)
+    }
+}
+
+//Follows every path the program can take from origin and marks where instructions start.
+//Anything never reached is treated as data, so sprites after the code aren't read as opcodes.
+//Jumps through v0 can't be followed exactly, only the base of the table is traced.
+pub fn trace(rom: &[u8], origin: u16) -> Vec<bool> {
+    let mut starts = vec![false; rom.len()];
+    //bytes already claimed by an instruction
+    let mut covered = vec![false; rom.len()];
+    let mut pending = vec![origin];
+
+    while let Some(addr) = pending.pop() {
+        let Some(offset) = (addr as usize).checked_sub(origin as usize) else {
+            continue;
+        };
+        if offset + 1 >= rom.len() || covered[offset] || covered[offset + 1] {
+            continue;
+        }
+        let opcode = word(rom, offset);
+        let size = if opcode == LONG_I_OPCODE { 4 } else { 2 };
+        if offset + size > rom.len() {
+            continue;
+        }
+        let next = addr.wrapping_add(size as u16);
+        let targets: &[u16] = match Opcode::decode(opcode) {
+            //stop rather than guess when we wandered into data
+            Opcode::Sys(_) | Opcode::UnknownOpcode(_) => continue,
+            Opcode::Jump(nnn) | Opcode::JumpPlusV0(nnn) => &[nnn],
+            Opcode::Call(nnn) => &[next, nnn],
+            Opcode::Ret | Opcode::Exit => &[],
+            Opcode::SkipIfEqualAtX { .. }
+            | Opcode::SkipIfNotEqualAtX { .. }
+            | Opcode::SkipIfBothValuesEqual { .. }
+            | Opcode::SkipIfBothValuesNotEqual { .. }
+            | Opcode::SkipIfKeyAtVxPressed { .. }
+            | Opcode::SkipIfKeyAtVxNotPressed { .. } => {
+                let skipped = (next as usize)
+                    .checked_sub(origin as usize)
+                    .filter(|offset| offset + 1 < rom.len())
+                    .map_or(2, |offset| {
+                        if word(rom, offset) == LONG_I_OPCODE {
+                            4
+                        } else {
+                            2
+                        }
+                    });
+                &[next, next.wrapping_add(skipped)]
+            }
+            _ => &[next],
+        };
+        starts[offset] = true;
+        covered[offset..offset + size].fill(true);
+        pending.extend_from_slice(targets);
+    }
+    starts
+}
+
+//Disassembles rom as loaded at origin, using trace to tell code from data
+pub fn disassemble(rom: &[u8], origin: u16, syntax: Syntax) -> Vec<Line> {
+    let starts = trace(rom, origin);
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    while offset < rom.len() {
+        let addr = origin.wrapping_add(offset as u16);
+        if !starts[offset] {
+            let byte = rom[offset];
+            lines.push(Line {
+                addr,
+                bytes: vec![byte],
+                text: data_text(byte, syntax),
+            });
+            offset += 1;
+            continue;
+        }
+
+        let opcode = word(rom, offset);
+        let (size, text) = if opcode == LONG_I_OPCODE {
+            let nnnn = word(rom, offset + 2);
+            let text = match syntax {
+                Syntax::Cowgod => format!("LD I, LONG {:#06X}", nnnn),
+                Syntax::Octo => format!("i := long {:#06X}", nnnn),
+            };
+            (4, text)
+        } else {
+            let opcode = Opcode::decode(opcode);
+            let text = match syntax {
+                Syntax::Cowgod => opcode.to_string(),
+                Syntax::Octo => format!("{:#}", opcode),
+            };
+            (2, text)
+        };
+        lines.push(Line {
+            addr,
+            bytes: rom[offset..offset + size].to_vec(),
+            text,
+        });
+        offset += size;
+    }
+    lines
+}
+
+//The listing as source chip8-asm assembles back to the same rom: no addresses or bytes,
+//and a label on everything jumped to or called, with octo's main on the origin
+pub fn source(rom: &[u8], origin: u16, syntax: Syntax) -> Vec<String> {
+    let lines = disassemble(rom, origin, syntax);
+    let mut labels = HashMap::new();
+    if syntax == Syntax::Octo {
+        labels.insert(origin, ENTRY_LABEL.to_string());
+    }
+    for line in &lines {
+        if let Some(target) = target(line) {
+            //jumps into data or the middle of an instruction keep their address
+            if lines.iter().any(|l| l.addr == target && l.bytes.len() > 1) {
+                labels
+                    .entry(target)
+                    .or_insert_with(|| format!("l{:03X}", target));
+            }
+        }
+    }
+
+    let mut source = Vec::with_capacity(lines.len() + labels.len());
+    for line in &lines {
+        if let Some(label) = labels.get(&line.addr) {
+            source.push(match syntax {
+                Syntax::Cowgod => format!("{}:", label),
+                Syntax::Octo => format!(": {}", label),
+            });
+        }
+        let text = match target(line).and_then(|target| labels.get(&target)) {
+            Some(label) => labelled(word(&line.bytes, 0), label, syntax),
+            None => line.text.clone(),
+        };
+        source.push(format!("  {}", text));
+    }
+    source
+}
+
+//a jump or call written with the label of its target
+fn labelled(opcode: u16, label: &str, syntax: Syntax) -> String {
+    match (opcode >> 12, syntax) {
+        (0x1, Syntax::Cowgod) => format!("JP {}", label),
+        (0x2, Syntax::Cowgod) => format!("CALL {}", label),
+        (_, Syntax::Cowgod) => format!("JP V0, {}", label),
+        (0x1, Syntax::Octo) => format!("jump {}", label),
+        //a bare name calls it in octo
+        (0x2, Syntax::Octo) => label.to_string(),
+        (_, Syntax::Octo) => format!("jump0 {}", label),
+    }
+}
+
+//where an instruction line jumps or calls to
+fn target(line: &Line) -> Option<u16> {
+    if line.bytes.len() != 2 {
+        return None;
+    }
+    match Opcode::decode(word(&line.bytes, 0)) {
+        Opcode::Jump(nnn) | Opcode::Call(nnn) | Opcode::JumpPlusV0(nnn) => Some(nnn),
+        _ => None,
+    }
+}
+
+//a single byte with its bits drawn out, since data is mostly sprites
+fn data_text(byte: u8, syntax: Syntax) -> String {
+    let pixels: String = (0..8)
+        .map(|bit| if byte & (0x80 >> bit) != 0 { '#' } else { '.' })
+        .collect();
+    match syntax {
+        Syntax::Cowgod => format!("DB {:#04X}  ; {}", byte, pixels),
+        Syntax::Octo => format!("{:#04X}  # {}", byte, pixels),
+    }
+}
+
+fn word(rom: &[u8], offset: usize) -> u16 {
+    (rom[offset] as u16) << 8 | rom[offset + 1] as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+    use crate::octo::compile;
+
+    const ORIGIN: u16 = 0x200;
+
+    fn texts(lines: &[Line]) -> Vec<&str> {
+        lines.iter().map(|line| line.text.as_str()).collect()
+    }
+
+    #[test]
+    fn test_sprite_after_code_is_data() {
+        //LD I, 0x206; DRW V0, V0, 1; JP 0x204; sprite
+        let rom = [0xA2, 0x06, 0xD0, 0x01, 0x12, 0x04, 0x3C];
+        let lines = disassemble(&rom, ORIGIN, Syntax::Cowgod);
+        assert_eq!(
+            vec![
+                "LD I, 0x206",
+                "DRW V0, V0, 1",
+                "JP 0x204",
+                "DB 0x3C  ; ..####..",
+            ],
+            texts(&lines)
+        );
+        assert_eq!("206: 3C        DB 0x3C  ; ..####..", lines[3].to_string());
+    }
+
+    #[test]
+    fn test_follows_calls_and_skips() {
+        //200 CALL 0x208; 202 JP 0x202; 204 data; 206 data; 208 SE V0, 0; 20A RET; 20C RET
+        let rom = [
+            0x22, 0x08, 0x12, 0x02, 0xFF, 0xFF, 0x00, 0x00, 0x30, 0x00, 0x00, 0xEE, 0x00, 0xEE,
+        ];
+        assert_eq!(
+            vec![
+                true, false, true, false, false, false, false, false, true, false, true, false,
+                true, false
+            ],
+            trace(&rom, ORIGIN)
+        );
+    }
+
+    #[test]
+    fn test_long_i_is_four_bytes() {
+        //skip over the whole F000 NNNN
+        let rom = [0x30, 0x00, 0xF0, 0x00, 0x12, 0x34, 0x00, 0xEE];
+        let lines = disassemble(&rom, ORIGIN, Syntax::Octo);
+        assert_eq!(
+            vec!["if v0 != 0x00 then", "i := long 0x1234", "return"],
+            texts(&lines)
+        );
+        assert_eq!(vec![0xF0, 0x00, 0x12, 0x34], lines[1].bytes);
+    }
+
+    #[test]
+    fn test_source_labels() {
+        //200 CALL 0x208; 202 SE V0, 0; 204 JP 0x202; 206 JP 0x301; 208 RET
+        let rom = [0x22, 0x08, 0x30, 0x00, 0x12, 0x02, 0x13, 0x01, 0x00, 0xEE];
+        assert_eq!(
+            vec![
+                ": main",
+                "  l208",
+                ": l202",
+                "  if v0 != 0x00 then",
+                "  jump l202",
+                "  jump 0x301",
+                ": l208",
+                "  return",
+            ],
+            source(&rom, ORIGIN, Syntax::Octo)
+        );
+        assert_eq!(
+            vec![
+                "  CALL l208",
+                "l202:",
+                "  SE V0, 0x00",
+                "  JP l202",
+                "  JP 0x301",
+                "l208:",
+                "  RET",
+            ],
+            source(&rom, ORIGIN, Syntax::Cowgod)
+        );
+    }
+
+    fn round_trip(rom: &[u8]) {
+        let cowgod = source(rom, ORIGIN, Syntax::Cowgod).join("\n");
+        assert_eq!(Ok(rom.to_vec()), assemble(&cowgod, ORIGIN));
+        let octo = source(rom, ORIGIN, Syntax::Octo).join("\n");
+        assert_eq!(Ok(rom.to_vec()), compile(&octo, ORIGIN));
+    }
+
+    #[test]
+    fn test_source_round_trip_roms() {
+        round_trip(include_bytes!("../../roms/PONG2"));
+        round_trip(include_bytes!("../../roms/BRIX"));
+        round_trip(include_bytes!("../../roms/INVADERS"));
+        round_trip(include_bytes!("../../roms/test_opcode.ch8"));
+        round_trip(include_bytes!("../../roms/5-quirks.ch8"));
+    }
+
+    #[test]
+    fn test_custom_origin() {
+        let rom = [0x16, 0x00];
+        let lines = disassemble(&rom, 0x600, Syntax::Cowgod);
+        assert_eq!(0x600, lines[0].addr);
+        assert_eq!(vec!["JP 0x600"], texts(&lines));
+    }
+}
@@ -1,7 +1,148 @@
+use std::fmt;
+use std::io;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ChipError {
-    StackOverflow,
-    StackUnderflow,
+    //pc is the address the faulting opcode was fetched from
+    StackOverflow { pc: u16, opcode: u16 },
+    StackUnderflow { pc: u16, opcode: u16 },
     InvalidKey(u8),
-    UnknownOpcode(u16),
-    SysOpcodeNotSupported(u16),
-}
\ No newline at end of file
+    UnknownOpcode { pc: u16, opcode: u16 },
+    SysOpcodeNotSupported { pc: u16, opcode: u16 },
+    RomTooLarge { size: usize, max: usize },
+    EmptyRom,
+    //below 0x200, where the interpreter and fonts live
+    InvalidLoadAddress(u16),
+    InvalidState(StateError),
+}
+
+impl fmt::Display for ChipError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChipError::StackOverflow { pc, opcode } => {
+                write!(f, "stack overflow at {:#05x} (opcode {:04x})", pc, opcode)
+            }
+            ChipError::StackUnderflow { pc, opcode } => {
+                write!(f, "stack underflow at {:#05x} (opcode {:04x})", pc, opcode)
+            }
+            ChipError::InvalidKey(key) => write!(
+                f,
+                "invalid key {:#x}, key must be hexadecimal value of 0x0 through 0xF",
+                key
+            ),
+            ChipError::UnknownOpcode { pc, opcode } => {
+                write!(f, "unknown opcode {:04x} at {:#05x}", opcode, pc)
+            }
+            ChipError::SysOpcodeNotSupported { pc, opcode } => {
+                write!(f, "sys opcode {:04x} at {:#05x} not supported", opcode, pc)
+            }
+            ChipError::RomTooLarge { size, max } => {
+                write!(f, "rom is {} bytes, max size is {} bytes", size, max)
+            }
+            ChipError::EmptyRom => write!(f, "rom is empty"),
+            ChipError::InvalidLoadAddress(addr) => {
+                write!(
+                    f,
+                    "can't load a rom at {:#05x}, it must be 0x200 or above",
+                    addr
+                )
+            }
+            ChipError::InvalidState(e) => write!(f, "invalid save state: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ChipError {}
+
+impl From<StateError> for ChipError {
+    fn from(e: StateError) -> Self {
+        ChipError::InvalidState(e)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    ChecksumMismatch,
+    Truncated,
+    //well formed but the values don't make sense
+    Corrupt,
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::BadMagic => write!(f, "not a save state"),
+            StateError::UnsupportedVersion(version) => {
+                write!(f, "unsupported save state version {}", version)
+            }
+            StateError::ChecksumMismatch => write!(f, "save state checksum mismatch"),
+            StateError::Truncated => write!(f, "save state is truncated"),
+            StateError::Corrupt => write!(f, "save state is corrupt"),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MovieError {
+    //line is 1 based
+    Parse { line: usize, message: String },
+    //crc32 of the rom the movie was recorded with and of the one given
+    RomMismatch { expected: u32, actual: u32 },
+    Chip(ChipError),
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MovieError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            MovieError::RomMismatch { expected, actual } => write!(
+                f,
+                "movie was recorded with rom {:08x}, this rom is {:08x}",
+                expected, actual
+            ),
+            MovieError::Chip(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+impl From<ChipError> for MovieError {
+    fn from(e: ChipError) -> Self {
+        MovieError::Chip(e)
+    }
+}
+
+//Loading a rom from a file or reader, which can fail before the chip sees it
+#[derive(Debug)]
+pub enum RomError {
+    Io(io::Error),
+    Chip(ChipError),
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RomError::Io(e) => write!(f, "{}", e),
+            RomError::Chip(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for RomError {}
+
+impl From<io::Error> for RomError {
+    fn from(e: io::Error) -> Self {
+        RomError::Io(e)
+    }
+}
+
+impl From<ChipError> for RomError {
+    fn from(e: ChipError) -> Self {
+        RomError::Chip(e)
+    }
+}
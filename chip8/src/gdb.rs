@@ -0,0 +1,630 @@
+use crate::chip::Chip8;
+use crate::debug::{Access, DebugEvent, Register, StopCondition, Watchpoint};
+use crate::error::ChipError;
+use crate::scheduler::{Scheduler, TIMER_HZ};
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+//how long a continue waits for an interrupt between frames
+const FRAME_TIME: Duration = Duration::from_nanos(1_000_000_000 / TIMER_HZ as u64);
+//gdb sends this byte, outside a packet, to stop a continue
+const INTERRUPT: u8 = 0x03;
+//in hex digits, so m can return half this many bytes
+const PACKET_SIZE: usize = 0x4000;
+const NUM_REGISTERS: usize = 21;
+//gdb only checks that there is an error, not which
+const ERROR: &str = "E01";
+
+//stop reasons, as unix signal numbers
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+
+//Register n as g, p and target.xml number them, with its size in bytes and name. Two byte
+//registers are sent big endian, like opcodes in memory
+fn register_info(n: usize) -> Option<(Register, usize, String)> {
+    let info = match n {
+        0..=15 => (Register::V(n as u8), 1, format!("v{:x}", n)),
+        16 => (Register::I, 2, "i".to_string()),
+        17 => (Register::Pc, 2, "pc".to_string()),
+        18 => (Register::Sp, 1, "sp".to_string()),
+        19 => (Register::DelayTimer, 1, "dt".to_string()),
+        20 => (Register::SoundTimer, 1, "st".to_string()),
+        _ => return None,
+    };
+    Some(info)
+}
+
+//tells gdb the register layout, it has no CHIP-8 architecture of its own
+pub fn target_xml() -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?>\n\
+         <!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n\
+         <target version=\"1.0\">\n  <feature name=\"org.chip8.core\">\n",
+    );
+    for n in 0..NUM_REGISTERS {
+        let (register, size, name) = register_info(n).unwrap();
+        let kind = match register {
+            Register::Pc => "code_ptr",
+            Register::I => "data_ptr",
+            _ if size == 1 => "uint8",
+            _ => "uint16",
+        };
+        let _ = writeln!(
+            xml,
+            "    <reg name=\"{}\" bitsize=\"{}\" type=\"{}\" regnum=\"{}\"/>",
+            name,
+            size * 8,
+            kind,
+            n
+        );
+    }
+    xml.push_str("  </feature>\n</target>\n");
+    xml
+}
+
+//$data#checksum, the checksum is the sum of the data bytes
+pub fn packet(data: &str) -> String {
+    let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+    format!("${}#{:02x}", data, checksum)
+}
+
+//What the stub does after a packet
+enum Action {
+    Reply(String),
+    Step,
+    Continue,
+    //reply OK and end the session
+    Detach,
+    //end the session without a reply
+    Kill,
+}
+
+//A GDB remote serial protocol server for one Chip8. Serves one client at a time over TCP,
+//with registers, memory, breakpoints, watchpoints, single step and continue.
+//Continue runs at instructions_per_second with the timers until a breakpoint or ^C
+pub struct GdbStub {
+    chip: Chip8,
+    instructions_per_second: u32,
+    //after QStartNoAckMode packets aren't acknowledged with + or -
+    no_ack: bool,
+    //sent again when the client answers -
+    last_reply: String,
+}
+
+impl GdbStub {
+    pub fn new(chip: Chip8, instructions_per_second: u32) -> Self {
+        GdbStub {
+            chip,
+            instructions_per_second,
+            no_ack: false,
+            last_reply: String::new(),
+        }
+    }
+
+    pub fn chip(&self) -> &Chip8 {
+        &self.chip
+    }
+
+    pub fn into_chip(self) -> Chip8 {
+        self.chip
+    }
+
+    //Serves a client until it detaches, kills or disconnects. Kill restarts the program
+    //for the next client, a detach or disconnect leaves it where it stopped
+    pub fn serve(&mut self, stream: TcpStream) -> io::Result<()> {
+        self.no_ack = false;
+        //packets are small and each waits on the last, don't hold them back
+        stream.set_nodelay(true)?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+        while let Some(request) = self.read_packet(&mut reader, &mut writer)? {
+            let reply = match self.handle(&request) {
+                Action::Reply(reply) => reply,
+                Action::Step => self.step(),
+                Action::Continue => match self.resume(&mut reader)? {
+                    Some(reply) => reply,
+                    None => return Ok(()),
+                },
+                Action::Detach => {
+                    self.write_packet(&mut writer, "OK")?;
+                    return Ok(());
+                }
+                Action::Kill => {
+                    self.chip.restart();
+                    return Ok(());
+                }
+            };
+            self.write_packet(&mut writer, &reply)?;
+        }
+        Ok(())
+    }
+
+    //the next packet's data, acknowledged unless in no ack mode. None when the client is gone
+    fn read_packet(
+        &mut self,
+        reader: &mut impl BufRead,
+        writer: &mut impl Write,
+    ) -> io::Result<Option<String>> {
+        loop {
+            let Some(byte) = read_byte(reader)? else {
+                return Ok(None);
+            };
+            match byte {
+                b'$' => (),
+                b'-' if !self.no_ack => {
+                    let resend = packet(&self.last_reply);
+                    writer.write_all(resend.as_bytes())?;
+                    writer.flush()?;
+                    continue;
+                }
+                //acks, and a ^C that came after we stopped
+                _ => continue,
+            }
+            let mut data = Vec::new();
+            if reader.read_until(b'#', &mut data)? == 0 || data.pop() != Some(b'#') {
+                return Ok(None);
+            }
+            let mut checksum = [0; 2];
+            reader.read_exact(&mut checksum)?;
+            let expected = data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+            let valid = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|raw| u8::from_str_radix(raw, 16).ok())
+                == Some(expected);
+            if !self.no_ack {
+                writer.write_all(if valid { b"+" } else { b"-" })?;
+                writer.flush()?;
+            }
+            if valid || self.no_ack {
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            }
+        }
+    }
+
+    fn write_packet(&mut self, writer: &mut impl Write, data: &str) -> io::Result<()> {
+        writer.write_all(packet(data).as_bytes())?;
+        writer.flush()?;
+        self.last_reply = data.to_string();
+        Ok(())
+    }
+
+    fn handle(&mut self, request: &str) -> Action {
+        let (name, args) = split_request(request);
+        let reply = match name {
+            "?" => stop_signal(SIGTRAP),
+            "g" => (0..NUM_REGISTERS).map(|n| self.read_register(n)).collect(),
+            "G" => self.write_registers(args),
+            "p" => hex(args)
+                .filter(|n| (*n as usize) < NUM_REGISTERS)
+                .map_or(ERROR.to_string(), |n| self.read_register(n as usize)),
+            "P" => self.write_register(args),
+            "m" => self.read_memory(args),
+            "M" => self.write_memory(args),
+            "s" | "c" => {
+                if !args.is_empty() {
+                    match hex(args) {
+                        Some(addr) => self.chip.set_register(Register::Pc, addr as u16),
+                        None => return Action::Reply(ERROR.to_string()),
+                    }
+                }
+                return if name == "s" {
+                    Action::Step
+                } else {
+                    Action::Continue
+                };
+            }
+            "Z" => self.set_breakpoint(args, true),
+            "z" => self.set_breakpoint(args, false),
+            "D" => return Action::Detach,
+            "k" => return Action::Kill,
+            "qSupported" => format!(
+                "PacketSize={:x};QStartNoAckMode+;qXfer:features:read+",
+                PACKET_SIZE
+            ),
+            "QStartNoAckMode" => {
+                self.no_ack = true;
+                "OK".to_string()
+            }
+            "qXfer" => read_target_xml(args),
+            "qAttached" => "1".to_string(),
+            //one thread, number 1
+            "qC" => "QC1".to_string(),
+            "qfThreadInfo" => "m1".to_string(),
+            "qsThreadInfo" => "l".to_string(),
+            "H" | "T" => "OK".to_string(),
+            //an empty reply means not supported
+            _ => String::new(),
+        };
+        Action::Reply(reply)
+    }
+
+    fn read_register(&self, n: usize) -> String {
+        let (register, size, _) = register_info(n).unwrap();
+        let value = self.chip.register(register);
+        match size {
+            1 => format!("{:02x}", value),
+            _ => format!("{:04x}", value),
+        }
+    }
+
+    //all registers in g order, one hex string
+    fn write_registers(&mut self, args: &str) -> String {
+        let mut rest = args;
+        let mut values = Vec::new();
+        for n in 0..NUM_REGISTERS {
+            let (register, size, _) = register_info(n).unwrap();
+            let Some((value, tail)) = rest.split_at_checked(size * 2) else {
+                return ERROR.to_string();
+            };
+            let Some(value) = hex(value) else {
+                return ERROR.to_string();
+            };
+            values.push((register, value as u16));
+            rest = tail;
+        }
+        for (register, value) in values {
+            self.chip.set_register(register, value);
+        }
+        "OK".to_string()
+    }
+
+    //n=value
+    fn write_register(&mut self, args: &str) -> String {
+        let parsed = args.split_once('=').and_then(|(n, value)| {
+            let (register, size, _) = register_info(hex(n)? as usize)?;
+            (value.len() == size * 2).then_some((register, hex(value)?))
+        });
+        match parsed {
+            Some((register, value)) => {
+                self.chip.set_register(register, value as u16);
+                "OK".to_string()
+            }
+            None => ERROR.to_string(),
+        }
+    }
+
+    //addr,length, memory wraps around like it does for the program
+    fn read_memory(&self, args: &str) -> String {
+        let Some((addr, length)) = pair(args) else {
+            return ERROR.to_string();
+        };
+        let memory = self.chip.memory();
+        (0..(length as usize).min(PACKET_SIZE / 2))
+            .map(|offset| format!("{:02x}", memory[(addr as usize + offset) % memory.len()]))
+            .collect()
+    }
+
+    //addr,length:bytes
+    fn write_memory(&mut self, args: &str) -> String {
+        let parsed = args.split_once(':').and_then(|(range, bytes)| {
+            let (addr, length) = pair(range)?;
+            if bytes.len() != length as usize * 2 {
+                return None;
+            }
+            let bytes = (0..bytes.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(bytes.get(i..i + 2)?, 16).ok())
+                .collect::<Option<Vec<u8>>>()?;
+            Some((addr, bytes))
+        });
+        let Some((addr, bytes)) = parsed else {
+            return ERROR.to_string();
+        };
+        for (offset, byte) in bytes.into_iter().enumerate() {
+            self.chip.poke((addr as usize + offset) as u16, byte);
+        }
+        "OK".to_string()
+    }
+
+    //type,addr,kind. 0 and 1 are breakpoints, 2 to 4 are write, read and access
+    //watchpoints of kind bytes
+    fn set_breakpoint(&mut self, args: &str, insert: bool) -> String {
+        let mut fields = args.split(',').map(hex);
+        let (Some(Some(kind)), Some(Some(addr)), Some(Some(length)), None) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            return ERROR.to_string();
+        };
+        let addr = addr as u16;
+        let (read, write) = match kind {
+            0 | 1 => {
+                if insert {
+                    self.chip.add_breakpoint(addr, None);
+                } else {
+                    self.chip.remove_breakpoint(addr);
+                }
+                return "OK".to_string();
+            }
+            2 => (false, true),
+            3 => (true, false),
+            4 => (true, true),
+            _ => return String::new(),
+        };
+        let watchpoint = Watchpoint {
+            start: addr,
+            end: addr.wrapping_add((length as u16).max(1) - 1),
+            read,
+            write,
+            execute: false,
+        };
+        if insert {
+            self.chip.add_watchpoint(watchpoint);
+        } else {
+            self.chip.remove_watchpoint(&watchpoint);
+        }
+        "OK".to_string()
+    }
+
+    fn step(&mut self) -> String {
+        stop_reply(self.chip.run_until(StopCondition::Step, 1))
+    }
+
+    //runs in real time until something stops it, None if the client went away
+    fn resume(&mut self, reader: &mut BufReader<TcpStream>) -> io::Result<Option<String>> {
+        let mut scheduler = Scheduler::new(self.instructions_per_second);
+        scheduler.update(Instant::now());
+        loop {
+            match interrupted(reader)? {
+                Some(true) => return Ok(Some(stop_signal(SIGINT))),
+                Some(false) => (),
+                None => return Ok(None),
+            }
+            let due = scheduler.update(Instant::now());
+            match self
+                .chip
+                .run_until(StopCondition::Breakpoint, due.instructions)
+            {
+                Ok(DebugEvent::InstructionLimit) => {
+                    for _ in 0..due.timer_ticks {
+                        self.chip.tick_timers();
+                    }
+                }
+                result => return Ok(Some(stop_reply(result))),
+            }
+        }
+    }
+}
+
+//Waits up to a frame for a ^C, the only thing gdb sends while the target runs. Anything
+//else, like a late ack, is dropped. None if the client went away
+fn interrupted(reader: &mut BufReader<TcpStream>) -> io::Result<Option<bool>> {
+    if reader.buffer().is_empty() {
+        reader.get_ref().set_read_timeout(Some(FRAME_TIME))?;
+        let filled = reader.fill_buf().map(|buffer| buffer.is_empty());
+        reader.get_ref().set_read_timeout(None)?;
+        match filled {
+            Ok(true) => return Ok(None),
+            Ok(false) => (),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                return Ok(Some(false));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    let interrupt = reader.buffer().contains(&INTERRUPT);
+    let length = reader.buffer().len();
+    reader.consume(length);
+    Ok(Some(interrupt))
+}
+
+fn stop_signal(signal: u8) -> String {
+    format!("S{:02x}", signal)
+}
+
+fn stop_reply(result: Result<DebugEvent, ChipError>) -> String {
+    match result {
+        //a watched fetch has no gdb equivalent, it's reported like a breakpoint
+        Ok(DebugEvent::Watchpoint(hit)) if hit.access != Access::Execute => {
+            let kind = if hit.access == Access::Read {
+                "rwatch"
+            } else {
+                "watch"
+            };
+            format!("T{:02x}{}:{:x};", SIGTRAP, kind, hit.addr)
+        }
+        Ok(DebugEvent::Halted) => "W00".to_string(),
+        Ok(_) => stop_signal(SIGTRAP),
+        //like the other frontends, pc is left after the faulting instruction
+        Err(_) => stop_signal(SIGILL),
+    }
+}
+
+//features:read:target.xml:offset,length
+fn read_target_xml(args: &str) -> String {
+    let Some(range) = args.strip_prefix("features:read:target.xml:") else {
+        return String::new();
+    };
+    let Some((offset, length)) = pair(range) else {
+        return ERROR.to_string();
+    };
+    let xml = target_xml();
+    let start = (offset as usize).min(xml.len());
+    let end = start.saturating_add(length as usize).min(xml.len());
+    //m means there's more, l is the last part
+    let more = if end < xml.len() { 'm' } else { 'l' };
+    format!("{}{}", more, &xml[start..end])
+}
+
+//the request name and what follows it. Single letter requests run straight into their
+//arguments, q requests put them after a colon
+fn split_request(request: &str) -> (&str, &str) {
+    if request.starts_with(['q', 'Q']) {
+        request.split_once(':').unwrap_or((request, ""))
+    } else if request.is_empty() {
+        ("", "")
+    } else {
+        request.split_at(1)
+    }
+}
+
+fn hex(raw: &str) -> Option<u32> {
+    u32::from_str_radix(raw, 16).ok()
+}
+
+//addr,length
+fn pair(raw: &str) -> Option<(u32, u32)> {
+    let (first, second) = raw.split_once(',')?;
+    Some((hex(first)?, hex(second)?))
+}
+
+fn read_byte(reader: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut byte = [0];
+    match reader.read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirks::Quirks;
+    use std::net::TcpListener;
+    use std::thread::{self, JoinHandle};
+
+    //A scripted gdb, sends a packet and returns the reply
+    struct Client {
+        reader: BufReader<TcpStream>,
+        writer: TcpStream,
+    }
+
+    impl Client {
+        fn send(&mut self, data: &str) -> String {
+            self.writer.write_all(packet(data).as_bytes()).unwrap();
+            let mut ack = [0];
+            self.reader.read_exact(&mut ack).unwrap();
+            assert_eq!(b'+', ack[0]);
+            self.reply()
+        }
+
+        fn reply(&mut self) -> String {
+            let mut reply = Vec::new();
+            self.reader.read_until(b'$', &mut reply).unwrap();
+            reply.clear();
+            self.reader.read_until(b'#', &mut reply).unwrap();
+            reply.pop();
+            let mut checksum = [0; 2];
+            self.reader.read_exact(&mut checksum).unwrap();
+            let reply = String::from_utf8(reply).unwrap();
+            assert_eq!(
+                packet(&reply)[reply.len() + 2..],
+                *std::str::from_utf8(&checksum).unwrap()
+            );
+            self.writer.write_all(b"+").unwrap();
+            reply
+        }
+    }
+
+    //serves the rom on a local port, the thread returns the chip once the client leaves
+    fn start(rom: &[u8]) -> (Client, JoinHandle<Chip8>) {
+        let mut chip = Chip8::with_seed(Quirks::default(), 0);
+        chip.load_rom(rom).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut stub = GdbStub::new(chip, 6000);
+            stub.serve(stream).unwrap();
+            stub.into_chip()
+        });
+        let stream = TcpStream::connect(addr).unwrap();
+        stream.set_nodelay(true).unwrap();
+        let client = Client {
+            reader: BufReader::new(stream.try_clone().unwrap()),
+            writer: stream,
+        };
+        (client, server)
+    }
+
+    #[test]
+    fn test_packet() {
+        assert_eq!("$OK#9a", packet("OK"));
+        assert_eq!("$#00", packet(""));
+        assert_eq!(("m", "200,4"), split_request("m200,4"));
+        assert_eq!(
+            ("qXfer", "features:read:target.xml:0,10"),
+            split_request("qXfer:features:read:target.xml:0,10")
+        );
+        assert!(target_xml()
+            .contains("<reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\" regnum=\"17\"/>"));
+    }
+
+    #[test]
+    fn test_registers_and_memory() {
+        //200 LD V3, 0x42; 202 LD I, 0x300
+        let (mut client, server) = start(&[0x63, 0x42, 0xA3, 0x00]);
+        assert!(client
+            .send("qSupported:multiprocess+")
+            .contains("qXfer:features:read+"));
+        assert_eq!("S05", client.send("?"));
+        assert_eq!("S05", client.send("s"));
+        assert_eq!("S05", client.send("s"));
+        assert_eq!(
+            format!("00000042{}03000204000000", "00".repeat(12)),
+            client.send("g")
+        );
+        assert_eq!("0204", client.send("p11"));
+        assert_eq!(ERROR, client.send("p15"));
+        assert_eq!("OK", client.send("P13=09"));
+        assert_eq!(ERROR, client.send("P13=0009"));
+        assert_eq!("00006342", client.send("m1fe,4"));
+        assert_eq!("OK", client.send("M300,2:abcd"));
+        assert_eq!("abcd", client.send("m300,2"));
+        assert_eq!(ERROR, client.send("M300,2:ab"));
+        assert_eq!(
+            "m<?xml version=\"1.0\"?",
+            client.send("qXfer:features:read:target.xml:0,14")
+        );
+        assert!(client
+            .send("qXfer:features:read:target.xml:14,4000")
+            .ends_with("</target>\n"));
+        assert_eq!("", client.send("vMustReplyEmpty"));
+        assert_eq!("OK", client.send("D"));
+
+        let chip = server.join().unwrap();
+        assert_eq!(9, chip.register(Register::DelayTimer));
+        assert_eq!([0xAB, 0xCD], chip.memory()[0x300..0x302]);
+    }
+
+    #[test]
+    fn test_breakpoints_and_continue() {
+        //200 ADD V0, 1; 202 LD I, 0x300; 204 LD [I], V0; 206 JP 0x200
+        let (mut client, server) = start(&[0x70, 0x01, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x00]);
+        assert_eq!("OK", client.send("Z0,206,2"));
+        assert_eq!("S05", client.send("c"));
+        assert_eq!("0206", client.send("p11"));
+        assert_eq!("S05", client.send("c"));
+        assert_eq!("02", client.send("p0"));
+        assert_eq!("OK", client.send("z0,206,2"));
+        assert_eq!("OK", client.send("Z2,300,1"));
+        assert_eq!("T05watch:300;", client.send("c"));
+        assert_eq!("03", client.send("m300,1"));
+        assert_eq!("OK", client.send("z2,300,1"));
+
+        //nothing stops it now but ^C
+        assert_eq!("OK", client.send("QStartNoAckMode"));
+        client.writer.write_all(packet("c").as_bytes()).unwrap();
+        thread::sleep(Duration::from_millis(50));
+        client.writer.write_all(&[INTERRUPT]).unwrap();
+        assert_eq!("S02", client.reply());
+        client.writer.write_all(packet("k").as_bytes()).unwrap();
+
+        let chip = server.join().unwrap();
+        assert_eq!(0x200, chip.register(Register::Pc));
+        assert_eq!(0, chip.register(Register::V(0)));
+    }
+
+    #[test]
+    fn test_halt_and_fault() {
+        //200 RET with nothing to return to
+        let (mut client, server) = start(&[0x00, 0xEE, 0x00, 0xFD]);
+        assert_eq!("S04", client.send("s"));
+        assert_eq!("0202", client.send("p11"));
+        assert_eq!("W00", client.send("c202"));
+        assert_eq!("OK", client.send("D"));
+        assert!(server.join().unwrap().is_halted());
+    }
+}
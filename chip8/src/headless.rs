@@ -0,0 +1,248 @@
+use crate::chip::Chip8;
+use crate::debug::Register;
+use crate::error::ChipError;
+use crate::opcode::Opcode;
+use std::fmt;
+use std::str::FromStr;
+
+//A key going down or up at the start of a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub frame: u64,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+//FRAME:+KEY presses and FRAME:-KEY releases, e.g. 30:+5. The key is hex
+impl FromStr for KeyEvent {
+    type Err = String;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("expected a key event like 30:+5 or 40:-5, got '{}'", raw);
+        let (frame, key) = raw.split_once(':').ok_or_else(invalid)?;
+        let (pressed, key) = match key.split_at_checked(1) {
+            Some(("+", key)) => (true, key),
+            Some(("-", key)) => (false, key),
+            _ => return Err(invalid()),
+        };
+        let key = u8::from_str_radix(key, 16)
+            .ok()
+            .filter(|key| *key < 16)
+            .ok_or_else(|| format!("invalid key '{}', expected 0-F", key))?;
+        Ok(KeyEvent {
+            frame: frame.parse().map_err(|_| invalid())?,
+            key,
+            pressed,
+        })
+    }
+}
+
+impl fmt::Display for KeyEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.pressed { '+' } else { '-' };
+        write!(f, "{}:{}{:X}", self.frame, sign, self.key)
+    }
+}
+
+//Key events separated by whitespace or commas, sorted by frame
+pub fn parse_key_script(script: &str) -> Result<Vec<KeyEvent>, String> {
+    let mut events = script
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|event| !event.is_empty())
+        .map(str::parse)
+        .collect::<Result<Vec<KeyEvent>, String>>()?;
+    events.sort_by_key(|event| event.frame);
+    Ok(events)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeadlessConfig {
+    pub instructions_per_frame: u32,
+    //stops after this many frames if nothing else stops it first
+    pub max_frames: u64,
+    //stops before running the instruction at this address
+    pub stop_pc: Option<u16>,
+    //stops when the program jumps to itself, how most test roms finish
+    pub stop_on_loop: bool,
+    pub keys: Vec<KeyEvent>,
+}
+
+impl Default for HeadlessConfig {
+    fn default() -> Self {
+        HeadlessConfig {
+            instructions_per_frame: 10,
+            max_frames: 600,
+            stop_pc: None,
+            stop_on_loop: false,
+            keys: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    Frames,
+    //pc reached stop_pc
+    Pc(u16),
+    //the jump at this address jumps to itself
+    Loop(u16),
+    //the program ran 00FD
+    Halted,
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::Frames => write!(f, "ran out of frames"),
+            StopReason::Pc(pc) => write!(f, "reached {:#05X}", pc),
+            StopReason::Loop(pc) => write!(f, "looping at {:#05X}", pc),
+            StopReason::Halted => write!(f, "halted"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeadlessRun {
+    //frames whose timers ticked, a stop partway through a frame doesn't count it
+    pub frames: u64,
+    pub reason: StopReason,
+}
+
+//Runs the chip as fast as possible with a fixed number of instructions per 60hz frame,
+//so the result only depends on the rom, quirks, seed and keys
+pub fn run(chip: &mut Chip8, config: &HeadlessConfig) -> Result<HeadlessRun, ChipError> {
+    let mut keys = config.keys.iter().peekable();
+    for frame in 0..config.max_frames {
+        while let Some(event) = keys.next_if(|event| event.frame <= frame) {
+            if event.pressed {
+                chip.key_press(event.key)?;
+            } else {
+                chip.key_release(event.key)?;
+            }
+        }
+        for _ in 0..config.instructions_per_frame {
+            if let Some(reason) = stop_reason(chip, config) {
+                return Ok(HeadlessRun {
+                    frames: frame,
+                    reason,
+                });
+            }
+            chip.tick()?;
+        }
+        chip.tick_timers();
+    }
+    Ok(HeadlessRun {
+        frames: config.max_frames,
+        reason: stop_reason(chip, config).unwrap_or(StopReason::Frames),
+    })
+}
+
+//checked before each instruction
+fn stop_reason(chip: &Chip8, config: &HeadlessConfig) -> Option<StopReason> {
+    let pc = chip.register(Register::Pc);
+    if chip.is_halted() {
+        Some(StopReason::Halted)
+    } else if config.stop_pc == Some(pc) {
+        Some(StopReason::Pc(pc))
+    } else if config.stop_on_loop && Opcode::decode(chip.opcode_at(pc)) == Opcode::Jump(pc) {
+        Some(StopReason::Loop(pc))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirks::Quirks;
+
+    fn chip(rom: &[u8]) -> Chip8 {
+        let mut chip = Chip8::with_seed(Quirks::default(), 0);
+        chip.load_rom(rom).unwrap();
+        chip
+    }
+
+    #[test]
+    fn test_parse_key_script() {
+        assert_eq!(
+            Ok(vec![
+                KeyEvent {
+                    frame: 3,
+                    key: 0xA,
+                    pressed: true
+                },
+                KeyEvent {
+                    frame: 10,
+                    key: 0xA,
+                    pressed: false
+                },
+            ]),
+            parse_key_script("10:-a, 3:+A\n")
+        );
+        assert_eq!("3:+A", "3:+a".parse::<KeyEvent>().unwrap().to_string());
+        assert!(parse_key_script("3:A").is_err());
+        assert!(parse_key_script("3:+10").is_err());
+        assert!(parse_key_script("x:+1").is_err());
+    }
+
+    #[test]
+    fn test_stops() {
+        //200 ADD V0, 1; 202 JP 0x202
+        let rom = [0x70, 0x01, 0x12, 0x02];
+        let config = HeadlessConfig {
+            stop_on_loop: true,
+            ..HeadlessConfig::default()
+        };
+        assert_eq!(
+            Ok(HeadlessRun {
+                frames: 0,
+                reason: StopReason::Loop(0x202)
+            }),
+            run(&mut chip(&rom), &config)
+        );
+
+        let config = HeadlessConfig {
+            max_frames: 3,
+            ..HeadlessConfig::default()
+        };
+        let mut looping = chip(&rom);
+        assert_eq!(
+            Ok(HeadlessRun {
+                frames: 3,
+                reason: StopReason::Frames
+            }),
+            run(&mut looping, &config)
+        );
+        assert_eq!(1, looping.register(Register::V(0)));
+
+        let config = HeadlessConfig {
+            stop_pc: Some(0x202),
+            ..HeadlessConfig::default()
+        };
+        assert_eq!(
+            StopReason::Pc(0x202),
+            run(&mut chip(&rom), &config).unwrap().reason
+        );
+        //00FD
+        assert_eq!(
+            StopReason::Halted,
+            run(&mut chip(&[0x00, 0xFD]), &config).unwrap().reason
+        );
+    }
+
+    #[test]
+    fn test_scripted_keys() {
+        //200 LD V0, K; 202 JP 0x202
+        let rom = [0xF0, 0x0A, 0x12, 0x02];
+        let config = HeadlessConfig {
+            stop_on_loop: true,
+            keys: parse_key_script("5:+7 6:-7").unwrap(),
+            ..HeadlessConfig::default()
+        };
+        let mut chip = chip(&rom);
+        let result = run(&mut chip, &config).unwrap();
+        //stops partway through the frame the key went down in
+        assert_eq!(5, result.frames);
+        assert_eq!(7, chip.register(Register::V(0)));
+    }
+}
@@ -1,3 +1,23 @@
+pub mod assembler;
+pub mod audio;
 pub mod chip;
+pub mod dap;
+pub mod debug;
+pub mod disassembler;
+pub mod error;
+pub mod gdb;
+pub mod headless;
+pub mod machine;
+pub mod monitor;
+pub mod movie;
+pub mod octo;
 pub mod opcode;
-pub mod error;
\ No newline at end of file
+pub mod quirks;
+pub mod rewind;
+pub mod rng;
+pub mod scheduler;
+pub mod screen;
+pub mod state;
+pub mod symbols;
+pub mod terminal;
+pub mod trace;
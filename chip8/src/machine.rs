@@ -0,0 +1,530 @@
+use crate::chip::{Chip8, Frame};
+use crate::debug::{DebugEvent, StopCondition, WatchHit};
+use crate::error::ChipError;
+use crate::movie::{Desync, Player, Recorder};
+use crate::rewind::RewindBuffer;
+use crate::scheduler::{Due, Scheduler, TIMER_HZ};
+use std::time::{Duration, Instant};
+
+//how long a loop should take when real time, sleeps if present comes back sooner
+const FRAME_TIME: Duration = Duration::from_nanos(1_000_000_000 / TIMER_HZ as u64);
+
+//Frontends plug into a Machine through these three traits, the machine owns the chip
+//and the timing loop so a frontend only shows frames, collects input and beeps.
+
+//Where the screen goes, e.g. a window or a terminal
+pub trait FrameSink {
+    //called once per loop with the whole screen
+    fn present(&mut self, frame: &Frame);
+    //something the user may want to know about, ignored by default
+    fn event(&mut self, _chip: &Chip8, _event: &MachineEvent) {}
+}
+
+//Where input comes from, polled once per loop
+pub trait InputSource {
+    //everything since the last poll. The chip is there for frontends that save or inspect it
+    fn poll(&mut self, chip: &Chip8) -> Vec<Input>;
+}
+
+pub trait AudioSink {
+    //on while the sound timer is running
+    fn set_active(&mut self, active: bool);
+}
+
+//no input, no screen and no sound
+impl InputSource for () {
+    fn poll(&mut self, _chip: &Chip8) -> Vec<Input> {
+        Vec::new()
+    }
+}
+
+impl FrameSink for () {
+    fn present(&mut self, _frame: &Frame) {}
+}
+
+impl AudioSink for () {
+    fn set_active(&mut self, _active: bool) {}
+}
+
+//None when there's no audio device, carries on silently
+impl<T: AudioSink> AudioSink for Option<T> {
+    fn set_active(&mut self, active: bool) {
+        if let Some(sink) = self {
+            sink.set_active(active);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Input {
+    //a keypad key 0x0-0xF going down or up
+    Key { key: u8, pressed: bool },
+    Quit,
+    //starts the rom over
+    Restart,
+    //pauses or resumes
+    Pause,
+    //only while paused
+    Step(Step),
+    //runs backwards a frame at a time while on
+    Rewind(bool),
+    //replaces the whole machine with a save state
+    LoadState(Vec<u8>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Into,
+    Over,
+    Out,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MachineEvent {
+    //from Input::Pause
+    Paused,
+    Resumed,
+    //paused before running the instruction at a breakpoint
+    Breakpoint(u16),
+    //paused after a step
+    Stopped(u16),
+    //paused after an instruction touched watched memory, with every access it made
+    Watchpoint(Vec<WatchHit>),
+    StateLoaded,
+    StateRejected(ChipError),
+    Desync(Desync),
+    ReplayFinished,
+}
+
+//Why Machine::run returned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    Quit,
+    //ran max_frames
+    Frames,
+    //these only end runs that aren't real time, nobody is there to carry on
+    Halted,
+    Paused,
+    ReplayFinished,
+}
+
+//A movie being recorded or played back. Loading states, rewinding and restarting would
+//break it, so the machine ignores them while there is one
+pub enum MovieMode {
+    Recording(Recorder),
+    Playing(Player),
+}
+
+//Runs a chip against the wall clock, or as fast as possible when not real time,
+//with a debugger, rewinding and movies on top
+pub struct Machine {
+    chip: Chip8,
+    scheduler: Scheduler,
+    instructions_per_frame: u32,
+    realtime: bool,
+    max_frames: Option<u64>,
+    frames: u64,
+    //one snapshot per frame
+    rewind: Option<RewindBuffer>,
+    rewinding: bool,
+    paused: bool,
+    //what hands control back to the debugger while running
+    stop: StopCondition,
+    movie: Option<MovieMode>,
+}
+
+impl Machine {
+    pub fn new(chip: Chip8, instructions_per_second: u32) -> Self {
+        Machine {
+            chip,
+            scheduler: Scheduler::new(instructions_per_second),
+            instructions_per_frame: instructions_per_second.div_ceil(TIMER_HZ),
+            realtime: true,
+            max_frames: None,
+            frames: 0,
+            rewind: None,
+            rewinding: false,
+            paused: false,
+            stop: StopCondition::Breakpoint,
+            movie: None,
+        }
+    }
+
+    pub fn chip(&self) -> &Chip8 {
+        &self.chip
+    }
+
+    pub fn chip_mut(&mut self) -> &mut Chip8 {
+        &mut self.chip
+    }
+
+    //When off every loop runs one frame's worth of instructions without waiting
+    pub fn set_realtime(&mut self, realtime: bool) {
+        self.realtime = realtime;
+    }
+
+    pub fn set_max_frames(&mut self, max_frames: Option<u64>) {
+        self.max_frames = max_frames;
+    }
+
+    //60hz frames whose timers have ticked
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn set_rewind(&mut self, rewind: RewindBuffer) {
+        self.rewind = Some(rewind);
+    }
+
+    pub fn set_movie(&mut self, movie: MovieMode) {
+        self.movie = Some(movie);
+    }
+
+    //the movie, unless a replay already finished
+    pub fn take_movie(&mut self) -> Option<MovieMode> {
+        self.movie.take()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    //Loops until quit, max_frames or an error. Each loop polls input, runs whatever
+    //is due, sets the audio and presents the screen
+    pub fn run(
+        &mut self,
+        input: &mut impl InputSource,
+        display: &mut impl FrameSink,
+        audio: &mut impl AudioSink,
+    ) -> Result<Exit, ChipError> {
+        self.scheduler.reset();
+        self.scheduler.update(Instant::now());
+        loop {
+            let started = Instant::now();
+            for event in input.poll(&self.chip) {
+                if let Some(exit) = self.handle(event, display)? {
+                    return Ok(exit);
+                }
+            }
+            let due = if self.realtime {
+                self.scheduler.update(Instant::now())
+            } else {
+                Due {
+                    instructions: self.instructions_per_frame,
+                    timer_ticks: 1,
+                }
+            };
+            let exit = self.update(due, display)?;
+            let running = !self.paused && !self.rewinding;
+            audio.set_active(running && self.chip.is_sound_active());
+            display.present(&self.chip.get_display());
+            if let Some(exit) = exit {
+                return Ok(exit);
+            }
+            //vsync may have already waited in present
+            if self.realtime {
+                if let Some(rest) = FRAME_TIME.checked_sub(started.elapsed()) {
+                    std::thread::sleep(rest);
+                }
+            }
+        }
+    }
+
+    fn handle(
+        &mut self,
+        input: Input,
+        display: &mut impl FrameSink,
+    ) -> Result<Option<Exit>, ChipError> {
+        match input {
+            Input::Quit => return Ok(Some(Exit::Quit)),
+            Input::Key { key, pressed } => match &mut self.movie {
+                Some(MovieMode::Recording(recorder)) => recorder.key(key, pressed),
+                //the keys come from the movie
+                Some(MovieMode::Playing(_)) => (),
+                None if pressed => self.chip.key_press(key)?,
+                None => self.chip.key_release(key)?,
+            },
+            Input::Restart if self.movie.is_none() => self.chip.restart(),
+            Input::Pause => {
+                self.paused = !self.paused;
+                self.stop = StopCondition::Breakpoint;
+                let event = if self.paused {
+                    MachineEvent::Paused
+                } else {
+                    MachineEvent::Resumed
+                };
+                display.event(&self.chip, &event);
+            }
+            //movies run whole frames, a step would put it out of line
+            Input::Step(step) if self.paused && self.movie.is_none() => {
+                self.stop = match step {
+                    Step::Into => StopCondition::Step,
+                    Step::Over => self.chip.step_over_condition(),
+                    Step::Out => self.chip.step_out_condition(),
+                };
+                self.paused = false;
+                display.event(&self.chip, &MachineEvent::Resumed);
+            }
+            Input::Rewind(on) => {
+                self.rewinding = on && self.rewind.is_some() && self.movie.is_none();
+            }
+            Input::LoadState(state) if self.movie.is_none() => {
+                let event = match self.chip.load_state(&state) {
+                    Ok(()) => MachineEvent::StateLoaded,
+                    Err(e) => MachineEvent::StateRejected(e),
+                };
+                display.event(&self.chip, &event);
+            }
+            Input::Restart | Input::Step(_) | Input::LoadState(_) => (),
+        }
+        Ok(None)
+    }
+
+    fn update(
+        &mut self,
+        due: Due,
+        display: &mut impl FrameSink,
+    ) -> Result<Option<Exit>, ChipError> {
+        if self.rewinding {
+            //stays on the oldest frame once we run out
+            if let Some(rewind) = self.rewind.as_mut() {
+                for _ in 0..due.timer_ticks {
+                    if let Some(state) = rewind.pop() {
+                        self.chip.load_state(&state)?;
+                    }
+                }
+            }
+            return Ok(None);
+        }
+        if self.paused {
+            return Ok((!self.realtime).then_some(Exit::Paused));
+        }
+        if self.movie.is_some() {
+            return self.movie_frames(due.timer_ticks, display);
+        }
+
+        let event = match self.chip.run_until(self.stop, due.instructions)? {
+            DebugEvent::InstructionLimit => None,
+            DebugEvent::Halted if self.realtime => None,
+            DebugEvent::Halted => return Ok(Some(Exit::Halted)),
+            DebugEvent::Breakpoint(pc) => Some(MachineEvent::Breakpoint(pc)),
+            DebugEvent::Stopped(pc) => Some(MachineEvent::Stopped(pc)),
+            DebugEvent::Watchpoint(_) => {
+                Some(MachineEvent::Watchpoint(self.chip.watch_hits().to_vec()))
+            }
+        };
+        if let Some(event) = event {
+            self.paused = true;
+            self.stop = StopCondition::Breakpoint;
+            display.event(&self.chip, &event);
+            return Ok((!self.realtime).then_some(Exit::Paused));
+        }
+        for _ in 0..due.timer_ticks {
+            self.chip.tick_timers();
+            if let Some(rewind) = self.rewind.as_mut() {
+                rewind.push(self.chip.save_state());
+            }
+            if self.count_frame() {
+                return Ok(Some(Exit::Frames));
+            }
+        }
+        Ok(None)
+    }
+
+    //movies run whole frames, so instructions only run as the timers tick
+    fn movie_frames(
+        &mut self,
+        timer_ticks: u32,
+        display: &mut impl FrameSink,
+    ) -> Result<Option<Exit>, ChipError> {
+        for _ in 0..timer_ticks {
+            match &mut self.movie {
+                Some(MovieMode::Recording(recorder)) => recorder.frame(&mut self.chip)?,
+                Some(MovieMode::Playing(player)) => {
+                    if let Some(desync) = player.frame(&mut self.chip)? {
+                        display.event(&self.chip, &MachineEvent::Desync(desync));
+                    }
+                    if player.is_finished() {
+                        //carries on as normal emulation
+                        self.movie = None;
+                        display.event(&self.chip, &MachineEvent::ReplayFinished);
+                        self.count_frame();
+                        return Ok((!self.realtime).then_some(Exit::ReplayFinished));
+                    }
+                }
+                None => break,
+            }
+            if self.count_frame() {
+                return Ok(Some(Exit::Frames));
+            }
+        }
+        Ok(None)
+    }
+
+    //true once max_frames is reached
+    fn count_frame(&mut self) -> bool {
+        self.frames += 1;
+        self.max_frames == Some(self.frames)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debug::Register;
+    use crate::movie::DEFAULT_CHECKSUM_INTERVAL;
+    use crate::quirks::Quirks;
+
+    //hands out one batch of input per poll
+    struct Script(Vec<Vec<Input>>);
+
+    impl InputSource for Script {
+        fn poll(&mut self, _chip: &Chip8) -> Vec<Input> {
+            if self.0.is_empty() {
+                Vec::new()
+            } else {
+                self.0.remove(0)
+            }
+        }
+    }
+
+    #[derive(Default)]
+    struct Screen {
+        presented: usize,
+        events: Vec<MachineEvent>,
+    }
+
+    impl FrameSink for Screen {
+        fn present(&mut self, _frame: &Frame) {
+            self.presented += 1;
+        }
+
+        fn event(&mut self, _chip: &Chip8, event: &MachineEvent) {
+            self.events.push(event.clone());
+        }
+    }
+
+    //200 LD V1, 5; 202 ADD V0, 1; 204 SKNP V1; 206 ADD V2, 1; 208 JP 0x202
+    const ROM: [u8; 10] = [0x61, 0x05, 0x70, 0x01, 0xE1, 0xA1, 0x72, 0x01, 0x12, 0x02];
+
+    //4 instructions a frame, the loop is 3 without key 5 and 4 with it
+    fn machine() -> Machine {
+        let mut chip = Chip8::with_seed(Quirks::default(), 0);
+        chip.load_rom(&ROM).unwrap();
+        let mut machine = Machine::new(chip, 4 * TIMER_HZ);
+        machine.set_realtime(false);
+        machine
+    }
+
+    #[test]
+    fn test_frames_and_keys() {
+        let mut machine = machine();
+        machine.set_max_frames(Some(3));
+        let mut input = Script(vec![
+            vec![],
+            vec![Input::Key {
+                key: 5,
+                pressed: true,
+            }],
+        ]);
+        let mut screen = Screen::default();
+        assert_eq!(
+            Ok(Exit::Frames),
+            machine.run(&mut input, &mut screen, &mut ())
+        );
+        assert_eq!(3, machine.frames());
+        assert_eq!(3, screen.presented);
+        assert_eq!(3, machine.chip().register(Register::V(0)));
+        //skipped once the key went down
+        assert_eq!(2, machine.chip().register(Register::V(2)));
+
+        let mut input = Script(vec![vec![Input::Quit]]);
+        assert_eq!(
+            Ok(Exit::Quit),
+            machine.run(&mut input, &mut screen, &mut ())
+        );
+        assert_eq!(3, screen.presented);
+    }
+
+    #[test]
+    fn test_debugger() {
+        let mut machine = machine();
+        machine.chip_mut().add_breakpoint(0x204, None);
+        let mut screen = Screen::default();
+        assert_eq!(Ok(Exit::Paused), machine.run(&mut (), &mut screen, &mut ()));
+        assert_eq!(vec![MachineEvent::Breakpoint(0x204)], screen.events);
+        assert!(machine.is_paused());
+
+        let mut input = Script(vec![vec![Input::Step(Step::Into)]]);
+        assert_eq!(
+            Ok(Exit::Paused),
+            machine.run(&mut input, &mut screen, &mut ())
+        );
+        //no key, so it skipped
+        assert_eq!(
+            &[MachineEvent::Resumed, MachineEvent::Stopped(0x208)],
+            &screen.events[1..]
+        );
+
+        //steps are ignored unless paused
+        let mut input = Script(vec![
+            vec![Input::Pause, Input::Step(Step::Into)],
+            vec![Input::Quit],
+        ]);
+        assert_eq!(
+            Ok(Exit::Paused),
+            machine.run(&mut input, &mut screen, &mut ())
+        );
+        assert_eq!(MachineEvent::Breakpoint(0x204), screen.events[4]);
+        assert_eq!(5, screen.events.len());
+    }
+
+    #[test]
+    fn test_rewind() {
+        let mut machine = machine();
+        machine.set_rewind(RewindBuffer::new(10, usize::MAX));
+        machine.set_max_frames(Some(3));
+        machine.run(&mut (), &mut (), &mut ()).unwrap();
+        assert_eq!(4, machine.chip().register(Register::V(0)));
+
+        let mut input = Script(vec![vec![Input::Rewind(true)], vec![], vec![Input::Quit]]);
+        machine.run(&mut input, &mut (), &mut ()).unwrap();
+        //back two frames to the end of the first
+        assert_eq!(1, machine.chip().register(Register::V(0)));
+        assert_eq!(3, machine.frames());
+    }
+
+    #[test]
+    fn test_movie() {
+        let mut machine = machine();
+        let recorder = Recorder::new(machine.chip(), &ROM, 4, DEFAULT_CHECKSUM_INTERVAL);
+        machine.set_movie(MovieMode::Recording(recorder));
+        machine.set_max_frames(Some(5));
+        let press = |key, pressed| Input::Key { key, pressed };
+        let mut input = Script(vec![
+            vec![press(5, true)],
+            vec![Input::Restart],
+            vec![press(5, false)],
+        ]);
+        machine.run(&mut input, &mut (), &mut ()).unwrap();
+        let Some(MovieMode::Recording(recorder)) = machine.take_movie() else {
+            panic!("expected a recording");
+        };
+        let movie = recorder.finish();
+        assert_eq!(5, movie.frames);
+        assert_eq!(2, movie.keys.len());
+        //restart was ignored
+        assert_eq!(6, machine.chip().register(Register::V(0)));
+
+        let mut replay = Machine::new(movie.start(&ROM).unwrap(), 4 * TIMER_HZ);
+        replay.set_realtime(false);
+        replay.set_movie(MovieMode::Playing(Player::new(movie)));
+        let mut screen = Screen::default();
+        assert_eq!(
+            Ok(Exit::ReplayFinished),
+            replay.run(&mut (), &mut screen, &mut ())
+        );
+        assert_eq!(vec![MachineEvent::ReplayFinished], screen.events);
+        assert_eq!(machine.chip().save_state(), replay.chip().save_state());
+    }
+}
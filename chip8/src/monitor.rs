@@ -0,0 +1,293 @@
+use crate::chip::Chip8;
+use crate::debug::{parse_value, Condition, DebugEvent, Register, StopCondition, Watchpoint};
+use crate::disassembler::Line;
+use crate::error::ChipError;
+use crate::opcode::Opcode;
+use std::fmt;
+use std::str::FromStr;
+
+//a step over a call that never returns gives up after this many instructions
+pub const MAX_STEP_OVER: u32 = 1_000_000;
+//how far ahead of pc next_sprite looks for a draw
+const SPRITE_LOOKAHEAD: u16 = 16;
+
+pub const HELP: &str = "\
+break ADDR[,COND]  stop before ADDR, e.g. break 0x2A4 or break 0x2A4,V3 == 0x10
+delete ADDR        remove the breakpoint at ADDR
+step [N]           run N instructions [default: 1]
+next               step over a call
+continue           run in real time until a breakpoint, F6 or Esc
+poke ADDR BYTE...  write bytes to memory
+watch RANGE        stop on memory access, e.g. watch 0x300-0x30F,rw
+unwatch RANGE      remove a watchpoint
+quit";
+
+//The terminal debugger's command line, each command has a one letter short form
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Break(u16, Option<Condition>),
+    Delete(u16),
+    Step(u32),
+    Next,
+    Continue,
+    Poke(u16, Vec<u8>),
+    Watch(Watchpoint),
+    Unwatch(Watchpoint),
+    Help,
+    Quit,
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let raw = raw.trim();
+        let (name, rest) = raw.split_once(char::is_whitespace).unwrap_or((raw, ""));
+        let rest = rest.trim();
+        let command = match name {
+            "break" | "b" => {
+                //the condition follows a comma like --break, or just a space
+                match rest.split_once(|c: char| c == ',' || c.is_whitespace()) {
+                    Some((addr, condition)) => {
+                        Command::Break(parse_value(addr)?, Some(condition.trim().parse()?))
+                    }
+                    None => Command::Break(parse_value(rest)?, None),
+                }
+            }
+            "delete" | "d" => Command::Delete(parse_value(rest)?),
+            "step" | "s" if rest.is_empty() => Command::Step(1),
+            "step" | "s" => Command::Step(
+                rest.parse()
+                    .map_err(|_| format!("invalid step count '{}'", rest))?,
+            ),
+            "next" | "n" => Command::Next,
+            "continue" | "c" => Command::Continue,
+            "poke" | "p" => {
+                let mut values = rest.split_whitespace().map(parse_value);
+                let addr = values.next().ok_or("poke needs an address")??;
+                let bytes = values
+                    .map(|value| {
+                        let value = value?;
+                        u8::try_from(value).map_err(|_| format!("{:#X} isn't a byte", value))
+                    })
+                    .collect::<Result<Vec<u8>, String>>()?;
+                if bytes.is_empty() {
+                    return Err("poke needs at least one byte".to_string());
+                }
+                Command::Poke(addr, bytes)
+            }
+            "watch" | "w" => Command::Watch(rest.parse()?),
+            "unwatch" | "u" => Command::Unwatch(rest.parse()?),
+            "help" | "h" => Command::Help,
+            "quit" | "q" => Command::Quit,
+            _ => return Err(format!("unknown command '{}', try help", name)),
+        };
+        Ok(command)
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::Break(addr, None) => write!(f, "break {:#05X}", addr),
+            Command::Break(addr, Some(condition)) => {
+                write!(f, "break {:#05X},{}", addr, condition)
+            }
+            Command::Delete(addr) => write!(f, "delete {:#05X}", addr),
+            Command::Step(count) => write!(f, "step {}", count),
+            Command::Next => write!(f, "next"),
+            Command::Continue => write!(f, "continue"),
+            Command::Poke(addr, bytes) => {
+                write!(f, "poke {:#05X}", addr)?;
+                bytes
+                    .iter()
+                    .try_for_each(|byte| write!(f, " {:#04X}", byte))
+            }
+            Command::Watch(watchpoint) => write!(f, "watch {}", range(watchpoint)),
+            Command::Unwatch(watchpoint) => write!(f, "unwatch {}", range(watchpoint)),
+            Command::Help => write!(f, "help"),
+            Command::Quit => write!(f, "quit"),
+        }
+    }
+}
+
+//0x300-0x30F,rw
+fn range(watchpoint: &Watchpoint) -> String {
+    let flags: String = [
+        (watchpoint.read, 'r'),
+        (watchpoint.write, 'w'),
+        (watchpoint.execute, 'x'),
+    ]
+    .iter()
+    .filter(|(on, _)| *on)
+    .map(|(_, flag)| *flag)
+    .collect();
+    format!(
+        "{:#05X}-{:#05X},{}",
+        watchpoint.start, watchpoint.end, flags
+    )
+}
+
+//Runs a command that doesn't need the clock and says what happened.
+//Continue, Help and Quit are up to the caller
+pub fn apply(chip: &mut Chip8, command: &Command) -> Result<String, ChipError> {
+    let message = match command {
+        Command::Break(addr, condition) => {
+            chip.add_breakpoint(*addr, *condition);
+            format!("Breakpoint at {:#05X}", addr)
+        }
+        Command::Delete(addr) if chip.remove_breakpoint(*addr) => {
+            format!("Deleted the breakpoint at {:#05X}", addr)
+        }
+        Command::Delete(addr) => format!("No breakpoint at {:#05X}", addr),
+        Command::Step(count) => {
+            let event = chip.run_until(StopCondition::Breakpoint, *count)?;
+            describe(chip, &event)
+        }
+        Command::Next => {
+            let event = chip.run_until(chip.step_over_condition(), MAX_STEP_OVER)?;
+            describe(chip, &event)
+        }
+        Command::Poke(addr, bytes) => {
+            for (offset, byte) in bytes.iter().enumerate() {
+                chip.poke(addr.wrapping_add(offset as u16), *byte);
+            }
+            format!("Wrote {} bytes at {:#05X}", bytes.len(), addr)
+        }
+        Command::Watch(watchpoint) => {
+            chip.add_watchpoint(*watchpoint);
+            format!("Watching {}", range(watchpoint))
+        }
+        Command::Unwatch(watchpoint) if chip.remove_watchpoint(watchpoint) => {
+            format!("Stopped watching {}", range(watchpoint))
+        }
+        Command::Unwatch(watchpoint) => format!("Not watching {}", range(watchpoint)),
+        Command::Continue | Command::Help | Command::Quit => String::new(),
+    };
+    Ok(message)
+}
+
+//one line about why the chip stopped
+pub fn describe(chip: &Chip8, event: &DebugEvent) -> String {
+    match event {
+        DebugEvent::Breakpoint(pc) => format!("Breakpoint at {:#05X}", pc),
+        DebugEvent::Stopped(pc) => format!("Stopped at {:#05X}", pc),
+        DebugEvent::Watchpoint(hit) => format!("Watchpoint {}", hit),
+        DebugEvent::Halted => "Halted".to_string(),
+        DebugEvent::InstructionLimit => {
+            format!("Stopped at {:#05X}", chip.register(Register::Pc))
+        }
+    }
+}
+
+//count instructions starting before of them ahead of pc. Goes two bytes at a time from pc,
+//so it can't tell data from code and may be out of step before pc
+pub fn disassembly(chip: &Chip8, pc: u16, before: u16, count: u16) -> Vec<Line> {
+    let start = pc.saturating_sub(before * 2);
+    (0..count)
+        .map(|i| {
+            let addr = start.wrapping_add(i * 2);
+            let opcode = chip.opcode_at(addr);
+            Line {
+                addr,
+                bytes: opcode.to_be_bytes().to_vec(),
+                text: Opcode::decode(opcode).to_string(),
+            }
+        })
+        .collect()
+}
+
+//How many bytes from I the next draw will read, from the first DRW in the next few
+//instructions. DRW with a height of 0 draws a 16x16 sprite, 32 bytes
+pub fn next_sprite(chip: &Chip8) -> Option<usize> {
+    let pc = chip.register(Register::Pc);
+    (0..SPRITE_LOOKAHEAD).find_map(|i| {
+        match Opcode::decode(chip.opcode_at(pc.wrapping_add(i * 2))) {
+            Opcode::Draw { n: 0, .. } => Some(32),
+            Opcode::Draw { n, .. } => Some(n as usize),
+            _ => None,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirks::Quirks;
+
+    fn parse(raw: &str) -> Result<Command, String> {
+        raw.parse()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Ok(Command::Break(0x2A4, None)), parse("break 0x2A4"));
+        assert_eq!(
+            Ok(Command::Break(0x2A4, Some("V3 == 0x10".parse().unwrap()))),
+            parse("b 0x2A4 V3 == 0x10")
+        );
+        assert_eq!(parse("b 0x2A4 V3 == 0x10"), parse("b 0x2A4,V3 == 0x10"));
+        assert_eq!(Ok(Command::Step(1)), parse("  s "));
+        assert_eq!(Ok(Command::Step(20)), parse("step 20"));
+        assert_eq!(
+            Ok(Command::Poke(0x300, vec![1, 0xFF])),
+            parse("poke 0x300 1 0xff")
+        );
+        assert_eq!(
+            Ok(Command::Watch("0x300-0x30F,rw".parse().unwrap())),
+            parse("watch 0x300-0x30F,rw")
+        );
+        assert_eq!(Ok(Command::Continue), parse("c"));
+        for command in ["break 0x2A4,V3 == 0x10", "poke 0x300 1 2", "watch 0x300,rx"] {
+            let parsed = parse(command).unwrap();
+            assert_eq!(Ok(parsed.clone()), parse(&parsed.to_string()));
+        }
+
+        assert!(parse("break").is_err());
+        assert!(parse("step x").is_err());
+        assert!(parse("poke 0x300").is_err());
+        assert!(parse("poke 0x300 0x100").is_err());
+        assert!(parse("jump 0x200").is_err());
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut chip = Chip8::with_seed(Quirks::default(), 0);
+        //200 ADD V0, 1; 202 CALL 0x208; 204 ADD V0, 1; 206 JP 0x200; 208 ADD V1, 1; 20A RET
+        chip.load_rom(&[
+            0x70, 0x01, 0x22, 0x08, 0x70, 0x01, 0x12, 0x00, 0x71, 0x01, 0x00, 0xEE,
+        ])
+        .unwrap();
+        let mut run = |command: &str| apply(&mut chip, &command.parse().unwrap()).unwrap();
+        assert_eq!("Breakpoint at 0x204", run("break 0x204"));
+        assert_eq!("Stopped at 0x202", run("step"));
+        assert_eq!("Stopped at 0x204", run("next"));
+        assert_eq!("Breakpoint at 0x204", run("step 10"));
+        assert_eq!("Deleted the breakpoint at 0x204", run("d 0x204"));
+        assert_eq!("No breakpoint at 0x204", run("d 0x204"));
+        assert_eq!("Wrote 2 bytes at 0x300", run("poke 0x300 0xAB 0xCD"));
+        assert_eq!("Watching 0x300-0x301,w", run("watch 0x300-0x301"));
+        assert_eq!("Stopped watching 0x300-0x301,w", run("unwatch 0x300-0x301"));
+        assert_eq!([0xAB, 0xCD], chip.memory()[0x300..0x302]);
+        assert_eq!(3, chip.register(Register::V(0)));
+        assert_eq!(2, chip.register(Register::V(1)));
+    }
+
+    #[test]
+    fn test_views() {
+        let mut chip = Chip8::with_seed(Quirks::default(), 0);
+        //200 LD I, 0x300; 202 ADD V0, 1; 204 DRW V0, V1, 5
+        chip.load_rom(&[0xA3, 0x00, 0x70, 0x01, 0xD0, 0x15])
+            .unwrap();
+        let lines = disassembly(&chip, 0x202, 1, 3);
+        assert_eq!(
+            vec!["200: A300      LD I, 0x300", "202: 7001      ADD V0, 0x01"],
+            lines[..2].iter().map(Line::to_string).collect::<Vec<_>>()
+        );
+        assert_eq!(0x204, lines[2].addr);
+        assert_eq!(Some(5), next_sprite(&chip));
+        chip.poke(0x204, 0x00);
+        chip.poke(0x205, 0xE0);
+        assert_eq!(None, next_sprite(&chip));
+    }
+}
@@ -0,0 +1,450 @@
+use crate::chip::Chip8;
+use crate::error::{ChipError, MovieError};
+use crate::headless::KeyEvent;
+use crate::quirks::Quirks;
+use crate::state::crc32;
+use std::fmt;
+use std::str::FromStr;
+
+const MOVIE_HEADER: &str = "chip8-movie";
+const MOVIE_VERSION: u32 = 1;
+//a checksum a second is cheap and pins a desync down closely enough
+pub const DEFAULT_CHECKSUM_INTERVAL: u64 = 60;
+
+//Checksum of the whole machine after a frame, compared when replaying
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint {
+    //frames run so far
+    pub frame: u64,
+    pub checksum: u32,
+}
+
+//Everything needed to replay a session exactly: the rom it ran, how the chip was set up
+//and every key event by frame. Each frame applies its key events, runs
+//instructions_per_frame instructions and then ticks the timers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_hash: u32,
+    pub quirks: Quirks,
+    pub seed: u64,
+    pub instructions_per_frame: u32,
+    //length of the recording
+    pub frames: u64,
+    pub keys: Vec<KeyEvent>,
+    pub checkpoints: Vec<Checkpoint>,
+}
+
+//Text, one item per line:
+//chip8-movie 1
+//rom 1a2b3c4d
+//quirks 0x00
+//seed 0
+//ipf 10
+//frames 600
+//key 30:+5
+//checksum 60 0badf00d
+impl fmt::Display for Movie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} {}", MOVIE_HEADER, MOVIE_VERSION)?;
+        writeln!(f, "rom {:08x}", self.rom_hash)?;
+        writeln!(f, "quirks {:#04x}", self.quirks.to_bits())?;
+        writeln!(f, "seed {}", self.seed)?;
+        writeln!(f, "ipf {}", self.instructions_per_frame)?;
+        writeln!(f, "frames {}", self.frames)?;
+        for key in &self.keys {
+            writeln!(f, "key {}", key)?;
+        }
+        for checkpoint in &self.checkpoints {
+            writeln!(
+                f,
+                "checksum {} {:08x}",
+                checkpoint.frame, checkpoint.checksum
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Movie {
+    type Err = MovieError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+        let parse_error = |line, message: String| MovieError::Parse { line, message };
+
+        match lines.next() {
+            Some((_, line)) if line == format!("{} {}", MOVIE_HEADER, MOVIE_VERSION) => (),
+            Some((number, line)) if line.starts_with(MOVIE_HEADER) => {
+                return Err(parse_error(number, format!("unsupported movie '{}'", line)))
+            }
+            _ => return Err(parse_error(1, "not a movie file".to_string())),
+        }
+
+        let (mut rom_hash, mut quirks, mut seed, mut ipf, mut frames) =
+            (None, None, None, None, None);
+        let mut keys = Vec::new();
+        let mut checkpoints = Vec::new();
+        for (number, line) in lines {
+            let (name, value) = line.split_once(' ').unwrap_or((line, ""));
+            let value = value.trim();
+            let invalid = || parse_error(number, format!("invalid {} '{}'", name, value));
+            match name {
+                "rom" => rom_hash = Some(u32::from_str_radix(value, 16).map_err(|_| invalid())?),
+                "quirks" => {
+                    let bits = value.strip_prefix("0x").unwrap_or(value);
+                    let bits = u8::from_str_radix(bits, 16).map_err(|_| invalid())?;
+                    quirks = Some(Quirks::from_bits(bits));
+                }
+                "seed" => seed = Some(value.parse().map_err(|_| invalid())?),
+                "ipf" => ipf = Some(value.parse().map_err(|_| invalid())?),
+                "frames" => frames = Some(value.parse().map_err(|_| invalid())?),
+                "key" => keys.push(value.parse().map_err(|e| parse_error(number, e))?),
+                "checksum" => {
+                    let (frame, checksum) = value.split_once(' ').ok_or_else(invalid)?;
+                    checkpoints.push(Checkpoint {
+                        frame: frame.parse().map_err(|_| invalid())?,
+                        checksum: u32::from_str_radix(checksum.trim(), 16)
+                            .map_err(|_| invalid())?,
+                    });
+                }
+                _ => return Err(parse_error(number, format!("unknown item '{}'", name))),
+            }
+        }
+        let missing = |name: &str| parse_error(1, format!("movie has no {}", name));
+        let mut movie = Movie {
+            rom_hash: rom_hash.ok_or_else(|| missing("rom"))?,
+            quirks: quirks.ok_or_else(|| missing("quirks"))?,
+            seed: seed.ok_or_else(|| missing("seed"))?,
+            instructions_per_frame: ipf.ok_or_else(|| missing("ipf"))?,
+            frames: frames.ok_or_else(|| missing("frames"))?,
+            keys,
+            checkpoints,
+        };
+        //stable, so events in the same frame keep their order
+        movie.keys.sort_by_key(|key| key.frame);
+        movie.checkpoints.sort_by_key(|checkpoint| checkpoint.frame);
+        Ok(movie)
+    }
+}
+
+impl Movie {
+    //A fresh chip set up the way the movie was recorded, with rom loaded
+    pub fn start(&self, rom: &[u8]) -> Result<Chip8, MovieError> {
+        let actual = crc32(rom);
+        if actual != self.rom_hash {
+            return Err(MovieError::RomMismatch {
+                expected: self.rom_hash,
+                actual,
+            });
+        }
+        let mut chip = Chip8::with_seed(self.quirks, self.seed);
+        chip.load_rom(rom)?;
+        Ok(chip)
+    }
+}
+
+//The whole machine, save states cover everything that affects what runs next.
+//They end with the crc32 of their contents, so that is the checksum
+pub fn checksum(chip: &Chip8) -> u32 {
+    let state = chip.save_state();
+    let crc: [u8; 4] = state[state.len() - 4..].try_into().unwrap();
+    u32::from_le_bytes(crc)
+}
+
+fn run_frame(chip: &mut Chip8, keys: &[KeyEvent], instructions: u32) -> Result<(), ChipError> {
+    for key in keys {
+        if key.pressed {
+            chip.key_press(key.key)?;
+        } else {
+            chip.key_release(key.key)?;
+        }
+    }
+    for _ in 0..instructions {
+        chip.tick()?;
+    }
+    chip.tick_timers();
+    Ok(())
+}
+
+//Records a movie of a chip, which has to be freshly set up with the rom loaded.
+//Keys go through the recorder rather than straight to the chip,
+//so they land at the start of a frame and can be replayed at the same point
+#[derive(Debug)]
+pub struct Recorder {
+    movie: Movie,
+    //key events for the next frame
+    pending: Vec<KeyEvent>,
+    checksum_interval: u64,
+}
+
+impl Recorder {
+    pub fn new(
+        chip: &Chip8,
+        rom: &[u8],
+        instructions_per_frame: u32,
+        checksum_interval: u64,
+    ) -> Self {
+        Recorder {
+            movie: Movie {
+                rom_hash: crc32(rom),
+                quirks: chip.quirks(),
+                seed: chip.seed(),
+                instructions_per_frame,
+                frames: 0,
+                keys: Vec::new(),
+                checkpoints: Vec::new(),
+            },
+            pending: Vec::new(),
+            checksum_interval: checksum_interval.max(1),
+        }
+    }
+
+    pub fn key(&mut self, key: u8, pressed: bool) {
+        self.pending.push(KeyEvent {
+            frame: self.movie.frames,
+            key,
+            pressed,
+        });
+    }
+
+    pub fn frame(&mut self, chip: &mut Chip8) -> Result<(), ChipError> {
+        let keys = std::mem::take(&mut self.pending);
+        let result = run_frame(chip, &keys, self.movie.instructions_per_frame);
+        //keep the keys even if the frame faulted, replaying should fault the same way
+        self.movie.keys.extend(keys);
+        result?;
+        self.movie.frames += 1;
+        if self.movie.frames.is_multiple_of(self.checksum_interval) {
+            self.movie.checkpoints.push(Checkpoint {
+                frame: self.movie.frames,
+                checksum: checksum(chip),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.movie.frames
+    }
+
+    pub fn finish(self) -> Movie {
+        self.movie
+    }
+}
+
+//A checkpoint where the replay didn't match the recording
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Desync {
+    pub frame: u64,
+    pub expected: u32,
+    pub actual: u32,
+}
+
+impl fmt::Display for Desync {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "desync at frame {}: expected checksum {:08x}, got {:08x}",
+            self.frame, self.expected, self.actual
+        )
+    }
+}
+
+//Plays a movie back a frame at a time on a chip from Movie::start
+#[derive(Debug)]
+pub struct Player {
+    movie: Movie,
+    frame: u64,
+    next_key: usize,
+    next_checkpoint: usize,
+}
+
+impl Player {
+    pub fn new(movie: Movie) -> Self {
+        Player {
+            movie,
+            frame: 0,
+            next_key: 0,
+            next_checkpoint: 0,
+        }
+    }
+
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+
+    pub fn frame_number(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frame >= self.movie.frames
+    }
+
+    //runs the next frame, returning a desync if it ended on a checkpoint that doesn't match
+    pub fn frame(&mut self, chip: &mut Chip8) -> Result<Option<Desync>, ChipError> {
+        let keys = &self.movie.keys[self.next_key..];
+        let count = keys
+            .iter()
+            .take_while(|key| key.frame <= self.frame)
+            .count();
+        run_frame(chip, &keys[..count], self.movie.instructions_per_frame)?;
+        self.next_key += count;
+        self.frame += 1;
+
+        let checkpoints = &self.movie.checkpoints[self.next_checkpoint..];
+        let Some(checkpoint) = checkpoints.first().filter(|c| c.frame <= self.frame) else {
+            return Ok(None);
+        };
+        self.next_checkpoint += 1;
+        let actual = checksum(chip);
+        Ok((actual != checkpoint.checksum).then_some(Desync {
+            frame: self.frame,
+            expected: checkpoint.checksum,
+            actual,
+        }))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayReport {
+    pub frames: u64,
+    //every checkpoint that didn't match, the first is where things went wrong
+    pub desyncs: Vec<Desync>,
+}
+
+//Replays the whole movie as fast as possible and checks every checkpoint
+pub fn replay(movie: &Movie, rom: &[u8]) -> Result<ReplayReport, MovieError> {
+    let mut chip = movie.start(rom)?;
+    let mut player = Player::new(movie.clone());
+    let mut desyncs = Vec::new();
+    while !player.is_finished() {
+        desyncs.extend(player.frame(&mut chip)?);
+    }
+    Ok(ReplayReport {
+        frames: player.frame_number(),
+        desyncs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //counts instructions run while key 5 is held, and draws a random number each time round
+    //200 LD V0, 5; 202 SKNP V0; 204 ADD V1, 1; 206 RND V2, 0xFF; 208 JP 0x202
+    const ROM: [u8; 10] = [0x60, 0x05, 0xE0, 0xA1, 0x71, 0x01, 0xC2, 0xFF, 0x12, 0x02];
+
+    fn record() -> Movie {
+        let chip = &mut Chip8::with_seed(Quirks::default(), 42);
+        chip.load_rom(&ROM).unwrap();
+        let mut recorder = Recorder::new(chip, &ROM, 10, 4);
+        for frame in 0..20 {
+            match frame {
+                3 | 11 => recorder.key(5, true),
+                5 | 13 => recorder.key(5, false),
+                _ => (),
+            }
+            recorder.frame(chip).unwrap();
+        }
+        recorder.finish()
+    }
+
+    #[test]
+    fn test_record() {
+        let movie = record();
+        assert_eq!(20, movie.frames);
+        assert_eq!(42, movie.seed);
+        assert_eq!(
+            vec!["3:+5", "5:-5", "11:+5", "13:-5"],
+            movie
+                .keys
+                .iter()
+                .map(|key| key.to_string())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![4, 8, 12, 16, 20],
+            movie
+                .checkpoints
+                .iter()
+                .map(|checkpoint| checkpoint.frame)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_replay_matches() {
+        let movie = record();
+        assert_eq!(
+            Ok(ReplayReport {
+                frames: 20,
+                desyncs: Vec::new()
+            }),
+            replay(&movie, &ROM)
+        );
+    }
+
+    #[test]
+    fn test_replay_desync() {
+        let mut movie = record();
+        //the second press lands a frame late
+        movie.keys[2].frame = 12;
+        let report = replay(&movie, &ROM).unwrap();
+        assert_eq!(
+            vec![12, 16, 20],
+            report
+                .desyncs
+                .iter()
+                .map(|desync| desync.frame)
+                .collect::<Vec<_>>()
+        );
+
+        let mut movie = record();
+        movie.seed = 7;
+        assert_eq!(4, replay(&movie, &ROM).unwrap().desyncs[0].frame);
+    }
+
+    #[test]
+    fn test_wrong_rom() {
+        let movie = record();
+        let mut rom = ROM;
+        rom[9] = 0x00;
+        assert!(matches!(
+            replay(&movie, &rom),
+            Err(MovieError::RomMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_text_round_trip() {
+        let movie = record();
+        let text = movie.to_string();
+        assert!(text.starts_with("chip8-movie 1\nrom "));
+        assert!(text.contains("\nkey 3:+5\n"));
+        assert_eq!(Ok(movie), text.parse());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let parse = |text: &str| text.parse::<Movie>().unwrap_err().to_string();
+        assert_eq!("line 1: not a movie file", parse("rom 0"));
+        assert_eq!(
+            "line 1: unsupported movie 'chip8-movie 2'",
+            parse("chip8-movie 2")
+        );
+        assert_eq!(
+            "line 3: invalid seed 'x'",
+            parse("chip8-movie 1\nrom 0\nseed x")
+        );
+        assert_eq!("line 1: movie has no quirks", parse("chip8-movie 1\nrom 0"));
+        assert_eq!(
+            "line 2: unknown item 'speed'",
+            parse("chip8-movie 1\nspeed 3")
+        );
+    }
+}
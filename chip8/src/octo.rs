@@ -0,0 +1,1093 @@
+use crate::assembler::AsmError;
+use crate::opcode::Opcode;
+use crate::symbols::Symbols;
+use std::collections::{HashMap, VecDeque};
+
+const LONG_I_OPCODE: u16 = 0xF000;
+//a macro that expands to itself would otherwise never finish
+const MAX_EXPANSIONS: usize = 10_000;
+const ENTRY_LABEL: &str = "main";
+
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    line: usize,
+    column: usize,
+}
+
+impl Token {
+    fn error(&self, message: String) -> AsmError {
+        AsmError {
+            line: self.line,
+            column: self.column,
+            message,
+        }
+    }
+}
+
+//label used before it was defined, filled in once everything is compiled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Patch {
+    //low 12 bits of the opcode at offset
+    Address,
+    //the word after F000
+    Long,
+}
+
+struct Fixup {
+    offset: usize,
+    patch: Patch,
+    name: Token,
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+}
+
+//open begin, else and loop blocks, waiting for their end or again
+enum Flow {
+    Begin { jump: usize },
+    Else { jump: usize },
+    Loop { start: u16, exits: Vec<usize> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+    LessOrEqual,
+    GreaterOrEqual,
+    Key,
+    NotKey,
+}
+
+impl Comparison {
+    fn negate(self) -> Self {
+        match self {
+            Comparison::Equal => Comparison::NotEqual,
+            Comparison::NotEqual => Comparison::Equal,
+            Comparison::Less => Comparison::GreaterOrEqual,
+            Comparison::GreaterOrEqual => Comparison::Less,
+            Comparison::Greater => Comparison::LessOrEqual,
+            Comparison::LessOrEqual => Comparison::Greater,
+            Comparison::Key => Comparison::NotKey,
+            Comparison::NotKey => Comparison::Key,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Operand {
+    Register(u8),
+    Byte(u8),
+    //key and -key compare against nothing
+    None,
+}
+
+//Compiles Octo source into a program loaded at origin.
+//Execution starts at the main label, a jump to it is put first unless main is already there.
+//:calc expressions have no operator precedence and work right to left, so use brackets.
+pub fn compile(source: &str, origin: u16) -> Result<Vec<u8>, AsmError> {
+    compile_with_symbols(source, origin).map(|(rom, _)| rom)
+}
+
+//compile, plus where each label and line went for a debugger
+pub fn compile_with_symbols(source: &str, origin: u16) -> Result<(Vec<u8>, Symbols), AsmError> {
+    let mut compiler = Compiler {
+        tokens: tokenize(source),
+        last: Token {
+            text: String::new(),
+            line: 1,
+            column: 1,
+        },
+        origin,
+        here: origin as usize,
+        rom: Vec::new(),
+        constants: HashMap::new(),
+        labels: HashMap::new(),
+        aliases: HashMap::new(),
+        macros: HashMap::new(),
+        fixups: Vec::new(),
+        flow: Vec::new(),
+        started: false,
+        expansions: 0,
+        line: None,
+        lines: Vec::new(),
+    };
+    while !compiler.tokens.is_empty() {
+        compiler.statement()?;
+    }
+    compiler.finish()
+}
+
+//whitespace separated, # comments run to the end of the line
+fn tokenize(source: &str) -> VecDeque<Token> {
+    let mut tokens = VecDeque::new();
+    for (index, line) in source.lines().enumerate() {
+        let code = line.split('#').next().unwrap_or("");
+        let mut start = None;
+        for (i, c) in code.char_indices().chain([(code.len(), ' ')]) {
+            match start {
+                Some(s) if c.is_whitespace() => {
+                    tokens.push_back(Token {
+                        text: code[s..i].to_string(),
+                        line: index + 1,
+                        column: s + 1,
+                    });
+                    start = None;
+                }
+                None if !c.is_whitespace() => start = Some(i),
+                _ => (),
+            }
+        }
+    }
+    tokens
+}
+
+fn parse_number(text: &str) -> Option<f64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let lower = digits.to_ascii_lowercase();
+    let value = if let Some(hex) = lower.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()?
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value } as f64)
+}
+
+fn parse_register(text: &str) -> Option<u8> {
+    let x = text.strip_prefix(['v', 'V'])?;
+    if x.len() != 1 {
+        return None;
+    }
+    u8::from_str_radix(x, 16).ok()
+}
+
+struct Compiler {
+    tokens: VecDeque<Token>,
+    //most recent token, where running out of input gets reported
+    last: Token,
+    origin: u16,
+    here: usize,
+    rom: Vec<u8>,
+    constants: HashMap<String, f64>,
+    labels: HashMap<String, u16>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    flow: Vec<(Flow, Token)>,
+    //whether the jump to main has been sorted out
+    started: bool,
+    expansions: usize,
+    //line of the statement being compiled until its first byte is placed
+    line: Option<usize>,
+    lines: Vec<(usize, u16)>,
+}
+
+impl Compiler {
+    fn next(&mut self) -> Result<Token, AsmError> {
+        match self.tokens.pop_front() {
+            Some(token) => {
+                self.last = token.clone();
+                Ok(token)
+            }
+            None => Err(self.last.error("unexpected end of file".to_string())),
+        }
+    }
+
+    fn peek_is(&self, text: &str) -> bool {
+        self.tokens.front().is_some_and(|t| t.text == text)
+    }
+
+    fn expect(&mut self, text: &str) -> Result<Token, AsmError> {
+        let token = self.next()?;
+        if token.text != text {
+            return Err(token.error(format!("expected '{}', got '{}'", text, token.text)));
+        }
+        Ok(token)
+    }
+
+    fn statement(&mut self) -> Result<(), AsmError> {
+        let token = self.next()?;
+        self.line = Some(token.line);
+        match token.text.as_str() {
+            ":" => {
+                let name = self.name()?;
+                self.define_label(&name)?;
+            }
+            ":alias" => {
+                let name = self.name()?;
+                let x = self.register()?;
+                self.aliases.insert(name.text, x);
+            }
+            ":const" => {
+                let name = self.name()?;
+                let value = self.next()?;
+                let value = self.number(&value)?;
+                self.define_constant(&name, value)?;
+            }
+            ":calc" => {
+                let name = self.name()?;
+                let value = self.calc()?;
+                self.define_constant(&name, value)?;
+            }
+            ":macro" => self.define_macro()?,
+            ":byte" => {
+                let byte = if self.peek_is("{") {
+                    let value = self.calc()?;
+                    self.byte_from(&token, value)?
+                } else {
+                    self.byte()?
+                };
+                self.emit_byte(byte);
+            }
+            ":call" => {
+                let target = self.next()?;
+                self.jump_like(0x2000, &target)?;
+            }
+            "return" | ";" => self.emit(Opcode::Ret),
+            "clear" => self.emit(Opcode::ClearScreen),
+            "exit" => self.emit(Opcode::Exit),
+            "lores" => self.emit(Opcode::LowResolution),
+            "hires" => self.emit(Opcode::HighResolution),
+            "scroll-left" => self.emit(Opcode::ScrollLeft),
+            "scroll-right" => self.emit(Opcode::ScrollRight),
+            "audio" => self.emit(Opcode::LoadAudioPatternFromI),
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit(Opcode::ScrollDown { n });
+            }
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.emit(Opcode::ScrollUp { n });
+            }
+            "plane" => {
+                let n = self.nibble()?;
+                self.emit(Opcode::SelectPlanes { n });
+            }
+            "bcd" => {
+                let x = self.register()?;
+                self.emit(Opcode::LoadVxAsDecimalIntoMemoryAtIRegister { x });
+            }
+            "saveflags" => {
+                let x = self.register()?;
+                self.emit(Opcode::StoreRegistersV0ToVxInRplFlags { x });
+            }
+            "loadflags" => {
+                let x = self.register()?;
+                self.emit(Opcode::LoadRegistersV0ToVxFromRplFlags { x });
+            }
+            "save" | "load" => {
+                let x = self.register()?;
+                let opcode = match (token.text.as_str(), self.range_end()?) {
+                    ("save", None) => Opcode::LoadRegistersV0ToVxIntoMemoryAtI { x },
+                    (_, None) => Opcode::FillRegistersV0ToVxFromMmoryAtI { x },
+                    ("save", Some(y)) => Opcode::LoadRegistersVxToVyIntoMemoryAtI { x, y },
+                    (_, Some(y)) => Opcode::FillRegistersVxToVyFromMemoryAtI { x, y },
+                };
+                self.emit(opcode);
+            }
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.nibble()?;
+                self.emit(Opcode::Draw { x, y, n });
+            }
+            "jump" | "jump0" | "native" => {
+                let target = self.next()?;
+                let high = match token.text.as_str() {
+                    "jump" => 0x1000,
+                    "jump0" => 0xB000,
+                    _ => 0x0000,
+                };
+                self.jump_like(high, &target)?;
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                self.emit(match token.text.as_str() {
+                    "delay" => Opcode::SetDelayTimer { x },
+                    "buzzer" => Opcode::SetSoundTimer { x },
+                    _ => Opcode::SetPitchFromVx { x },
+                });
+            }
+            "i" => self.i_statement()?,
+            "if" => self.if_statement()?,
+            "else" => match self.flow.pop() {
+                Some((Flow::Begin { jump }, _)) => {
+                    let skip_else = self.jump_placeholder();
+                    self.patch_jump(jump, &token)?;
+                    self.flow.push((Flow::Else { jump: skip_else }, token));
+                }
+                _ => return Err(token.error("'else' without 'begin'".to_string())),
+            },
+            "end" => match self.flow.pop() {
+                Some((Flow::Begin { jump } | Flow::Else { jump }, _)) => {
+                    self.patch_jump(jump, &token)?
+                }
+                _ => return Err(token.error("'end' without 'begin'".to_string())),
+            },
+            "loop" => {
+                let start = self.here as u16;
+                self.flow.push((
+                    Flow::Loop {
+                        start,
+                        exits: Vec::new(),
+                    },
+                    token,
+                ));
+            }
+            "while" => {
+                let (x, comparison, operand) = self.condition()?;
+                //leave the loop when the condition doesn't hold
+                self.skip_unless(x, comparison.negate(), operand);
+                let exit = self.jump_placeholder();
+                let Some((Flow::Loop { exits, .. }, _)) = self
+                    .flow
+                    .iter_mut()
+                    .rev()
+                    .find(|(flow, _)| matches!(flow, Flow::Loop { .. }))
+                else {
+                    return Err(token.error("'while' outside of a loop".to_string()));
+                };
+                exits.push(exit);
+            }
+            "again" => match self.flow.pop() {
+                Some((Flow::Loop { start, exits }, _)) => {
+                    self.emit(Opcode::Jump(start));
+                    for exit in exits {
+                        self.patch_jump(exit, &token)?;
+                    }
+                }
+                _ => return Err(token.error("'again' without 'loop'".to_string())),
+            },
+            text if self.is_register(text) => {
+                self.tokens.push_front(token);
+                self.register_statement()?;
+            }
+            text if self.macros.contains_key(text) => self.expand(&token)?,
+            text if parse_number(text).is_some() || self.constants.contains_key(text) => {
+                let value = self.number(&token)?;
+                let byte = self.byte_from(&token, value)?;
+                self.emit_byte(byte);
+            }
+            text if text.starts_with(':') => {
+                return Err(token.error(format!("unsupported directive '{}'", text)));
+            }
+            //anything else is a subroutine, called by name
+            _ => self.jump_like(0x2000, &token)?,
+        }
+        Ok(())
+    }
+
+    fn name(&mut self) -> Result<Token, AsmError> {
+        let name = self.next()?;
+        let text = name.text.as_str();
+        let keyword =
+            matches!(text, "{" | "}" | ":=" | "i" | "then" | "begin") || text.starts_with(':');
+        if keyword || parse_number(text).is_some() || parse_register(text).is_some() {
+            return Err(name.error(format!("'{}' can't be used as a name", text)));
+        }
+        Ok(name)
+    }
+
+    fn define_label(&mut self, name: &Token) -> Result<(), AsmError> {
+        self.start(Some(&name.text));
+        if self.labels.contains_key(&name.text) || self.constants.contains_key(&name.text) {
+            return Err(name.error(format!("'{}' is already defined", name.text)));
+        }
+        self.labels.insert(name.text.clone(), self.here as u16);
+        Ok(())
+    }
+
+    fn define_constant(&mut self, name: &Token, value: f64) -> Result<(), AsmError> {
+        if self.labels.contains_key(&name.text) {
+            return Err(name.error(format!("'{}' is already a label", name.text)));
+        }
+        self.constants.insert(name.text.clone(), value);
+        Ok(())
+    }
+
+    //:macro name params... { body }
+    fn define_macro(&mut self) -> Result<(), AsmError> {
+        let name = self.name()?;
+        let mut params = Vec::new();
+        loop {
+            let param = self.next()?;
+            if param.text == "{" {
+                break;
+            }
+            params.push(param.text);
+        }
+        let mut body = Vec::new();
+        let mut depth = 1;
+        loop {
+            let token = self.next()?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" => depth -= 1,
+                _ => (),
+            }
+            if depth == 0 {
+                break;
+            }
+            body.push(token);
+        }
+        self.macros.insert(name.text, Macro { params, body });
+        Ok(())
+    }
+
+    fn expand(&mut self, name: &Token) -> Result<(), AsmError> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(name.error("too many macro expansions, is one recursive?".to_string()));
+        }
+        let count = self.macros[&name.text].params.len();
+        let mut args = Vec::new();
+        for _ in 0..count {
+            args.push(self.next()?.text);
+        }
+        let definition = &self.macros[&name.text];
+        for token in definition.body.iter().rev() {
+            let text = match definition.params.iter().position(|p| *p == token.text) {
+                Some(index) => args[index].clone(),
+                None => token.text.clone(),
+            };
+            self.tokens.push_front(Token {
+                text,
+                ..token.clone()
+            });
+        }
+        Ok(())
+    }
+
+    fn is_register(&self, text: &str) -> bool {
+        parse_register(text).is_some() || self.aliases.contains_key(text)
+    }
+
+    fn register(&mut self) -> Result<u8, AsmError> {
+        let token = self.next()?;
+        parse_register(&token.text)
+            .or_else(|| self.aliases.get(&token.text).copied())
+            .ok_or_else(|| token.error(format!("expected a register, got '{}'", token.text)))
+    }
+
+    //the - vy part of save vx - vy
+    fn range_end(&mut self) -> Result<Option<u8>, AsmError> {
+        if !self.peek_is("-") {
+            return Ok(None);
+        }
+        self.next()?;
+        Ok(Some(self.register()?))
+    }
+
+    //a number, or a constant or label defined earlier
+    fn number(&self, token: &Token) -> Result<f64, AsmError> {
+        parse_number(&token.text)
+            .or_else(|| self.constants.get(&token.text).copied())
+            .or_else(|| self.labels.get(&token.text).map(|addr| *addr as f64))
+            .ok_or_else(|| token.error(format!("unknown value '{}'", token.text)))
+    }
+
+    //bytes can be written signed, -1 is 0xFF
+    fn byte_from(&self, token: &Token, value: f64) -> Result<u8, AsmError> {
+        let value = value.floor();
+        if !(-128.0..=255.0).contains(&value) {
+            return Err(token.error(format!("{} doesn't fit in a byte", value)));
+        }
+        Ok(value as i64 as u8)
+    }
+
+    fn byte(&mut self) -> Result<u8, AsmError> {
+        let token = self.next()?;
+        let value = self.number(&token)?;
+        self.byte_from(&token, value)
+    }
+
+    fn nibble(&mut self) -> Result<u8, AsmError> {
+        let token = self.next()?;
+        let value = self.number(&token)?.floor();
+        if !(0.0..=15.0).contains(&value) {
+            return Err(token.error(format!("{} doesn't fit in 4 bits", value)));
+        }
+        Ok(value as u8)
+    }
+
+    //an address that can be a label defined further down
+    fn address(&mut self, token: &Token, patch: Patch, offset: usize) -> Result<u16, AsmError> {
+        let max = match patch {
+            Patch::Address => 0xFFF,
+            Patch::Long => 0xFFFF,
+        };
+        let value = if let Some(value) =
+            parse_number(&token.text).or_else(|| self.constants.get(&token.text).copied())
+        {
+            value.floor()
+        } else if let Some(addr) = self.labels.get(&token.text) {
+            *addr as f64
+        } else {
+            self.fixups.push(Fixup {
+                offset,
+                patch,
+                name: token.clone(),
+            });
+            return Ok(0);
+        };
+        if !(0.0..=max as f64).contains(&value) {
+            return Err(token.error(format!("address {} is out of range", value)));
+        }
+        Ok(value as u16)
+    }
+
+    //jump, jump0, native and calls all take a 12 bit address
+    fn jump_like(&mut self, high: u16, target: &Token) -> Result<(), AsmError> {
+        self.start(None);
+        let offset = self.offset();
+        let addr = self.address(target, Patch::Address, offset)?;
+        self.emit_word(high | addr);
+        Ok(())
+    }
+
+    fn i_statement(&mut self) -> Result<(), AsmError> {
+        let op = self.next()?;
+        match op.text.as_str() {
+            ":=" => {
+                let value = self.next()?;
+                match value.text.as_str() {
+                    "hex" => {
+                        let x = self.register()?;
+                        self.emit(Opcode::SetICorrespondingFontAddressFromVx { x });
+                    }
+                    "bighex" => {
+                        let x = self.register()?;
+                        self.emit(Opcode::SetICorrespondingBigFontAddressFromVx { x });
+                    }
+                    "long" => {
+                        let target = self.next()?;
+                        self.start(None);
+                        let offset = self.offset() + 2;
+                        let addr = self.address(&target, Patch::Long, offset)?;
+                        self.emit_word(LONG_I_OPCODE);
+                        self.emit_word(addr);
+                    }
+                    _ => self.jump_like(0xA000, &value)?,
+                }
+            }
+            "+=" => {
+                let x = self.register()?;
+                self.emit(Opcode::AddVxToIRegister { x });
+            }
+            _ => return Err(op.error(format!("unknown operator 'i {}'", op.text))),
+        }
+        Ok(())
+    }
+
+    fn register_statement(&mut self) -> Result<(), AsmError> {
+        let x = self.register()?;
+        let op = self.next()?;
+        let rhs = self.next()?;
+        let y = parse_register(&rhs.text).or_else(|| self.aliases.get(&rhs.text).copied());
+        let opcode = match (op.text.as_str(), y) {
+            (":=", Some(y)) => Opcode::LoadYIntoX { x, y },
+            ("+=", Some(y)) => Opcode::AddXY { x, y },
+            ("-=", Some(y)) => Opcode::SubXfromY { x, y },
+            ("=-", Some(y)) => Opcode::SubYfromX { x, y },
+            ("|=", Some(y)) => Opcode::BitwiseOrXY { x, y },
+            ("&=", Some(y)) => Opcode::BitwiseAndXY { x, y },
+            ("^=", Some(y)) => Opcode::BitwiseXorXY { x, y },
+            (">>=", Some(y)) => Opcode::ShiftRight { x, y },
+            ("<<=", Some(y)) => Opcode::ShiftLeft { x, y },
+            (":=", None) => match rhs.text.as_str() {
+                "key" => Opcode::WaitForKeyPressAndStoreVx { x },
+                "delay" => Opcode::LoadDelayTimerToVx { x },
+                "random" => Opcode::RandomNumberToRegisterX {
+                    x,
+                    kk: self.byte()?,
+                },
+                _ => Opcode::LoadValueToRegister {
+                    x,
+                    kk: self.byte_value(&rhs)?,
+                },
+            },
+            ("+=", None) => Opcode::AddToValueInRegister {
+                x,
+                kk: self.byte_value(&rhs)?,
+            },
+            //there is no subtract immediate, add the negative instead
+            ("-=", None) => Opcode::AddToValueInRegister {
+                x,
+                kk: self.byte_value(&rhs)?.wrapping_neg(),
+            },
+            _ => return Err(op.error(format!("unknown operator '{} {}'", op.text, rhs.text))),
+        };
+        self.emit(opcode);
+        Ok(())
+    }
+
+    fn byte_value(&self, token: &Token) -> Result<u8, AsmError> {
+        let value = self.number(token)?;
+        self.byte_from(token, value)
+    }
+
+    //vx == 5, vx != vy, vx < 3, vx key and so on
+    fn condition(&mut self) -> Result<(u8, Comparison, Operand), AsmError> {
+        let x = self.register()?;
+        let op = self.next()?;
+        let comparison = match op.text.as_str() {
+            "==" => Comparison::Equal,
+            "!=" => Comparison::NotEqual,
+            "<" => Comparison::Less,
+            ">" => Comparison::Greater,
+            "<=" => Comparison::LessOrEqual,
+            ">=" => Comparison::GreaterOrEqual,
+            "key" => return Ok((x, Comparison::Key, Operand::None)),
+            "-key" => return Ok((x, Comparison::NotKey, Operand::None)),
+            _ => return Err(op.error(format!("unknown comparison '{}'", op.text))),
+        };
+        let rhs = self.next()?;
+        let operand =
+            match parse_register(&rhs.text).or_else(|| self.aliases.get(&rhs.text).copied()) {
+                Some(y) => Operand::Register(y),
+                None => Operand::Byte(self.byte_value(&rhs)?),
+            };
+        Ok((x, comparison, operand))
+    }
+
+    fn if_statement(&mut self) -> Result<(), AsmError> {
+        let (x, comparison, operand) = self.condition()?;
+        let body = self.next()?;
+        match body.text.as_str() {
+            //the next statement only runs if the condition holds
+            "then" => self.skip_unless(x, comparison, operand),
+            //jump past the block if it doesn't
+            "begin" => {
+                self.skip_unless(x, comparison.negate(), operand);
+                let jump = self.jump_placeholder();
+                self.flow.push((Flow::Begin { jump }, body));
+            }
+            _ => return Err(body.error(format!("expected 'then' or 'begin', got '{}'", body.text))),
+        }
+        Ok(())
+    }
+
+    //Emits a skip so that the next instruction only runs when vx compares true.
+    //Ordering comparisons subtract into vf and test the borrow, so they clobber vf.
+    fn skip_unless(&mut self, x: u8, comparison: Comparison, operand: Operand) {
+        const VF: u8 = 0xF;
+        match (comparison, operand) {
+            (Comparison::Equal, Operand::Byte(kk)) => {
+                self.emit(Opcode::SkipIfNotEqualAtX { x, kk })
+            }
+            (Comparison::NotEqual, Operand::Byte(kk)) => {
+                self.emit(Opcode::SkipIfEqualAtX { x, kk })
+            }
+            (Comparison::Equal, Operand::Register(y)) => {
+                self.emit(Opcode::SkipIfBothValuesNotEqual { x, y })
+            }
+            (Comparison::NotEqual, Operand::Register(y)) => {
+                self.emit(Opcode::SkipIfBothValuesEqual { x, y })
+            }
+            (Comparison::Key, _) => self.emit(Opcode::SkipIfKeyAtVxNotPressed { x }),
+            (Comparison::NotKey, _) => self.emit(Opcode::SkipIfKeyAtVxPressed { x }),
+            (_, operand) => {
+                match operand {
+                    Operand::Register(y) => self.emit(Opcode::LoadYIntoX { x: VF, y }),
+                    Operand::Byte(kk) => self.emit(Opcode::LoadValueToRegister { x: VF, kk }),
+                    Operand::None => unreachable!("only key comparisons have no operand"),
+                }
+                //vf is 1 when there was no borrow
+                match comparison {
+                    //vf = vx - rhs, no borrow when vx >= rhs
+                    Comparison::Less | Comparison::GreaterOrEqual => {
+                        self.emit(Opcode::SubYfromX { x: VF, y: x })
+                    }
+                    //vf = rhs - vx, no borrow when vx <= rhs
+                    _ => self.emit(Opcode::SubXfromY { x: VF, y: x }),
+                }
+                let kk = 1;
+                match comparison {
+                    Comparison::Less | Comparison::Greater => {
+                        self.emit(Opcode::SkipIfEqualAtX { x: VF, kk })
+                    }
+                    _ => self.emit(Opcode::SkipIfNotEqualAtX { x: VF, kk }),
+                }
+            }
+        }
+    }
+
+    //:calc name { expr }
+    fn calc(&mut self) -> Result<f64, AsmError> {
+        let open = self.expect("{")?;
+        let mut tokens = Vec::new();
+        loop {
+            let token = self.next()?;
+            if token.text == "}" {
+                break;
+            }
+            tokens.push(token);
+        }
+        let mut position = 0;
+        let value = self.expression(&tokens, &mut position, &open)?;
+        if let Some(extra) = tokens.get(position) {
+            return Err(extra.error(format!("unexpected '{}' in expression", extra.text)));
+        }
+        Ok(value)
+    }
+
+    //term, or term op expression, so everything groups to the right
+    fn expression(
+        &self,
+        tokens: &[Token],
+        position: &mut usize,
+        open: &Token,
+    ) -> Result<f64, AsmError> {
+        let left = self.term(tokens, position, open)?;
+        let Some(op) = tokens.get(*position) else {
+            return Ok(left);
+        };
+        if op.text == ")" {
+            return Ok(left);
+        }
+        *position += 1;
+        let right = self.expression(tokens, position, open)?;
+        let truth = |b: bool| if b { 1.0 } else { 0.0 };
+        let int = |v: f64| v as i64;
+        Ok(match op.text.as_str() {
+            "+" => left + right,
+            "-" => left - right,
+            "*" => left * right,
+            "/" => left / right,
+            "%" => left % right,
+            "&" => (int(left) & int(right)) as f64,
+            "|" => (int(left) | int(right)) as f64,
+            "^" => (int(left) ^ int(right)) as f64,
+            "<<" => (int(left) << int(right)) as f64,
+            ">>" => (int(left) >> int(right)) as f64,
+            "pow" => left.powf(right),
+            "min" => left.min(right),
+            "max" => left.max(right),
+            "<" => truth(left < right),
+            ">" => truth(left > right),
+            "<=" => truth(left <= right),
+            ">=" => truth(left >= right),
+            "==" => truth(left == right),
+            "!=" => truth(left != right),
+            _ => return Err(op.error(format!("unknown operator '{}'", op.text))),
+        })
+    }
+
+    fn term(&self, tokens: &[Token], position: &mut usize, open: &Token) -> Result<f64, AsmError> {
+        let Some(token) = tokens.get(*position) else {
+            return Err(open.error("expression ends too soon".to_string()));
+        };
+        *position += 1;
+        let unary =
+            |f: fn(f64) -> f64, position: &mut usize| self.term(tokens, position, open).map(f);
+        match token.text.as_str() {
+            "(" => {
+                let value = self.expression(tokens, position, open)?;
+                match tokens.get(*position) {
+                    Some(close) if close.text == ")" => {
+                        *position += 1;
+                        Ok(value)
+                    }
+                    _ => Err(token.error("'(' without ')'".to_string())),
+                }
+            }
+            "-" => unary(|v| -v, position),
+            "~" => unary(|v| !(v as i64) as f64, position),
+            "!" => unary(|v| if v == 0.0 { 1.0 } else { 0.0 }, position),
+            "abs" => unary(f64::abs, position),
+            "sqrt" => unary(f64::sqrt, position),
+            "sin" => unary(f64::sin, position),
+            "cos" => unary(f64::cos, position),
+            "floor" => unary(f64::floor, position),
+            "ceil" => unary(f64::ceil, position),
+            "@" => {
+                //a byte already compiled into the program
+                let addr = self.term(tokens, position, open)? as i64;
+                let offset = addr - self.origin as i64;
+                Ok(usize::try_from(offset)
+                    .ok()
+                    .and_then(|offset| self.rom.get(offset))
+                    .map_or(0.0, |byte| *byte as f64))
+            }
+            "HERE" => Ok(self.here as f64),
+            "PI" => Ok(std::f64::consts::PI),
+            "E" => Ok(std::f64::consts::E),
+            _ => self.number(token),
+        }
+    }
+
+    //the first code or label decides whether main needs jumping to
+    fn start(&mut self, label: Option<&str>) {
+        if self.started {
+            return;
+        }
+        self.started = true;
+        if label != Some(ENTRY_LABEL) {
+            //the jump belongs to no line, the statement starts after it
+            let line = self.line.take();
+            let offset = self.offset();
+            self.fixups.push(Fixup {
+                offset,
+                patch: Patch::Address,
+                name: Token {
+                    text: ENTRY_LABEL.to_string(),
+                    line: 1,
+                    column: 1,
+                },
+            });
+            self.emit_word(0x1000);
+            self.line = line;
+        }
+    }
+
+    fn offset(&self) -> usize {
+        self.here - self.origin as usize
+    }
+
+    fn emit(&mut self, opcode: Opcode) {
+        self.start(None);
+        self.emit_word(opcode.encode());
+    }
+
+    fn emit_word(&mut self, word: u16) {
+        let [high, low] = word.to_be_bytes();
+        self.emit_byte(high);
+        self.emit_byte(low);
+    }
+
+    fn emit_byte(&mut self, byte: u8) {
+        self.start(None);
+        //one entry per run of code from a line, not one per data byte
+        if let Some(line) = self.line.take() {
+            if self.lines.last().map(|(last, _)| *last) != Some(line) {
+                self.lines.push((line, self.here as u16));
+            }
+        }
+        self.rom.push(byte);
+        self.here += 1;
+    }
+
+    //a jump to be pointed somewhere once we know where
+    fn jump_placeholder(&mut self) -> usize {
+        self.start(None);
+        let offset = self.offset();
+        self.emit_word(0x1000);
+        offset
+    }
+
+    fn patch_jump(&mut self, offset: usize, token: &Token) -> Result<(), AsmError> {
+        if self.here > 0xFFF {
+            return Err(token.error(format!("address {:#X} is out of range", self.here)));
+        }
+        self.rom[offset] = 0x10 | (self.here >> 8) as u8;
+        self.rom[offset + 1] = self.here as u8;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<(Vec<u8>, Symbols), AsmError> {
+        if let Some((_, token)) = self.flow.last() {
+            return Err(token.error(format!("'{}' is never closed", token.text)));
+        }
+        if self.here > u16::MAX as usize + 1 {
+            return Err(self.last.error("program doesn't fit in memory".to_string()));
+        }
+        if !self.labels.contains_key(ENTRY_LABEL) {
+            return Err(AsmError {
+                line: 1,
+                column: 1,
+                message: "program has no 'main' label".to_string(),
+            });
+        }
+        for fixup in &self.fixups {
+            let Some(addr) = self.labels.get(&fixup.name.text).copied() else {
+                return Err(fixup
+                    .name
+                    .error(format!("undefined name '{}'", fixup.name.text)));
+            };
+            let offset = fixup.offset;
+            match fixup.patch {
+                Patch::Address => {
+                    if addr > 0xFFF {
+                        return Err(fixup
+                            .name
+                            .error(format!("address {:#X} is out of range", addr)));
+                    }
+                    self.rom[offset] |= (addr >> 8) as u8;
+                    self.rom[offset + 1] = addr as u8;
+                }
+                Patch::Long => self.rom[offset..offset + 2].copy_from_slice(&addr.to_be_bytes()),
+            }
+        }
+        let symbols = Symbols {
+            source: None,
+            labels: self.labels.into_iter().collect(),
+            lines: self.lines,
+        };
+        Ok((self.rom, symbols))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::Chip8;
+    use crate::debug::{DebugEvent, Register};
+    use crate::quirks::Quirks;
+
+    const ORIGIN: u16 = 0x200;
+
+    fn compile_ok(source: &str) -> Vec<u8> {
+        compile(source, ORIGIN).unwrap()
+    }
+
+    fn error(source: &str) -> String {
+        compile(source, ORIGIN).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_reference_bytes() {
+        //assembled by hand from octo's lowering, not by this compiler
+        assert_eq!(
+            include_bytes!("../../roms/octo/bounce.ch8").to_vec(),
+            compile_ok(include_str!("../../roms/octo/bounce.8o"))
+        );
+        assert_eq!(
+            include_bytes!("../../roms/octo/control.ch8").to_vec(),
+            compile_ok(include_str!("../../roms/octo/control.8o"))
+        );
+    }
+
+    #[test]
+    fn test_bounce_program_runs() {
+        let rom = compile_ok(include_str!("../../roms/octo/bounce.8o"));
+        let mut chip = Chip8::with_seed(Quirks::default(), 0);
+        chip.load_rom(&rom).unwrap();
+        let ball = |chip: &Chip8| (chip.register(Register::V(0)), chip.register(Register::V(1)));
+        let mut positions = Vec::new();
+        //long enough to hit every edge
+        for _ in 0..600 {
+            for _ in 0..10 {
+                chip.tick().unwrap();
+            }
+            chip.tick_timers();
+            let (x, y) = ball(&chip);
+            assert!(x <= 60 && y <= 28, "ball at {}, {}", x, y);
+            positions.push((x, y));
+        }
+        assert!(positions.iter().any(|(x, _)| *x == 0));
+        assert!(positions.iter().any(|(x, _)| *x == 60));
+        assert!(positions.iter().any(|(_, y)| *y == 0));
+        assert!(positions.iter().any(|(_, y)| *y == 28));
+    }
+
+    #[test]
+    fn test_control_program_runs() {
+        let rom = compile_ok(include_str!("../../roms/octo/control.8o"));
+        let mut chip = Chip8::with_seed(Quirks::default(), 0);
+        chip.load_rom(&rom).unwrap();
+        //the program ends in a loop on itself at 0x24A
+        assert_eq!(Ok(DebugEvent::Stopped(0x24A)), chip.run_to(0x24A, 1000));
+        let v = |x| chip.register(Register::V(x));
+        assert_eq!(
+            vec![10, 1, 2, 0, 10, 10, 6, 0xF0, 150, 147, 0xAB],
+            (0..=0xA).map(v).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_symbols() {
+        let (_, symbols) = compile_with_symbols(
+            ": sub\n  return\n\n: main\n  v0 := 1 if v0 == 1 then\n  sub",
+            ORIGIN,
+        )
+        .unwrap();
+        assert_eq!(Some(&0x202), symbols.labels.get("sub"));
+        assert_eq!(Some(&0x204), symbols.labels.get("main"));
+        //the jump to main at 0x200 has no line, the then statement shares the if's
+        assert_eq!(vec![(2, 0x202), (5, 0x204), (6, 0x208)], symbols.lines);
+    }
+
+    #[test]
+    fn test_jump_to_main() {
+        //main first needs no jump
+        assert_eq!(vec![0x00, 0xE0], compile_ok(": main clear"));
+        assert_eq!(
+            vec![0x12, 0x04, 0x00, 0xEE, 0x22, 0x02],
+            compile_ok(": sub return : main sub")
+        );
+    }
+
+    #[test]
+    fn test_statements() {
+        assert_eq!(
+            vec![
+                0x63, 0x05, 0x73, 0xFB, 0x83, 0x45, 0x83, 0x47, 0x83, 0x46, 0xC3, 0x0F, 0xF3, 0x0A,
+                0xF3, 0x29, 0xF3, 0x1E, 0x00, 0xFB, 0x00, 0xC2, 0xF2, 0x01,
+            ],
+            compile_ok(
+                ": main v3 := 5 v3 -= 5 v3 -= v4 v3 =- v4 v3 >>= v4 v3 := random 0x0F \
+                 v3 := key i := hex v3 i += v3 scroll-right scroll-down 2 plane 2"
+            )
+        );
+        assert_eq!(
+            vec![0x52, 0x52, 0x53, 0x23, 0xF0, 0x00, 0x02, 0x08, 0x01, 0x02],
+            compile_ok(": main save v2 - v5 load v3 - v2 i := long data : data 1 2")
+        );
+    }
+
+    #[test]
+    fn test_macros_and_calc() {
+        assert_eq!(
+            vec![0x60, 0x07, 0x61, 0x07, 0x08],
+            compile_ok(
+                ":macro set R V { R := V } \
+                 :calc SEVEN { 1 + 2 * 3 } \
+                 : main set v0 SEVEN :alias seven v1 set seven 7 \
+                 :byte { 1 << 3 }"
+            )
+        );
+        //right to left, so 1 + 2 * 3 is 7 but ( 1 + 2 ) * 3 is 9
+        assert_eq!(vec![0x09], compile_ok(": main :byte { ( 1 + 2 ) * 3 }"));
+        assert_eq!(
+            vec![0x00, 0x02],
+            compile_ok(": main 0 :byte { @ 0x200 + 2 }")
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!("1:1: program has no 'main' label", error("clear"));
+        assert_eq!(
+            "1:13: undefined name 'nowhere'",
+            error(": main jump nowhere")
+        );
+        assert_eq!(
+            "1:19: 'begin' is never closed",
+            error(": main if v0 == 1 begin")
+        );
+        assert_eq!("1:8: 'again' without 'loop'", error(": main again"));
+        assert_eq!(
+            "1:8: 'while' outside of a loop",
+            error(": main while v0 == 1")
+        );
+        assert_eq!("1:14: 256 doesn't fit in a byte", error(": main v0 := 256"));
+        assert_eq!("1:3: 'v0' can't be used as a name", error(": v0"));
+        assert_eq!(
+            "1:12: too many macro expansions, is one recursive?",
+            error(":macro m { m } : main m")
+        );
+    }
+}
@@ -1,4 +1,6 @@
-#[derive(Debug, PartialEq)]
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Opcode {
     Sys(u16),
     Jump(u16),
@@ -13,7 +15,9 @@ pub enum Opcode {
     BitwiseAndXY { x: u8, y: u8 },
     BitwiseXorXY { x: u8, y: u8 },
     AddXY { x: u8, y: u8 },
+    //8xy5 SUB Vx, Vy
     SubXfromY { x: u8, y: u8 },
+    //8xy7 SUBN Vx, Vy
     SubYfromX { x: u8, y: u8 },
     Ret,
     ShiftRight { x: u8, y: u8 },
@@ -35,8 +39,26 @@ pub enum Opcode {
     Draw { x: u8, y: u8, n: u8 },
     ClearScreen,
     SetICorrespondingFontAddressFromVx { x: u8 },
+    //SUPER-CHIP
+    ScrollDown { n: u8 },
+    ScrollRight,
+    ScrollLeft,
+    Exit,
+    LowResolution,
+    HighResolution,
+    SetICorrespondingBigFontAddressFromVx { x: u8 },
+    StoreRegistersV0ToVxInRplFlags { x: u8 },
+    LoadRegistersV0ToVxFromRplFlags { x: u8 },
+    //XO-CHIP
+    ScrollUp { n: u8 },
+    //I is loaded from the word following the opcode
+    SetIRegisterLong,
+    SelectPlanes { n: u8 },
+    LoadRegistersVxToVyIntoMemoryAtI { x: u8, y: u8 },
+    FillRegistersVxToVyFromMemoryAtI { x: u8, y: u8 },
+    LoadAudioPatternFromI,
+    SetPitchFromVx { x: u8 },
     UnknownOpcode(u16),
-    //todo two display opcodes
 }
 
 impl Opcode {
@@ -54,6 +76,8 @@ impl Opcode {
             (0x3, x, _, _) => Opcode::SkipIfEqualAtX { x, kk },
             (0x4, x, _, _) => Opcode::SkipIfNotEqualAtX { x, kk },
             (0x5, x, y, 0x0) => Opcode::SkipIfBothValuesEqual { x, y },
+            (0x5, x, y, 0x2) => Opcode::LoadRegistersVxToVyIntoMemoryAtI { x, y },
+            (0x5, x, y, 0x3) => Opcode::FillRegistersVxToVyFromMemoryAtI { x, y },
             (0x6, x, _, _) => Opcode::LoadValueToRegister { x, kk },
             (0x7, x, _, _) => Opcode::AddToValueInRegister { x, kk },
             (0x8, x, y, 0x0) => Opcode::LoadYIntoX { x, y },
@@ -72,21 +96,247 @@ impl Opcode {
             (0xD, x, y, d) => Opcode::Draw { x, y, n: d },
             (0xE, x, 0x9, 0xE) => Opcode::SkipIfKeyAtVxPressed { x },
             (0xE, x, 0xA, 0x1) => Opcode::SkipIfKeyAtVxNotPressed { x },
+            (0xF, 0x0, 0x0, 0x0) => Opcode::SetIRegisterLong,
+            (0xF, n, 0x0, 0x1) => Opcode::SelectPlanes { n },
+            (0xF, 0x0, 0x0, 0x2) => Opcode::LoadAudioPatternFromI,
             (0xF, x, 0x0, 0x7) => Opcode::LoadDelayTimerToVx { x },
             (0xF, x, 0x0, 0xA) => Opcode::WaitForKeyPressAndStoreVx { x },
             (0xF, x, 0x1, 0x5) => Opcode::SetDelayTimer { x },
             (0xF, x, 0x1, 0x8) => Opcode::SetSoundTimer { x },
             (0xF, x, 0x1, 0xE) => Opcode::AddVxToIRegister { x },
             (0xF, x, 0x2, 0x9) => Opcode::SetICorrespondingFontAddressFromVx { x },
+            (0xF, x, 0x3, 0x0) => Opcode::SetICorrespondingBigFontAddressFromVx { x },
+            (0xF, x, 0x3, 0xA) => Opcode::SetPitchFromVx { x },
             (0xF, x, 0x3, 0x3) => Opcode::LoadVxAsDecimalIntoMemoryAtIRegister { x },
             (0xF, x, 0x5, 0x5) => Opcode::LoadRegistersV0ToVxIntoMemoryAtI { x },
             (0xF, x, 0x6, 0x5) => Opcode::FillRegistersV0ToVxFromMmoryAtI { x },
+            (0xF, x, 0x7, 0x5) => Opcode::StoreRegistersV0ToVxInRplFlags { x },
+            (0xF, x, 0x8, 0x5) => Opcode::LoadRegistersV0ToVxFromRplFlags { x },
             (0x0, 0x0, 0xE, 0x0) => Opcode::ClearScreen,
             (0x0, 0x0, 0xE, 0xE) => Opcode::Ret,
+            (0x0, 0x0, 0xC, n) => Opcode::ScrollDown { n },
+            (0x0, 0x0, 0xD, n) => Opcode::ScrollUp { n },
+            (0x0, 0x0, 0xF, 0xB) => Opcode::ScrollRight,
+            (0x0, 0x0, 0xF, 0xC) => Opcode::ScrollLeft,
+            (0x0, 0x0, 0xF, 0xD) => Opcode::Exit,
+            (0x0, 0x0, 0xF, 0xE) => Opcode::LowResolution,
+            (0x0, 0x0, 0xF, 0xF) => Opcode::HighResolution,
             (0x0, _, _, _) => Opcode::Sys(nnn),
             _ => Opcode::UnknownOpcode(opcode),
         }
     }
+
+    //inverse of decode, fields are masked to their nibbles
+    pub fn encode(&self) -> u16 {
+        let x = |x: u8| (x as u16 & 0xF) << 8;
+        let y = |y: u8| (y as u16 & 0xF) << 4;
+        let n = |n: u8| n as u16 & 0xF;
+        let nnn = |nnn: u16| nnn & 0x0FFF;
+        match *self {
+            Opcode::Sys(addr) => nnn(addr),
+            Opcode::Jump(addr) => 0x1000 | nnn(addr),
+            Opcode::Call(addr) => 0x2000 | nnn(addr),
+            Opcode::SkipIfEqualAtX { x: vx, kk } => 0x3000 | x(vx) | kk as u16,
+            Opcode::SkipIfNotEqualAtX { x: vx, kk } => 0x4000 | x(vx) | kk as u16,
+            Opcode::SkipIfBothValuesEqual { x: vx, y: vy } => 0x5000 | x(vx) | y(vy),
+            Opcode::LoadRegistersVxToVyIntoMemoryAtI { x: vx, y: vy } => 0x5002 | x(vx) | y(vy),
+            Opcode::FillRegistersVxToVyFromMemoryAtI { x: vx, y: vy } => 0x5003 | x(vx) | y(vy),
+            Opcode::LoadValueToRegister { x: vx, kk } => 0x6000 | x(vx) | kk as u16,
+            Opcode::AddToValueInRegister { x: vx, kk } => 0x7000 | x(vx) | kk as u16,
+            Opcode::LoadYIntoX { x: vx, y: vy } => 0x8000 | x(vx) | y(vy),
+            Opcode::BitwiseOrXY { x: vx, y: vy } => 0x8001 | x(vx) | y(vy),
+            Opcode::BitwiseAndXY { x: vx, y: vy } => 0x8002 | x(vx) | y(vy),
+            Opcode::BitwiseXorXY { x: vx, y: vy } => 0x8003 | x(vx) | y(vy),
+            Opcode::AddXY { x: vx, y: vy } => 0x8004 | x(vx) | y(vy),
+            Opcode::SubXfromY { x: vx, y: vy } => 0x8005 | x(vx) | y(vy),
+            Opcode::ShiftRight { x: vx, y: vy } => 0x8006 | x(vx) | y(vy),
+            Opcode::SubYfromX { x: vx, y: vy } => 0x8007 | x(vx) | y(vy),
+            Opcode::ShiftLeft { x: vx, y: vy } => 0x800E | x(vx) | y(vy),
+            Opcode::SkipIfBothValuesNotEqual { x: vx, y: vy } => 0x9000 | x(vx) | y(vy),
+            Opcode::SetIRegister(addr) => 0xA000 | nnn(addr),
+            Opcode::JumpPlusV0(addr) => 0xB000 | nnn(addr),
+            Opcode::RandomNumberToRegisterX { x: vx, kk } => 0xC000 | x(vx) | kk as u16,
+            Opcode::Draw {
+                x: vx,
+                y: vy,
+                n: rows,
+            } => 0xD000 | x(vx) | y(vy) | n(rows),
+            Opcode::SkipIfKeyAtVxPressed { x: vx } => 0xE09E | x(vx),
+            Opcode::SkipIfKeyAtVxNotPressed { x: vx } => 0xE0A1 | x(vx),
+            Opcode::SetIRegisterLong => 0xF000,
+            Opcode::SelectPlanes { n: planes } => 0xF001 | x(planes),
+            Opcode::LoadAudioPatternFromI => 0xF002,
+            Opcode::LoadDelayTimerToVx { x: vx } => 0xF007 | x(vx),
+            Opcode::WaitForKeyPressAndStoreVx { x: vx } => 0xF00A | x(vx),
+            Opcode::SetDelayTimer { x: vx } => 0xF015 | x(vx),
+            Opcode::SetSoundTimer { x: vx } => 0xF018 | x(vx),
+            Opcode::AddVxToIRegister { x: vx } => 0xF01E | x(vx),
+            Opcode::SetICorrespondingFontAddressFromVx { x: vx } => 0xF029 | x(vx),
+            Opcode::SetICorrespondingBigFontAddressFromVx { x: vx } => 0xF030 | x(vx),
+            Opcode::LoadVxAsDecimalIntoMemoryAtIRegister { x: vx } => 0xF033 | x(vx),
+            Opcode::SetPitchFromVx { x: vx } => 0xF03A | x(vx),
+            Opcode::LoadRegistersV0ToVxIntoMemoryAtI { x: vx } => 0xF055 | x(vx),
+            Opcode::FillRegistersV0ToVxFromMmoryAtI { x: vx } => 0xF065 | x(vx),
+            Opcode::StoreRegistersV0ToVxInRplFlags { x: vx } => 0xF075 | x(vx),
+            Opcode::LoadRegistersV0ToVxFromRplFlags { x: vx } => 0xF085 | x(vx),
+            Opcode::ClearScreen => 0x00E0,
+            Opcode::Ret => 0x00EE,
+            Opcode::ScrollDown { n: rows } => 0x00C0 | n(rows),
+            Opcode::ScrollUp { n: rows } => 0x00D0 | n(rows),
+            Opcode::ScrollRight => 0x00FB,
+            Opcode::ScrollLeft => 0x00FC,
+            Opcode::Exit => 0x00FD,
+            Opcode::LowResolution => 0x00FE,
+            Opcode::HighResolution => 0x00FF,
+            Opcode::UnknownOpcode(opcode) => opcode,
+        }
+    }
+}
+
+//Cowgod's mnemonics, e.g. LD V3, 0x10. The alternate form {:#} writes Octo, e.g. v3 := 0x10.
+//F000 is followed by its address word, which the opcode alone doesn't have,
+//so it is written as LD I, LONG and i := long
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            self.fmt_octo(f)
+        } else {
+            self.fmt_cowgod(f)
+        }
+    }
+}
+
+impl Opcode {
+    fn fmt_cowgod(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Opcode::Sys(nnn) => write!(f, "SYS {:#05X}", nnn),
+            Opcode::Jump(nnn) => write!(f, "JP {:#05X}", nnn),
+            Opcode::Call(nnn) => write!(f, "CALL {:#05X}", nnn),
+            Opcode::SkipIfEqualAtX { x, kk } => write!(f, "SE V{:X}, {:#04X}", x, kk),
+            Opcode::SkipIfNotEqualAtX { x, kk } => write!(f, "SNE V{:X}, {:#04X}", x, kk),
+            Opcode::LoadValueToRegister { x, kk } => write!(f, "LD V{:X}, {:#04X}", x, kk),
+            Opcode::AddToValueInRegister { x, kk } => write!(f, "ADD V{:X}, {:#04X}", x, kk),
+            Opcode::SkipIfBothValuesEqual { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            Opcode::LoadYIntoX { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            Opcode::BitwiseOrXY { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            Opcode::BitwiseAndXY { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            Opcode::BitwiseXorXY { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Opcode::AddXY { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Opcode::SubXfromY { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Opcode::SubYfromX { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Opcode::Ret => write!(f, "RET"),
+            Opcode::ShiftRight { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Opcode::ShiftLeft { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Opcode::SkipIfBothValuesNotEqual { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Opcode::SetIRegister(nnn) => write!(f, "LD I, {:#05X}", nnn),
+            Opcode::JumpPlusV0(nnn) => write!(f, "JP V0, {:#05X}", nnn),
+            Opcode::RandomNumberToRegisterX { x, kk } => write!(f, "RND V{:X}, {:#04X}", x, kk),
+            Opcode::LoadDelayTimerToVx { x } => write!(f, "LD V{:X}, DT", x),
+            Opcode::SetDelayTimer { x } => write!(f, "LD DT, V{:X}", x),
+            Opcode::SetSoundTimer { x } => write!(f, "LD ST, V{:X}", x),
+            Opcode::AddVxToIRegister { x } => write!(f, "ADD I, V{:X}", x),
+            Opcode::LoadVxAsDecimalIntoMemoryAtIRegister { x } => write!(f, "LD B, V{:X}", x),
+            Opcode::LoadRegistersV0ToVxIntoMemoryAtI { x } => write!(f, "LD [I], V{:X}", x),
+            Opcode::FillRegistersV0ToVxFromMmoryAtI { x } => write!(f, "LD V{:X}, [I]", x),
+            Opcode::WaitForKeyPressAndStoreVx { x } => write!(f, "LD V{:X}, K", x),
+            Opcode::SkipIfKeyAtVxPressed { x } => write!(f, "SKP V{:X}", x),
+            Opcode::SkipIfKeyAtVxNotPressed { x } => write!(f, "SKNP V{:X}", x),
+            Opcode::Draw { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Opcode::ClearScreen => write!(f, "CLS"),
+            Opcode::SetICorrespondingFontAddressFromVx { x } => write!(f, "LD F, V{:X}", x),
+            Opcode::ScrollDown { n } => write!(f, "SCD {}", n),
+            Opcode::ScrollRight => write!(f, "SCR"),
+            Opcode::ScrollLeft => write!(f, "SCL"),
+            Opcode::Exit => write!(f, "EXIT"),
+            Opcode::LowResolution => write!(f, "LOW"),
+            Opcode::HighResolution => write!(f, "HIGH"),
+            Opcode::SetICorrespondingBigFontAddressFromVx { x } => write!(f, "LD HF, V{:X}", x),
+            Opcode::StoreRegistersV0ToVxInRplFlags { x } => write!(f, "LD R, V{:X}", x),
+            Opcode::LoadRegistersV0ToVxFromRplFlags { x } => write!(f, "LD V{:X}, R", x),
+            Opcode::ScrollUp { n } => write!(f, "SCU {}", n),
+            Opcode::SetIRegisterLong => write!(f, "LD I, LONG"),
+            Opcode::SelectPlanes { n } => write!(f, "PLANE {}", n),
+            Opcode::LoadRegistersVxToVyIntoMemoryAtI { x, y } => {
+                write!(f, "SAVE V{:X}, V{:X}", x, y)
+            }
+            Opcode::FillRegistersVxToVyFromMemoryAtI { x, y } => {
+                write!(f, "LOAD V{:X}, V{:X}", x, y)
+            }
+            Opcode::LoadAudioPatternFromI => write!(f, "AUDIO"),
+            Opcode::SetPitchFromVx { x } => write!(f, "PITCH V{:X}", x),
+            Opcode::UnknownOpcode(opcode) => write!(f, "DW {:#06X}", opcode),
+        }
+    }
+
+    //Octo has no skip instructions, a skip is the opposite if ... then
+    fn fmt_octo(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Opcode::Sys(nnn) => write!(f, "{:#04X} {:#04X}", nnn >> 8, nnn & 0xFF),
+            Opcode::Jump(nnn) => write!(f, "jump {:#05X}", nnn),
+            Opcode::Call(nnn) => write!(f, ":call {:#05X}", nnn),
+            Opcode::SkipIfEqualAtX { x, kk } => write!(f, "if v{:x} != {:#04X} then", x, kk),
+            Opcode::SkipIfNotEqualAtX { x, kk } => write!(f, "if v{:x} == {:#04X} then", x, kk),
+            Opcode::LoadValueToRegister { x, kk } => write!(f, "v{:x} := {:#04X}", x, kk),
+            Opcode::AddToValueInRegister { x, kk } => write!(f, "v{:x} += {:#04X}", x, kk),
+            Opcode::SkipIfBothValuesEqual { x, y } => write!(f, "if v{:x} != v{:x} then", x, y),
+            Opcode::LoadYIntoX { x, y } => write!(f, "v{:x} := v{:x}", x, y),
+            Opcode::BitwiseOrXY { x, y } => write!(f, "v{:x} |= v{:x}", x, y),
+            Opcode::BitwiseAndXY { x, y } => write!(f, "v{:x} &= v{:x}", x, y),
+            Opcode::BitwiseXorXY { x, y } => write!(f, "v{:x} ^= v{:x}", x, y),
+            Opcode::AddXY { x, y } => write!(f, "v{:x} += v{:x}", x, y),
+            Opcode::SubXfromY { x, y } => write!(f, "v{:x} -= v{:x}", x, y),
+            Opcode::SubYfromX { x, y } => write!(f, "v{:x} =- v{:x}", x, y),
+            Opcode::Ret => write!(f, "return"),
+            Opcode::ShiftRight { x, y } => write!(f, "v{:x} >>= v{:x}", x, y),
+            Opcode::ShiftLeft { x, y } => write!(f, "v{:x} <<= v{:x}", x, y),
+            Opcode::SkipIfBothValuesNotEqual { x, y } => {
+                write!(f, "if v{:x} == v{:x} then", x, y)
+            }
+            Opcode::SetIRegister(nnn) => write!(f, "i := {:#05X}", nnn),
+            Opcode::JumpPlusV0(nnn) => write!(f, "jump0 {:#05X}", nnn),
+            Opcode::RandomNumberToRegisterX { x, kk } => {
+                write!(f, "v{:x} := random {:#04X}", x, kk)
+            }
+            Opcode::LoadDelayTimerToVx { x } => write!(f, "v{:x} := delay", x),
+            Opcode::SetDelayTimer { x } => write!(f, "delay := v{:x}", x),
+            Opcode::SetSoundTimer { x } => write!(f, "buzzer := v{:x}", x),
+            Opcode::AddVxToIRegister { x } => write!(f, "i += v{:x}", x),
+            Opcode::LoadVxAsDecimalIntoMemoryAtIRegister { x } => write!(f, "bcd v{:x}", x),
+            Opcode::LoadRegistersV0ToVxIntoMemoryAtI { x } => write!(f, "save v{:x}", x),
+            Opcode::FillRegistersV0ToVxFromMmoryAtI { x } => write!(f, "load v{:x}", x),
+            Opcode::WaitForKeyPressAndStoreVx { x } => write!(f, "v{:x} := key", x),
+            Opcode::SkipIfKeyAtVxPressed { x } => write!(f, "if v{:x} -key then", x),
+            Opcode::SkipIfKeyAtVxNotPressed { x } => write!(f, "if v{:x} key then", x),
+            Opcode::Draw { x, y, n } => write!(f, "sprite v{:x} v{:x} {}", x, y, n),
+            Opcode::ClearScreen => write!(f, "clear"),
+            Opcode::SetICorrespondingFontAddressFromVx { x } => write!(f, "i := hex v{:x}", x),
+            Opcode::ScrollDown { n } => write!(f, "scroll-down {}", n),
+            Opcode::ScrollRight => write!(f, "scroll-right"),
+            Opcode::ScrollLeft => write!(f, "scroll-left"),
+            Opcode::Exit => write!(f, "exit"),
+            Opcode::LowResolution => write!(f, "lores"),
+            Opcode::HighResolution => write!(f, "hires"),
+            Opcode::SetICorrespondingBigFontAddressFromVx { x } => {
+                write!(f, "i := bighex v{:x}", x)
+            }
+            Opcode::StoreRegistersV0ToVxInRplFlags { x } => write!(f, "saveflags v{:x}", x),
+            Opcode::LoadRegistersV0ToVxFromRplFlags { x } => write!(f, "loadflags v{:x}", x),
+            Opcode::ScrollUp { n } => write!(f, "scroll-up {}", n),
+            Opcode::SetIRegisterLong => write!(f, "i := long"),
+            Opcode::SelectPlanes { n } => write!(f, "plane {}", n),
+            Opcode::LoadRegistersVxToVyIntoMemoryAtI { x, y } => {
+                write!(f, "save v{:x} - v{:x}", x, y)
+            }
+            Opcode::FillRegistersVxToVyFromMemoryAtI { x, y } => {
+                write!(f, "load v{:x} - v{:x}", x, y)
+            }
+            Opcode::LoadAudioPatternFromI => write!(f, "audio"),
+            Opcode::SetPitchFromVx { x } => write!(f, "pitch := v{:x}", x),
+            Opcode::UnknownOpcode(opcode) => {
+                write!(f, "{:#04X} {:#04X}", opcode >> 8, opcode & 0xFF)
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -123,6 +373,75 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_decode_super_chip() {
+        assert_eq!(Opcode::decode(0x00C4), Opcode::ScrollDown { n: 4 });
+        assert_eq!(Opcode::decode(0x00FB), Opcode::ScrollRight);
+        assert_eq!(Opcode::decode(0x00FC), Opcode::ScrollLeft);
+        assert_eq!(Opcode::decode(0x00FD), Opcode::Exit);
+        assert_eq!(Opcode::decode(0x00FE), Opcode::LowResolution);
+        assert_eq!(Opcode::decode(0x00FF), Opcode::HighResolution);
+        assert_eq!(
+            Opcode::decode(0xF330),
+            Opcode::SetICorrespondingBigFontAddressFromVx { x: 3 }
+        );
+        assert_eq!(
+            Opcode::decode(0xF775),
+            Opcode::StoreRegistersV0ToVxInRplFlags { x: 7 }
+        );
+        assert_eq!(
+            Opcode::decode(0xF785),
+            Opcode::LoadRegistersV0ToVxFromRplFlags { x: 7 }
+        );
+    }
+
+    #[test]
+    fn test_decode_xo_chip() {
+        assert_eq!(Opcode::decode(0x00D2), Opcode::ScrollUp { n: 2 });
+        assert_eq!(Opcode::decode(0xF000), Opcode::SetIRegisterLong);
+        assert_eq!(Opcode::decode(0xF301), Opcode::SelectPlanes { n: 3 });
+        assert_eq!(
+            Opcode::decode(0x5122),
+            Opcode::LoadRegistersVxToVyIntoMemoryAtI { x: 1, y: 2 }
+        );
+        assert_eq!(
+            Opcode::decode(0x5213),
+            Opcode::FillRegistersVxToVyFromMemoryAtI { x: 2, y: 1 }
+        );
+        assert_eq!(Opcode::decode(0xF002), Opcode::LoadAudioPatternFromI);
+        assert_eq!(Opcode::decode(0xF53A), Opcode::SetPitchFromVx { x: 5 });
+    }
+
+    #[test]
+    fn test_encode_is_inverse_of_decode() {
+        for opcode in 0..=u16::MAX {
+            assert_eq!(opcode, Opcode::decode(opcode).encode(), "{:04X}", opcode);
+        }
+    }
+
+    #[test]
+    fn test_display_cowgod() {
+        assert_eq!("CLS", Opcode::decode(0x00E0).to_string());
+        assert_eq!("LD VA, 0x02", Opcode::decode(0x6A02).to_string());
+        assert_eq!("DRW V1, VB, 5", Opcode::decode(0xD1B5).to_string());
+        assert_eq!("JP V0, 0x228", Opcode::decode(0xB228).to_string());
+        assert_eq!("LD [I], V3", Opcode::decode(0xF355).to_string());
+        assert_eq!("DW 0xFFFF", Opcode::decode(0xFFFF).to_string());
+    }
+
+    #[test]
+    fn test_display_octo() {
+        assert_eq!("clear", format!("{:#}", Opcode::decode(0x00E0)));
+        assert_eq!("va := 0x02", format!("{:#}", Opcode::decode(0x6A02)));
+        assert_eq!(
+            "if v3 != 0x10 then",
+            format!("{:#}", Opcode::decode(0x3310))
+        );
+        assert_eq!("v1 =- v2", format!("{:#}", Opcode::decode(0x8127)));
+        assert_eq!("save v1 - v2", format!("{:#}", Opcode::decode(0x5122)));
+        assert_eq!("0xFF 0xFF", format!("{:#}", Opcode::decode(0xFFFF)));
+    }
+
     #[test]
     fn test_decode_unknown_opcode() {
         let opcode = 0xFFFF;
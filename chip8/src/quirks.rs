@@ -0,0 +1,180 @@
+use std::str::FromStr;
+
+//Different CHIP-8 interpreters disagree on how a handful of instructions behave.
+//Each flag picks one interpretation, presets match the well known platforms.
+//See https://github.com/Timendus/chip8-test-suite#quirks-test for details
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    //8xy6/8xyE shift Vx in place instead of shifting Vy into Vx
+    pub shift: bool,
+    //how far Fx55/Fx65 move I
+    pub memory_increment: MemoryIncrement,
+    //Bnnn jumps to nnn + Vx (x being the high nibble of nnn) instead of nnn + V0
+    pub jump: bool,
+    //sprites are clipped at the edge of the screen instead of wrapping around
+    pub clipping: bool,
+    //8xy1/8xy2/8xy3 reset VF to 0
+    pub vf_reset: bool,
+    //Dxyn waits for the next 60hz timer tick before drawing
+    pub display_wait: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryIncrement {
+    //I is left alone
+    None,
+    //I ends up one short of where the VIP leaves it, a CHIP-48 bug
+    X,
+    //I ends up past the last register, like the VIP
+    XPlusOne,
+}
+
+impl MemoryIncrement {
+    //what's added to I after Fx55/Fx65 with this x
+    pub fn amount(self, x: u8) -> u16 {
+        match self {
+            MemoryIncrement::None => 0,
+            MemoryIncrement::X => x as u16,
+            MemoryIncrement::XPlusOne => x as u16 + 1,
+        }
+    }
+}
+
+impl Quirks {
+    pub const COSMAC_VIP: Quirks = Quirks {
+        shift: false,
+        memory_increment: MemoryIncrement::XPlusOne,
+        jump: false,
+        clipping: true,
+        vf_reset: true,
+        display_wait: true,
+    };
+
+    pub const CHIP_48: Quirks = Quirks {
+        shift: true,
+        memory_increment: MemoryIncrement::X,
+        jump: true,
+        clipping: true,
+        vf_reset: false,
+        display_wait: false,
+    };
+
+    pub const SUPER_CHIP: Quirks = Quirks {
+        shift: true,
+        memory_increment: MemoryIncrement::None,
+        jump: true,
+        clipping: true,
+        vf_reset: false,
+        display_wait: false,
+    };
+
+    pub const XO_CHIP: Quirks = Quirks {
+        shift: false,
+        memory_increment: MemoryIncrement::XPlusOne,
+        jump: false,
+        clipping: false,
+        vf_reset: false,
+        display_wait: false,
+    };
+
+    //packs the flags into a byte for save states, one bit per flag in field order.
+    //Increment by x came later and got the next free bit, so older states still load
+    pub fn to_bits(&self) -> u8 {
+        [
+            self.shift,
+            self.memory_increment == MemoryIncrement::XPlusOne,
+            self.jump,
+            self.clipping,
+            self.vf_reset,
+            self.display_wait,
+            self.memory_increment == MemoryIncrement::X,
+        ]
+        .iter()
+        .enumerate()
+        .fold(0, |bits, (i, flag)| bits | (*flag as u8) << i)
+    }
+
+    pub fn from_bits(bits: u8) -> Quirks {
+        Quirks {
+            shift: bits & 1 != 0,
+            memory_increment: if bits & 1 << 1 != 0 {
+                MemoryIncrement::XPlusOne
+            } else if bits & 1 << 6 != 0 {
+                MemoryIncrement::X
+            } else {
+                MemoryIncrement::None
+            },
+            jump: bits & 1 << 2 != 0,
+            clipping: bits & 1 << 3 != 0,
+            vf_reset: bits & 1 << 4 != 0,
+            display_wait: bits & 1 << 5 != 0,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::COSMAC_VIP
+    }
+}
+
+//preset names as used on the command line
+impl FromStr for Quirks {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "vip" | "cosmac-vip" | "chip-8" | "chip8" => Ok(Quirks::COSMAC_VIP),
+            "chip-48" | "chip48" => Ok(Quirks::CHIP_48),
+            "super-chip" | "superchip" | "schip" => Ok(Quirks::SUPER_CHIP),
+            "xo-chip" | "xochip" => Ok(Quirks::XO_CHIP),
+            _ => Err(format!(
+                "unknown quirks preset '{}', expected one of vip, chip-48, schip, xo-chip",
+                name
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bits_round_trip() {
+        for quirks in [
+            Quirks::COSMAC_VIP,
+            Quirks::CHIP_48,
+            Quirks::SUPER_CHIP,
+            Quirks::XO_CHIP,
+        ] {
+            assert_eq!(quirks, Quirks::from_bits(quirks.to_bits()));
+        }
+        //states from before increment by x
+        assert_eq!(Quirks::COSMAC_VIP, Quirks::from_bits(0b111010));
+        assert_eq!(Quirks::SUPER_CHIP, Quirks::from_bits(0b001101));
+    }
+
+    #[test]
+    fn test_presets_differ() {
+        //CHIP-48 only differs from SUPER-CHIP in moving I by x on load and store
+        assert_eq!(
+            Quirks {
+                memory_increment: MemoryIncrement::X,
+                ..Quirks::SUPER_CHIP
+            },
+            Quirks::CHIP_48
+        );
+        assert_ne!(Quirks::SUPER_CHIP, Quirks::CHIP_48);
+        assert_eq!(4, MemoryIncrement::X.amount(4));
+        assert_eq!(5, MemoryIncrement::XPlusOne.amount(4));
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!(Ok(Quirks::COSMAC_VIP), "vip".parse());
+        assert_eq!(Ok(Quirks::SUPER_CHIP), "SCHIP".parse());
+        assert_eq!(Ok(Quirks::XO_CHIP), "xo-chip".parse());
+        assert!("nope".parse::<Quirks>().is_err());
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::VecDeque;
+use std::mem;
+
+//Ring buffer of save states for stepping a game backwards one frame at a time.
+//Only the newest state is kept whole, every older frame is a delta against the
+//frame after it, which is small since most of memory doesn't change per frame.
+#[derive(Debug)]
+pub struct RewindBuffer {
+    latest: Option<Vec<u8>>,
+    //oldest first, deltas[i] turns frame i + 1 back into frame i
+    deltas: VecDeque<Delta>,
+    max_frames: usize,
+    max_bytes: usize,
+    used_bytes: usize,
+}
+
+#[derive(Debug)]
+enum Delta {
+    //runs of bytes that differ, as (offset, old bytes)
+    Patch(Vec<(usize, Vec<u8>)>),
+    //states of different sizes (resolution change) can't be patched
+    Full(Vec<u8>),
+}
+
+//bookkeeping cost of a patch run on top of its bytes
+const RUN_OVERHEAD: usize = mem::size_of::<(usize, Vec<u8>)>();
+
+impl Delta {
+    //delta that turns newer back into older
+    fn between(older: &[u8], newer: &[u8]) -> Self {
+        if older.len() != newer.len() {
+            return Delta::Full(older.to_vec());
+        }
+        let mut runs = Vec::new();
+        let mut i = 0;
+        while i < older.len() {
+            if older[i] == newer[i] {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            while i < older.len() && older[i] != newer[i] {
+                i += 1;
+            }
+            runs.push((start, older[start..i].to_vec()));
+        }
+        Delta::Patch(runs)
+    }
+
+    fn apply(self, newer: Vec<u8>) -> Vec<u8> {
+        match self {
+            Delta::Patch(runs) => {
+                let mut older = newer;
+                for (offset, bytes) in runs {
+                    older[offset..offset + bytes.len()].copy_from_slice(&bytes);
+                }
+                older
+            }
+            Delta::Full(older) => older,
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Delta::Patch(runs) => runs.iter().map(|(_, b)| b.len() + RUN_OVERHEAD).sum(),
+            Delta::Full(older) => older.len(),
+        }
+    }
+}
+
+impl RewindBuffer {
+    //max_frames is how far back we can go, max_bytes caps the memory the deltas use
+    pub fn new(max_frames: usize, max_bytes: usize) -> Self {
+        RewindBuffer {
+            latest: None,
+            deltas: VecDeque::new(),
+            max_frames,
+            max_bytes,
+            used_bytes: 0,
+        }
+    }
+
+    //record the state of the frame that just ran
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(previous) = self.latest.take() {
+            let delta = Delta::between(&previous, &state);
+            self.used_bytes += delta.size();
+            self.deltas.push_back(delta);
+        }
+        self.latest = Some(state);
+
+        //drop the oldest frames until we fit
+        while self.deltas.len() > self.max_frames || self.used_bytes > self.max_bytes {
+            match self.deltas.pop_front() {
+                Some(delta) => self.used_bytes -= delta.size(),
+                None => break,
+            }
+        }
+    }
+
+    //steps back one frame and returns its state, None once the oldest frame is reached
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let delta = self.deltas.pop_back()?;
+        self.used_bytes -= delta.size();
+        let latest = self.latest.take()?;
+        let previous = delta.apply(latest);
+        self.latest = Some(previous.clone());
+        Some(previous)
+    }
+
+    //number of frames we can step back
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+        self.used_bytes = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(n: u8) -> Vec<u8> {
+        let mut state = vec![0; 64];
+        state[10] = n;
+        state[40] = n.wrapping_mul(3);
+        state
+    }
+
+    #[test]
+    fn test_pop_walks_backwards() {
+        let mut rewind = RewindBuffer::new(10, usize::MAX);
+        for n in 0..5 {
+            rewind.push(frame(n));
+        }
+        assert_eq!(4, rewind.len());
+
+        for n in (0..4).rev() {
+            assert_eq!(Some(frame(n)), rewind.pop());
+        }
+        assert_eq!(None, rewind.pop());
+    }
+
+    #[test]
+    fn test_push_after_pop_continues_from_there() {
+        let mut rewind = RewindBuffer::new(10, usize::MAX);
+        rewind.push(frame(0));
+        rewind.push(frame(1));
+        rewind.push(frame(2));
+        assert_eq!(Some(frame(1)), rewind.pop());
+
+        rewind.push(frame(7));
+        assert_eq!(Some(frame(1)), rewind.pop());
+        assert_eq!(Some(frame(0)), rewind.pop());
+    }
+
+    #[test]
+    fn test_max_frames() {
+        let mut rewind = RewindBuffer::new(3, usize::MAX);
+        for n in 0..10 {
+            rewind.push(frame(n));
+        }
+        assert_eq!(3, rewind.len());
+        assert_eq!(Some(frame(8)), rewind.pop());
+        assert_eq!(Some(frame(7)), rewind.pop());
+        assert_eq!(Some(frame(6)), rewind.pop());
+        assert_eq!(None, rewind.pop());
+    }
+
+    #[test]
+    fn test_memory_cap() {
+        //every delta is two single byte runs
+        let cap = 3 * 2 * (1 + RUN_OVERHEAD);
+        let mut rewind = RewindBuffer::new(100, cap);
+        for n in 1..10 {
+            rewind.push(frame(n));
+        }
+        assert_eq!(3, rewind.len());
+    }
+
+    #[test]
+    fn test_states_of_different_size() {
+        let mut rewind = RewindBuffer::new(10, usize::MAX);
+        rewind.push(vec![1; 8]);
+        rewind.push(vec![2; 16]);
+        rewind.push(vec![3; 8]);
+        assert_eq!(Some(vec![2; 16]), rewind.pop());
+        assert_eq!(Some(vec![1; 8]), rewind.pop());
+    }
+}
@@ -0,0 +1,79 @@
+use std::fmt;
+
+//Source of random bytes for Cxkk.
+//State has to fit in a u64 so it can go into save states and be restored exactly.
+pub trait RandomSource: fmt::Debug + Send {
+    fn next_u8(&mut self) -> u8;
+    fn state(&self) -> u64;
+    fn set_state(&mut self, state: u64);
+}
+
+//SplitMix64, tiny and good enough for games
+//https://prng.di.unimi.it/splitmix64.c
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        SplitMix64 { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+impl RandomSource for SplitMix64 {
+    fn next_u8(&mut self) -> u8 {
+        //top bits are the best mixed
+        (self.next_u64() >> 56) as u8
+    }
+
+    fn state(&self) -> u64 {
+        self.state
+    }
+
+    fn set_state(&mut self, state: u64) {
+        self.state = state;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_sequence() {
+        let mut a = SplitMix64::new(1234);
+        let mut b = SplitMix64::new(1234);
+        let a: Vec<u8> = (0..32).map(|_| a.next_u8()).collect();
+        let b: Vec<u8> = (0..32).map(|_| b.next_u8()).collect();
+        assert_eq!(a, b);
+        //not stuck on one value
+        assert!(a.iter().any(|n| *n != a[0]));
+    }
+
+    #[test]
+    fn test_known_values() {
+        //first output for seed 0 from the reference implementation
+        let mut rng = SplitMix64::new(0);
+        assert_eq!(0xE220A8397B1DCDAF, rng.next_u64());
+    }
+
+    #[test]
+    fn test_restore_state() {
+        let mut rng = SplitMix64::new(99);
+        rng.next_u8();
+        let state = rng.state();
+        let expected = rng.next_u8();
+        rng.next_u8();
+        rng.set_state(state);
+        assert_eq!(expected, rng.next_u8());
+    }
+}
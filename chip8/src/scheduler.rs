@@ -0,0 +1,126 @@
+use std::time::{Duration, Instant};
+
+pub const TIMER_HZ: u32 = 60;
+const NANOS_PER_SECOND: u128 = 1_000_000_000;
+//after a stall (window drag, debugger, sleep) don't try to run everything we missed
+pub const MAX_CATCH_UP: Duration = Duration::from_millis(250);
+
+//What the frontend should run to catch up with the wall clock
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Due {
+    pub instructions: u32,
+    pub timer_ticks: u32,
+}
+
+//Turns elapsed wall clock time into instructions and 60hz timer ticks.
+//Leftover time carries over exactly, so nothing drifts no matter how
+//often the frontend polls or what the monitor refresh rate is.
+#[derive(Debug, Clone)]
+pub struct Scheduler {
+    instructions_per_second: u32,
+    //elapsed nanos scaled by the rate, a whole NANOS_PER_SECOND is one unit due
+    cpu_budget: u128,
+    timer_budget: u128,
+    last_update: Option<Instant>,
+}
+
+impl Scheduler {
+    pub fn new(instructions_per_second: u32) -> Self {
+        Scheduler {
+            instructions_per_second,
+            cpu_budget: 0,
+            timer_budget: 0,
+            last_update: None,
+        }
+    }
+
+    pub fn instructions_per_second(&self) -> u32 {
+        self.instructions_per_second
+    }
+
+    //measures against a monotonic clock, the first call only starts the clock
+    pub fn update(&mut self, now: Instant) -> Due {
+        let elapsed = match self.last_update {
+            Some(last) => now.saturating_duration_since(last),
+            None => Duration::ZERO,
+        };
+        self.last_update = Some(now);
+        self.advance(elapsed)
+    }
+
+    pub fn advance(&mut self, elapsed: Duration) -> Due {
+        let nanos = elapsed.min(MAX_CATCH_UP).as_nanos();
+        self.cpu_budget += nanos * self.instructions_per_second as u128;
+        self.timer_budget += nanos * TIMER_HZ as u128;
+
+        let instructions = self.cpu_budget / NANOS_PER_SECOND;
+        self.cpu_budget -= instructions * NANOS_PER_SECOND;
+        let timer_ticks = self.timer_budget / NANOS_PER_SECOND;
+        self.timer_budget -= timer_ticks * NANOS_PER_SECOND;
+
+        Due {
+            instructions: instructions as u32,
+            timer_ticks: timer_ticks as u32,
+        }
+    }
+
+    //forget about time that passed, e.g. while paused
+    pub fn reset(&mut self) {
+        self.cpu_budget = 0;
+        self.timer_budget = 0;
+        self.last_update = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_one_second() {
+        let mut scheduler = Scheduler::new(700);
+        let mut total = Due::default();
+        //4 updates of 250ms, the catch up limit
+        for _ in 0..4 {
+            let due = scheduler.advance(Duration::from_millis(250));
+            total.instructions += due.instructions;
+            total.timer_ticks += due.timer_ticks;
+        }
+        assert_eq!(700, total.instructions);
+        assert_eq!(60, total.timer_ticks);
+    }
+
+    #[test]
+    fn test_no_drift_at_odd_refresh_rates() {
+        //144hz monitor for 10 seconds
+        let mut scheduler = Scheduler::new(1000);
+        let frame = Duration::from_nanos(NANOS_PER_SECOND as u64 / 144);
+        let mut total = Due::default();
+        for _ in 0..1440 {
+            let due = scheduler.advance(frame);
+            total.instructions += due.instructions;
+            total.timer_ticks += due.timer_ticks;
+        }
+        //frame rounds down a few nanos, so allow being one short
+        assert!((9999..=10000).contains(&total.instructions));
+        assert!((599..=600).contains(&total.timer_ticks));
+    }
+
+    #[test]
+    fn test_catch_up_is_capped() {
+        let mut scheduler = Scheduler::new(600);
+        let due = scheduler.advance(Duration::from_secs(10));
+        assert_eq!(150, due.instructions);
+        assert_eq!(15, due.timer_ticks);
+    }
+
+    #[test]
+    fn test_first_update_starts_clock() {
+        let mut scheduler = Scheduler::new(600);
+        let start = Instant::now();
+        assert_eq!(Due::default(), scheduler.update(start));
+        let due = scheduler.update(start + Duration::from_millis(100));
+        assert_eq!(60, due.instructions);
+        assert_eq!(6, due.timer_ticks);
+    }
+}
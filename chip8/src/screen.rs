@@ -0,0 +1,193 @@
+use crate::chip::Frame;
+use crate::state::crc32;
+
+//background, plane 1, plane 2, both planes
+pub type Palette = [(u8, u8, u8); 4];
+pub const DEFAULT_PALETTE: Palette = [(0, 0, 0), (255, 255, 255), (170, 170, 170), (85, 85, 85)];
+
+//one character per color index, so 4 color XO-CHIP screens still read
+const ASCII_PIXELS: [char; 4] = ['.', '#', 'o', '@'];
+//indexed by top pixel on, bottom pixel on
+const HALF_BLOCKS: [[char; 2]; 2] = [[' ', '▄'], ['▀', '█']];
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+//8 bit indexes into a PLTE chunk
+const PNG_BIT_DEPTH: u8 = 8;
+const PNG_COLOR_TYPE_PALETTE: u8 = 3;
+//deflate stored blocks hold at most this many bytes
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+//One line per row, . for off and # for on, o and @ for the second plane
+pub fn ascii(frame: &Frame) -> String {
+    let mut text = String::with_capacity((frame.width + 1) * frame.height);
+    for row in frame.pixels.chunks(frame.width) {
+        text.extend(row.iter().map(|pixel| ASCII_PIXELS[*pixel as usize & 3]));
+        text.push('\n');
+    }
+    text
+}
+
+//Two rows of pixels per line using the upper, lower and full block characters, for terminals
+//where a character cell is about twice as tall as it is wide. Any plane counts as on
+pub fn half_blocks(frame: &Frame) -> String {
+    let mut text = String::with_capacity((frame.width * 3 + 1) * frame.height.div_ceil(2));
+    for rows in frame.pixels.chunks(frame.width * 2) {
+        let (top, bottom) = rows.split_at(frame.width.min(rows.len()));
+        for (x, pixel) in top.iter().enumerate() {
+            let lower = bottom.get(x).is_some_and(|pixel| *pixel != 0);
+            text.push(HALF_BLOCKS[(*pixel != 0) as usize][lower as usize]);
+        }
+        text.push('\n');
+    }
+    text
+}
+
+//Identifies what's on screen, the size is included so a blank hires screen differs from lores
+pub fn hash(frame: &Frame) -> u32 {
+    let mut data = Vec::with_capacity(4 + frame.pixels.len());
+    data.extend_from_slice(&(frame.width as u16).to_be_bytes());
+    data.extend_from_slice(&(frame.height as u16).to_be_bytes());
+    data.extend_from_slice(frame.pixels);
+    crc32(&data)
+}
+
+//Encodes the screen as a paletted PNG, each CHIP-8 pixel scale pixels wide.
+//The image data is stored uncompressed, screens are small enough that it doesn't matter
+pub fn png(frame: &Frame, palette: &Palette, scale: usize) -> Vec<u8> {
+    let scale = scale.max(1);
+    let width = frame.width * scale;
+    let height = frame.height * scale;
+
+    //each scanline starts with filter type 0, none
+    let mut scanlines = Vec::with_capacity((width + 1) * height);
+    for row in frame.pixels.chunks(frame.width) {
+        let mut line = Vec::with_capacity(width + 1);
+        line.push(0);
+        for pixel in row {
+            line.extend(std::iter::repeat_n(*pixel & 3, scale));
+        }
+        for _ in 0..scale {
+            scanlines.extend_from_slice(&line);
+        }
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    //bit depth, color type, then default compression, filter and no interlace
+    header.extend_from_slice(&[PNG_BIT_DEPTH, PNG_COLOR_TYPE_PALETTE, 0, 0, 0]);
+    let colors: Vec<u8> = palette.iter().flat_map(|(r, g, b)| [*r, *g, *b]).collect();
+
+    let mut png = PNG_SIGNATURE.to_vec();
+    png_chunk(&mut png, b"IHDR", &header);
+    png_chunk(&mut png, b"PLTE", &colors);
+    png_chunk(&mut png, b"IDAT", &zlib_stored(&scanlines));
+    png_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+//length | type | data | crc32 of type and data
+fn png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+//a zlib stream made of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    //deflate with a 32K window, no preset dictionary
+    let mut stream = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        stream.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        stream.push(last as u8);
+        stream.extend_from_slice(&len.to_le_bytes());
+        stream.extend_from_slice(&(!len).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD_ADLER: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + *byte as u32) % MOD_ADLER;
+        b = (b + a) % MOD_ADLER;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(pixels: &[u8]) -> Frame<'_> {
+        Frame {
+            width: 4,
+            height: 2,
+            pixels,
+        }
+    }
+
+    #[test]
+    fn test_ascii() {
+        assert_eq!("#..#\n.o@.\n", ascii(&frame(&[1, 0, 0, 1, 0, 2, 3, 0])));
+    }
+
+    #[test]
+    fn test_half_blocks() {
+        assert_eq!("▀ █▄\n", half_blocks(&frame(&[1, 0, 2, 0, 0, 0, 1, 1])));
+    }
+
+    #[test]
+    fn test_hash() {
+        let pixels = [0; 8];
+        let blank = frame(&pixels);
+        let wide = Frame {
+            width: 8,
+            height: 1,
+            pixels: &pixels,
+        };
+        assert_ne!(hash(&blank), hash(&wide));
+        assert_ne!(hash(&blank), hash(&frame(&[0, 0, 0, 0, 0, 0, 0, 1])));
+        assert_eq!(hash(&blank), hash(&frame(&[0; 8])));
+    }
+
+    #[test]
+    fn test_adler32() {
+        assert_eq!(0x11E60398, adler32(b"Wikipedia"));
+    }
+
+    #[test]
+    fn test_png() {
+        let png = png(&frame(&[1, 0, 0, 1, 0, 2, 3, 0]), &DEFAULT_PALETTE, 2);
+        assert_eq!(PNG_SIGNATURE, png[..8]);
+        //IHDR says 8x4 after scaling
+        assert_eq!(b"IHDR", &png[12..16]);
+        assert_eq!([0, 0, 0, 8, 0, 0, 0, 4], png[16..24]);
+        assert_eq!(b"IEND", &png[png.len() - 8..png.len() - 4]);
+        //4 scanlines of a filter byte and 8 pixels, in one stored block
+        let idat = png.windows(4).position(|w| w == b"IDAT").unwrap();
+        let length = u32::from_be_bytes(png[idat - 4..idat].try_into().unwrap());
+        assert_eq!(2 + 5 + 4 * 9 + 4, length);
+        assert_eq!([0, 1, 1, 0, 0, 0, 0, 1, 1], png[idat + 11..idat + 20]);
+    }
+
+    #[test]
+    fn test_zlib_splits_blocks() {
+        let data = vec![7; MAX_STORED_BLOCK + 1];
+        let stream = zlib_stored(&data);
+        assert_eq!(2 + 5 + MAX_STORED_BLOCK + 5 + 1 + 4, stream.len());
+        //first block isn't final, second is
+        assert_eq!(0, stream[2]);
+        assert_eq!(1, stream[2 + 5 + MAX_STORED_BLOCK]);
+    }
+}
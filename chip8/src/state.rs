@@ -0,0 +1,223 @@
+use crate::error::StateError;
+
+//Save state layout, all numbers little endian
+//magic (4) | version (2) | payload length (4) | payload | crc32 of payload (4)
+pub const STATE_MAGIC: [u8; 4] = *b"C8ST";
+pub const STATE_VERSION: u16 = 2;
+const HEADER_SIZE: usize = 10;
+const CHECKSUM_SIZE: usize = 4;
+
+//CRC-32 (IEEE) a byte at a time, rewind checksums a whole state every frame
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in data {
+        crc = (crc >> 8) ^ CRC_TABLE[((crc ^ *byte as u32) & 0xFF) as usize];
+    }
+    !crc
+}
+
+//what the 8 bitwise steps do to each low byte
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut crc = n as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+            bit += 1;
+        }
+        table[n] = crc;
+        n += 1;
+    }
+    table
+};
+
+pub(crate) struct StateWriter {
+    payload: Vec<u8>,
+}
+
+impl StateWriter {
+    pub(crate) fn new() -> Self {
+        StateWriter {
+            payload: Vec::new(),
+        }
+    }
+
+    pub(crate) fn u8(&mut self, value: u8) {
+        self.payload.push(value);
+    }
+
+    pub(crate) fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub(crate) fn u16(&mut self, value: u16) {
+        self.payload.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn u32(&mut self, value: u32) {
+        self.payload.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn u64(&mut self, value: u64) {
+        self.payload.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn bytes(&mut self, bytes: &[u8]) {
+        self.payload.extend_from_slice(bytes);
+    }
+
+    //wraps the payload in the header and checksum
+    pub(crate) fn finish(self) -> Vec<u8> {
+        let mut state = Vec::with_capacity(HEADER_SIZE + self.payload.len() + CHECKSUM_SIZE);
+        state.extend_from_slice(&STATE_MAGIC);
+        state.extend_from_slice(&STATE_VERSION.to_le_bytes());
+        state.extend_from_slice(&(self.payload.len() as u32).to_le_bytes());
+        state.extend_from_slice(&self.payload);
+        state.extend_from_slice(&crc32(&self.payload).to_le_bytes());
+        state
+    }
+}
+
+pub(crate) struct StateReader<'a> {
+    payload: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    //validates the header and checksum before anything is read
+    pub(crate) fn open(state: &'a [u8]) -> Result<Self, StateError> {
+        if state.len() < HEADER_SIZE + CHECKSUM_SIZE {
+            return Err(StateError::Truncated);
+        }
+        if state[0..4] != STATE_MAGIC {
+            return Err(StateError::BadMagic);
+        }
+        let version = u16::from_le_bytes([state[4], state[5]]);
+        if version != STATE_VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        let length = u32::from_le_bytes([state[6], state[7], state[8], state[9]]) as usize;
+        if state.len() != HEADER_SIZE + length + CHECKSUM_SIZE {
+            return Err(StateError::Truncated);
+        }
+        let payload = &state[HEADER_SIZE..HEADER_SIZE + length];
+        let checksum = &state[HEADER_SIZE + length..];
+        if crc32(payload).to_le_bytes() != checksum {
+            return Err(StateError::ChecksumMismatch);
+        }
+        Ok(StateReader {
+            payload,
+            position: 0,
+        })
+    }
+
+    pub(crate) fn bytes(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        let end = self.position + len;
+        if end > self.payload.len() {
+            return Err(StateError::Truncated);
+        }
+        let bytes = &self.payload[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub(crate) fn bool(&mut self) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::Corrupt),
+        }
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, StateError> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, StateError> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, StateError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    //fails if anything is left over, a sign the state doesn't match this version
+    pub(crate) fn finish(self) -> Result<(), StateError> {
+        if self.position != self.payload.len() {
+            return Err(StateError::Corrupt);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        //standard check value
+        assert_eq!(0xCBF43926, crc32(b"123456789"));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut writer = StateWriter::new();
+        writer.u8(1);
+        writer.u16(0x1234);
+        writer.u32(0xDEADBEEF);
+        writer.bytes(&[9, 8, 7]);
+        let state = writer.finish();
+
+        let mut reader = StateReader::open(&state).unwrap();
+        assert_eq!(1, reader.u8().unwrap());
+        assert_eq!(0x1234, reader.u16().unwrap());
+        assert_eq!(0xDEADBEEF, reader.u32().unwrap());
+        assert_eq!([9, 8, 7], reader.bytes(3).unwrap());
+        assert!(reader.finish().is_ok());
+    }
+
+    #[test]
+    fn test_open_rejects_bad_state() {
+        let mut writer = StateWriter::new();
+        writer.u8(1);
+        let state = writer.finish();
+
+        let mut bad_magic = state.clone();
+        bad_magic[0] = b'X';
+        assert_eq!(
+            Some(StateError::BadMagic),
+            StateReader::open(&bad_magic).err()
+        );
+
+        let mut bad_version = state.clone();
+        bad_version[4] = 99;
+        assert_eq!(
+            Some(StateError::UnsupportedVersion(99)),
+            StateReader::open(&bad_version).err()
+        );
+
+        let mut bad_checksum = state.clone();
+        bad_checksum[HEADER_SIZE] ^= 0xFF;
+        assert_eq!(
+            Some(StateError::ChecksumMismatch),
+            StateReader::open(&bad_checksum).err()
+        );
+
+        assert_eq!(
+            Some(StateError::Truncated),
+            StateReader::open(&state[..state.len() - 1]).err()
+        );
+    }
+}
@@ -0,0 +1,127 @@
+use crate::debug::parse_value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+//Where an assembled program's labels and source lines ended up, for debuggers.
+//chip8-asm --symbols writes them one per line:
+//  source /home/me/game.8o
+//  label main 0x202
+//  line 12 0x202
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Symbols {
+    //the file the lines are in
+    pub source: Option<String>,
+    pub labels: BTreeMap<String, u16>,
+    //source line and the address of the first byte it assembled to, in address order.
+    //A line can show up more than once, e.g. from a macro body
+    pub lines: Vec<(usize, u16)>,
+}
+
+impl Symbols {
+    //the first line at or after line that has code, with its address
+    pub fn line_address(&self, line: usize) -> Option<(usize, u16)> {
+        self.lines.iter().filter(|(l, _)| *l >= line).min().copied()
+    }
+
+    //the line of the code at addr, or of the code before it when addr is mid instruction
+    pub fn address_line(&self, addr: u16) -> Option<usize> {
+        self.lines
+            .iter()
+            .rev()
+            .find(|(_, a)| *a <= addr)
+            .map(|(line, _)| *line)
+    }
+
+    //the closest label at or before addr, with how far past it addr is
+    pub fn label_before(&self, addr: u16) -> Option<(&str, u16)> {
+        self.labels
+            .iter()
+            .filter(|(_, a)| **a <= addr)
+            .max_by_key(|(_, a)| **a)
+            .map(|(name, a)| (name.as_str(), addr - a))
+    }
+}
+
+impl FromStr for Symbols {
+    type Err = String;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let mut symbols = Symbols::default();
+        for (index, line) in raw.lines().enumerate() {
+            let error = |message: String| format!("line {}: {}", index + 1, message);
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields[..] {
+                [] => (),
+                ["source", ..] => {
+                    //paths can have spaces
+                    let path = line.trim_start().strip_prefix("source").unwrap().trim();
+                    symbols.source = Some(path.to_string());
+                }
+                ["label", name, addr] => {
+                    symbols
+                        .labels
+                        .insert(name.to_string(), parse_value(addr).map_err(error)?);
+                }
+                ["line", number, addr] => {
+                    let number = number
+                        .parse()
+                        .map_err(|_| error(format!("invalid line number '{}'", number)))?;
+                    symbols
+                        .lines
+                        .push((number, parse_value(addr).map_err(error)?));
+                }
+                _ => {
+                    return Err(error(format!(
+                        "expected source, label or line, got '{}'",
+                        line
+                    )))
+                }
+            }
+        }
+        symbols.lines.sort_by_key(|(_, addr)| *addr);
+        Ok(symbols)
+    }
+}
+
+impl fmt::Display for Symbols {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(source) = &self.source {
+            writeln!(f, "source {}", source)?;
+        }
+        for (name, addr) in &self.labels {
+            writeln!(f, "label {} {:#05X}", name, addr)?;
+        }
+        for (line, addr) in &self.lines {
+            writeln!(f, "line {} {:#05X}", line, addr)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_symbols() {
+        let text = "source /tmp/my game.8o\nlabel main 0x202\nlabel sub 0x20A\n\
+                    line 3 0x200\nline 5 0x202\nline 6 0x204\nline 9 0x20A\n";
+        let symbols: Symbols = text.parse().unwrap();
+        assert_eq!(Some("/tmp/my game.8o"), symbols.source.as_deref());
+        assert_eq!(text, symbols.to_string());
+        assert_eq!(Some((5, 0x202)), symbols.line_address(4));
+        assert_eq!(Some((9, 0x20A)), symbols.line_address(9));
+        assert_eq!(None, symbols.line_address(10));
+        assert_eq!(Some(6), symbols.address_line(0x205));
+        assert_eq!(None, symbols.address_line(0x100));
+        assert_eq!(Some(("main", 4)), symbols.label_before(0x206));
+        assert_eq!(None, symbols.label_before(0x200));
+
+        assert_eq!(
+            Err("line 2: invalid line number 'x'".to_string()),
+            "label a 0x200\nline x 0x202".parse::<Symbols>()
+        );
+        assert!("label main".parse::<Symbols>().is_err());
+    }
+}
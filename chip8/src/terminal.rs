@@ -0,0 +1,106 @@
+use std::time::{Duration, Instant};
+
+//Long enough to bridge the pause most terminals leave before they start repeating a held key
+pub const DEFAULT_HOLD: Duration = Duration::from_millis(250);
+
+//The left of a QWERTY keyboard stands in for the keypad, same as the SDL frontend
+//1 2 3 C    1 2 3 4
+//4 5 6 D    Q W E R
+//7 8 9 E    A S D F
+//A 0 B F    Z X C V
+pub fn keypad_key(c: char) -> Option<u8> {
+    match c.to_ascii_lowercase() {
+        '1' => Some(0x1),
+        '2' => Some(0x2),
+        '3' => Some(0x3),
+        '4' => Some(0xC),
+        'q' => Some(0x4),
+        'w' => Some(0x5),
+        'e' => Some(0x6),
+        'r' => Some(0xD),
+        'a' => Some(0x7),
+        's' => Some(0x8),
+        'd' => Some(0x9),
+        'f' => Some(0xE),
+        'z' => Some(0xA),
+        'x' => Some(0x0),
+        'c' => Some(0xB),
+        'v' => Some(0xF),
+        _ => None,
+    }
+}
+
+//Terminals only report key presses, repeated while a key is held down. A key counts as
+//held until no press has come in for the hold time, then it counts as released
+#[derive(Debug, Clone)]
+pub struct HeldKeys {
+    hold: Duration,
+    //when each key runs out
+    until: [Option<Instant>; 16],
+}
+
+impl HeldKeys {
+    pub fn new(hold: Duration) -> Self {
+        HeldKeys {
+            hold,
+            until: [None; 16],
+        }
+    }
+
+    //true if the key wasn't already held, repeats only extend the hold
+    pub fn press(&mut self, key: u8, now: Instant) -> bool {
+        let until = &mut self.until[key as usize & 0xF];
+        let new = until.is_none();
+        *until = Some(now + self.hold);
+        new
+    }
+
+    //for terminals that do report releases, true if the key was held
+    pub fn release(&mut self, key: u8) -> bool {
+        self.until[key as usize & 0xF].take().is_some()
+    }
+
+    //keys whose hold ran out, they're released from here on
+    pub fn expire(&mut self, now: Instant) -> Vec<u8> {
+        let mut released = Vec::new();
+        for (key, until) in self.until.iter_mut().enumerate() {
+            if until.is_some_and(|until| until <= now) {
+                *until = None;
+                released.push(key as u8);
+            }
+        }
+        released
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keypad_key() {
+        assert_eq!(Some(0xC), keypad_key('4'));
+        assert_eq!(Some(0x5), keypad_key('W'));
+        assert_eq!(Some(0x0), keypad_key('x'));
+        assert_eq!(None, keypad_key('5'));
+    }
+
+    #[test]
+    fn test_held_keys() {
+        let start = Instant::now();
+        let ms = |ms| start + Duration::from_millis(ms);
+        let mut keys = HeldKeys::new(Duration::from_millis(100));
+        assert!(keys.press(5, start));
+        //a repeat keeps it down past the first timeout
+        assert!(!keys.press(5, ms(80)));
+        assert!(keys.press(6, ms(90)));
+        assert_eq!(Vec::<u8>::new(), keys.expire(ms(120)));
+        assert_eq!(vec![5, 6], keys.expire(ms(190)));
+        assert_eq!(Vec::<u8>::new(), keys.expire(ms(300)));
+
+        assert!(keys.press(5, ms(300)));
+        assert!(keys.release(5));
+        assert!(!keys.release(5));
+        assert_eq!(Vec::<u8>::new(), keys.expire(ms(500)));
+    }
+}
@@ -0,0 +1,433 @@
+use crate::debug::Register;
+use crate::opcode::Opcode;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::str::FromStr;
+
+//binary traces start with this and a version byte
+const MAGIC: &[u8; 4] = b"C8TR";
+//version 2 added the address word after F000
+const VERSION: u8 = 2;
+//register tags in binary records, V0-VF are 0x0-0xF
+const TAG_I: u8 = 0x10;
+const TAG_SP: u8 = 0x11;
+//F000 NNNN is the only 4 byte instruction
+const LONG_I_OPCODE: u16 = 0xF000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TraceFormat {
+    //one readable line per instruction
+    #[default]
+    Text,
+    //see write_binary for the layout
+    Binary,
+}
+
+impl FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "text" => Ok(TraceFormat::Text),
+            "binary" => Ok(TraceFormat::Binary),
+            _ => Err(format!(
+                "unknown trace format '{}', expected text or binary",
+                name
+            )),
+        }
+    }
+}
+
+//A register the instruction changed, only V0-VF, I and SP are traced
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Change {
+    pub register: Register,
+    pub old: u16,
+    pub new: u16,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.register {
+            Register::I => write!(f, "I {:04X}->{:04X}", self.old, self.new),
+            Register::Sp => write!(f, "SP {}->{}", self.old, self.new),
+            register => write!(f, "{} {:02X}->{:02X}", register, self.old, self.new),
+        }
+    }
+}
+
+//One executed instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceRecord {
+    pub pc: u16,
+    pub opcode: u16,
+    //the address word after F000, None for every other instruction
+    pub operand: Option<u16>,
+    pub decoded: Opcode,
+    pub changes: Vec<Change>,
+}
+
+//200: 6A02  LD VA, 0x02             VA 00->02
+//202: F000 1234  LD I, LONG 0x1234  I 0000->1234
+impl fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.operand {
+            Some(nnnn) => {
+                let text = format!("LD I, LONG {:#06X}", nnnn);
+                write!(
+                    f,
+                    "{:03X}: {:04X} {:04X}  {:<19}",
+                    self.pc, self.opcode, nnnn, text
+                )?
+            }
+            None => {
+                let text = self.decoded.to_string();
+                write!(f, "{:03X}: {:04X}  {:<24}", self.pc, self.opcode, text)?
+            }
+        }
+        for change in &self.changes {
+            write!(f, " {}", change)?;
+        }
+        Ok(())
+    }
+}
+
+//The traced registers, taken before and after each instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Snapshot {
+    pub registers: [u8; 16],
+    pub i: u16,
+    pub sp: u16,
+}
+
+impl TraceRecord {
+    pub(crate) fn new(
+        pc: u16,
+        opcode: u16,
+        operand: Option<u16>,
+        before: &Snapshot,
+        after: &Snapshot,
+    ) -> Self {
+        let mut changes: Vec<Change> = (0..16)
+            .map(|x| {
+                (
+                    Register::V(x as u8),
+                    before.registers[x],
+                    after.registers[x],
+                )
+            })
+            .filter(|(_, old, new)| old != new)
+            .map(|(register, old, new)| Change {
+                register,
+                old: old as u16,
+                new: new as u16,
+            })
+            .collect();
+        for (register, old, new) in [
+            (Register::I, before.i, after.i),
+            (Register::Sp, before.sp, after.sp),
+        ] {
+            if old != new {
+                changes.push(Change { register, old, new });
+            }
+        }
+        TraceRecord {
+            pc,
+            opcode,
+            operand,
+            decoded: Opcode::decode(opcode),
+            changes,
+        }
+    }
+
+    //pc, opcode, the address word for F000 only, change count then a tag, old and new
+    //value for each change. Everything is big endian, I takes two bytes per value and
+    //the rest take one
+    pub fn write_binary<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut record = Vec::with_capacity(7 + self.changes.len() * 5);
+        record.extend_from_slice(&self.pc.to_be_bytes());
+        record.extend_from_slice(&self.opcode.to_be_bytes());
+        if self.opcode == LONG_I_OPCODE {
+            record.extend_from_slice(&self.operand.unwrap_or(0).to_be_bytes());
+        }
+        record.push(self.changes.len() as u8);
+        for change in &self.changes {
+            match change.register {
+                Register::I => {
+                    record.push(TAG_I);
+                    record.extend_from_slice(&change.old.to_be_bytes());
+                    record.extend_from_slice(&change.new.to_be_bytes());
+                }
+                register => {
+                    record.push(match register {
+                        Register::V(x) => x,
+                        _ => TAG_SP,
+                    });
+                    record.push(change.old as u8);
+                    record.push(change.new as u8);
+                }
+            }
+        }
+        writer.write_all(&record)
+    }
+}
+
+//Reads back a trace written in the binary format, header included
+pub fn read_binary<R: Read>(mut reader: R) -> io::Result<Vec<TraceRecord>> {
+    let mut header = [0; 5];
+    reader.read_exact(&mut header)?;
+    if &header[..4] != MAGIC {
+        return Err(invalid_data("not a binary trace"));
+    }
+    let version = header[4];
+    if version == 0 || version > VERSION {
+        return Err(invalid_data("unsupported trace version"));
+    }
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    let mut bytes = bytes.as_slice();
+    let mut records = Vec::new();
+    while !bytes.is_empty() {
+        let pc = take_u16(&mut bytes)?;
+        let opcode = take_u16(&mut bytes)?;
+        //version 1 didn't have it
+        let operand = if opcode == LONG_I_OPCODE && version >= 2 {
+            Some(take_u16(&mut bytes)?)
+        } else {
+            None
+        };
+        let count = take_u8(&mut bytes)?;
+        let mut changes = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let (register, old, new) = match take_u8(&mut bytes)? {
+                TAG_I => (Register::I, take_u16(&mut bytes)?, take_u16(&mut bytes)?),
+                TAG_SP => (
+                    Register::Sp,
+                    take_u8(&mut bytes)? as u16,
+                    take_u8(&mut bytes)? as u16,
+                ),
+                x @ 0..=0xF => (
+                    Register::V(x),
+                    take_u8(&mut bytes)? as u16,
+                    take_u8(&mut bytes)? as u16,
+                ),
+                _ => return Err(invalid_data("unknown register in trace")),
+            };
+            changes.push(Change { register, old, new });
+        }
+        records.push(TraceRecord {
+            pc,
+            opcode,
+            operand,
+            decoded: Opcode::decode(opcode),
+            changes,
+        });
+    }
+    Ok(records)
+}
+
+fn take_u8(bytes: &mut &[u8]) -> io::Result<u8> {
+    let (first, rest) = bytes
+        .split_first()
+        .ok_or_else(|| invalid_data("trace ends mid record"))?;
+    *bytes = rest;
+    Ok(*first)
+}
+
+fn take_u16(bytes: &mut &[u8]) -> io::Result<u16> {
+    Ok(u16::from_be_bytes([take_u8(bytes)?, take_u8(bytes)?]))
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+//Writes a record for every instruction the chip runs, see Chip8::set_tracer.
+//Write errors don't stop emulation, tracing stops and finish returns the error
+pub struct Tracer {
+    writer: Box<dyn Write + Send>,
+    format: TraceFormat,
+    wrote_header: bool,
+    error: Option<io::Error>,
+}
+
+impl fmt::Debug for Tracer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tracer")
+            .field("format", &self.format)
+            .field("error", &self.error)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Tracer {
+    //wrap files in a BufWriter, a record is written per instruction
+    pub fn new<W: Write + Send + 'static>(writer: W, format: TraceFormat) -> Self {
+        Tracer {
+            writer: Box::new(writer),
+            format,
+            wrote_header: false,
+            error: None,
+        }
+    }
+
+    pub fn format(&self) -> TraceFormat {
+        self.format
+    }
+
+    pub fn record(&mut self, record: &TraceRecord) {
+        if self.error.is_some() {
+            return;
+        }
+        if let Err(e) = self.write(record) {
+            self.error = Some(e);
+        }
+    }
+
+    fn write(&mut self, record: &TraceRecord) -> io::Result<()> {
+        match self.format {
+            TraceFormat::Text => writeln!(self.writer, "{}", record),
+            TraceFormat::Binary => {
+                if !self.wrote_header {
+                    self.writer.write_all(MAGIC)?;
+                    self.writer.write_all(&[VERSION])?;
+                    self.wrote_header = true;
+                }
+                record.write_binary(&mut self.writer)
+            }
+        }
+    }
+
+    //flushes the writer, reporting the first error hit while tracing
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    //a writer the test can still read after handing it to a tracer
+    #[derive(Clone, Default)]
+    pub(crate) struct Shared(pub(crate) Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn record() -> TraceRecord {
+        let before = Snapshot {
+            registers: [0; 16],
+            i: 0x200,
+            sp: 0,
+        };
+        let mut after = before;
+        after.registers[0xA] = 2;
+        after.i = 0x1234;
+        after.sp = 1;
+        TraceRecord::new(0x200, 0x6A02, None, &before, &after)
+    }
+
+    fn long_i_record() -> TraceRecord {
+        let before = Snapshot {
+            registers: [0; 16],
+            i: 0,
+            sp: 0,
+        };
+        let after = Snapshot {
+            i: 0x1234,
+            ..before
+        };
+        TraceRecord::new(0x202, 0xF000, Some(0x1234), &before, &after)
+    }
+
+    #[test]
+    fn test_changes() {
+        assert_eq!(
+            vec![
+                Change {
+                    register: Register::V(0xA),
+                    old: 0,
+                    new: 2
+                },
+                Change {
+                    register: Register::I,
+                    old: 0x200,
+                    new: 0x1234
+                },
+                Change {
+                    register: Register::Sp,
+                    old: 0,
+                    new: 1
+                },
+            ],
+            record().changes
+        );
+    }
+
+    #[test]
+    fn test_text_format() {
+        assert_eq!(
+            "200: 6A02  LD VA, 0x02              VA 00->02 I 0200->1234 SP 0->1",
+            record().to_string()
+        );
+        assert_eq!(
+            "202: F000 1234  LD I, LONG 0x1234   I 0000->1234",
+            long_i_record().to_string()
+        );
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let output = Shared::default();
+        let mut tracer = Tracer::new(output.clone(), TraceFormat::Binary);
+        tracer.record(&record());
+        tracer.record(&long_i_record());
+        tracer.record(&record());
+        tracer.finish().unwrap();
+
+        let bytes = output.0.lock().unwrap().clone();
+        //header, then 5 bytes of record and 3, 5 and 3 bytes of changes,
+        //7 bytes of record with the address and 5 of changes
+        assert_eq!(5 + 2 * 16 + 12, bytes.len());
+        assert_eq!(&[0xF0, 0x00, 0x12, 0x34, 1], &bytes[23..28]);
+        assert_eq!(
+            vec![record(), long_i_record(), record()],
+            read_binary(bytes.as_slice()).unwrap()
+        );
+        //version 1 traces have no address after F000
+        let old = b"C8TR\x01\x02\x02\xF0\x00\x00";
+        assert_eq!(None, read_binary(&old[..]).unwrap()[0].operand);
+        assert!(read_binary(&bytes[..bytes.len() - 1]).is_err());
+        assert!(read_binary(&b"NOPE\x01"[..]).is_err());
+    }
+
+    #[test]
+    fn test_write_error_is_kept() {
+        struct Broken;
+        impl Write for Broken {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::Error::other("disk full"))
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let mut tracer = Tracer::new(Broken, TraceFormat::Text);
+        tracer.record(&record());
+        tracer.record(&record());
+        assert_eq!("disk full", tracer.finish().unwrap_err().to_string());
+    }
+}
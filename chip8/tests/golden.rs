@@ -0,0 +1,153 @@
+//Golden screen tests: runs roms for a fixed number of frames and compares the screen
+//with the ASCII images in tests/golden. After an intended change in output, regenerate with
+//UPDATE_GOLDEN=1 cargo test --test golden
+//and check the new images in git diff before committing them.
+use chip8::chip::Chip8;
+use chip8::headless::{self, parse_key_script, HeadlessConfig};
+use chip8::quirks::Quirks;
+use chip8::screen;
+use std::path::{Path, PathBuf};
+
+//fixed so Cxkk draws the same every run
+const SEED: u64 = 0;
+const UPDATE_VAR: &str = "UPDATE_GOLDEN";
+
+struct Golden {
+    //image is tests/golden/<name>.txt
+    name: &'static str,
+    rom: &'static str,
+    quirks: Quirks,
+    frames: u64,
+    instructions_per_frame: u32,
+    keys: &'static str,
+}
+
+fn manifest_dir() -> &'static Path {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+}
+
+fn run(golden: &Golden) -> String {
+    let rom_path = manifest_dir().join("../roms").join(golden.rom);
+    let rom = std::fs::read(&rom_path)
+        .unwrap_or_else(|e| panic!("failed to read {}: {}", rom_path.display(), e));
+    let mut chip = Chip8::with_seed(golden.quirks, SEED);
+    chip.load_rom(&rom).unwrap();
+    let config = HeadlessConfig {
+        instructions_per_frame: golden.instructions_per_frame,
+        max_frames: golden.frames,
+        keys: parse_key_script(golden.keys).unwrap(),
+        ..HeadlessConfig::default()
+    };
+    headless::run(&mut chip, &config).unwrap();
+    screen::ascii(&chip.get_display())
+}
+
+fn check(golden: Golden) {
+    let actual = run(&golden);
+    let path: PathBuf = manifest_dir()
+        .join("tests/golden")
+        .join(format!("{}.txt", golden.name));
+    if std::env::var_os(UPDATE_VAR).is_some() {
+        std::fs::write(&path, &actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&path).unwrap_or_else(|e| {
+        panic!(
+            "failed to read {}: {}, run with {}=1 to create it",
+            path.display(),
+            e,
+            UPDATE_VAR
+        )
+    });
+    if let Some(diff) = diff(&expected, &actual) {
+        panic!(
+            "{} doesn't match {}\n{}\nrun with {}=1 to accept the new screen",
+            golden.name,
+            path.display(),
+            diff,
+            UPDATE_VAR
+        );
+    }
+}
+
+//Rows that differ as - expected and + actual, with ^ under the changed pixels.
+//None when they match
+fn diff(expected: &str, actual: &str) -> Option<String> {
+    if expected == actual {
+        return None;
+    }
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    let mut text = String::new();
+    if expected.len() != actual.len() {
+        text += &format!("expected {} rows, got {}\n", expected.len(), actual.len());
+    }
+    for row in 0..expected.len().max(actual.len()) {
+        let (old, new) = (expected.get(row), actual.get(row));
+        if old == new {
+            continue;
+        }
+        let old = old.copied().unwrap_or("");
+        let new = new.copied().unwrap_or("");
+        let marks: String = (0..old.len().max(new.len()))
+            .map(|x| {
+                if old.as_bytes().get(x) == new.as_bytes().get(x) {
+                    ' '
+                } else {
+                    '^'
+                }
+            })
+            .collect();
+        text += &format!(
+            "row {:2} - {}\n       + {}\n         {}\n",
+            row, old, new, marks
+        );
+    }
+    Some(text)
+}
+
+#[test]
+fn test_opcode() {
+    check(Golden {
+        name: "test_opcode",
+        rom: "test_opcode.ch8",
+        quirks: Quirks::COSMAC_VIP,
+        frames: 100,
+        instructions_per_frame: 10,
+        keys: "",
+    });
+}
+
+#[test]
+fn quirks() {
+    check(Golden {
+        name: "5-quirks",
+        rom: "5-quirks.ch8",
+        quirks: Quirks::COSMAC_VIP,
+        frames: 400,
+        instructions_per_frame: 30,
+        //picks CHIP-8 from the menu
+        keys: "100:+1 110:-1",
+    });
+}
+
+#[test]
+fn zero() {
+    check(Golden {
+        name: "zero",
+        rom: "zero.ch8",
+        quirks: Quirks::COSMAC_VIP,
+        frames: 120,
+        instructions_per_frame: 10,
+        keys: "",
+    });
+}
+
+#[test]
+fn test_diff() {
+    assert_eq!(None, diff("..\n##\n", "..\n##\n"));
+    assert_eq!(
+        Some("row  1 - ##.\n       + #.#\n          ^^\n".to_string()),
+        diff("...\n##.\n", "...\n#.#\n")
+    );
+}
@@ -0,0 +1,91 @@
+use chip8::audio::{ToneGenerator, Waveform};
+use chip8::machine::AudioSink;
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::AudioSubsystem;
+
+const SAMPLE_RATE: i32 = 44100;
+//small buffer so the beep starts and stops close to the frame it was asked for
+const BUFFER_SAMPLES: u16 = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioSettings {
+    pub frequency: f32,
+    //0 to 1
+    pub volume: f32,
+    pub waveform: Waveform,
+}
+
+//runs on sdl's audio thread
+struct Beeper {
+    tone: ToneGenerator,
+    active: bool,
+}
+
+impl AudioCallback for Beeper {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        self.tone.fill(out, self.active);
+    }
+}
+
+pub struct Audio {
+    device: AudioDevice<Beeper>,
+}
+
+impl Audio {
+    //starts playing silence straight away, set_active turns the tone on
+    pub fn open(subsystem: &AudioSubsystem, settings: AudioSettings) -> Result<Self, String> {
+        let desired = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE),
+            channels: Some(1),
+            samples: Some(BUFFER_SAMPLES),
+        };
+        let device = subsystem.open_playback(None, &desired, |spec| Beeper {
+            tone: ToneGenerator::new(
+                spec.freq as u32,
+                settings.frequency,
+                settings.volume,
+                settings.waveform,
+            ),
+            active: false,
+        })?;
+        device.resume();
+        Ok(Audio { device })
+    }
+}
+
+impl AudioSink for Audio {
+    fn set_active(&mut self, active: bool) {
+        self.device.lock().active = active;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sdl2::audio::AudioStatus;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_dummy_driver() {
+        //no sound card needed
+        std::env::set_var("SDL_AUDIODRIVER", "dummy");
+        let sdl_context = sdl2::init().unwrap();
+        let subsystem = sdl_context.audio().unwrap();
+        let settings = AudioSettings {
+            frequency: 440.0,
+            volume: 0.5,
+            waveform: Waveform::Square,
+        };
+
+        let mut audio = Audio::open(&subsystem, settings).unwrap();
+        audio.set_active(true);
+        thread::sleep(Duration::from_millis(50));
+        audio.set_active(false);
+
+        assert_eq!(AudioStatus::Playing, audio.device.status());
+        assert!(!audio.device.lock().active);
+    }
+}
@@ -0,0 +1,318 @@
+use chip8::audio::Waveform;
+use chip8::debug::{parse_value, Condition, Watchpoint};
+use chip8::quirks::Quirks;
+use chip8::scheduler::TIMER_HZ;
+pub use chip8::screen::{Palette, DEFAULT_PALETTE};
+use chip8::trace::TraceFormat;
+
+pub const USAGE: &str = "\
+Usage: cpu [OPTIONS] <ROM>
+
+Arguments:
+  <ROM>                 Path to the CHIP-8 program to run
+
+Options:
+      --ipf <N>         Instructions per 60hz frame [default: 10]
+      --hz <N>          Instructions per second, alternative to --ipf
+      --scale <N>       Window pixels per CHIP-8 pixel [default: 16]
+      --quirks <NAME>   Quirks preset: vip, chip-48, schip, xo-chip [default: vip]
+      --seed <N>        Seed for the random number generator
+      --palette <LIST>  Comma separated hex colors: background, plane 1, plane 2, both
+      --tone <HZ>       Beep frequency [default: 440]
+      --volume <N>      Beep volume from 0 to 100 [default: 25]
+      --waveform <NAME> Beep waveform: square, triangle, sawtooth, sine [default: square]
+      --break <ADDR>    Pause at ADDR, e.g. 0x2A4, or '0x2A4,V3 == 0x10' with a condition.
+                        Can be given more than once
+      --watch <RANGE>   Pause when memory is accessed, e.g. 0x300-0x30F,rw.
+                        r, w and x watch reads, writes and executes [default: w]
+      --fullscreen      Start in fullscreen
+      --headless        Run without a window or audio
+      --frames <N>      Stop after N frames, mostly useful with --headless
+      --trace <PATH>    Write every instruction run and the registers it changed to PATH
+      --trace-format <NAME>
+                        text or binary [default: text]
+      --record <PATH>   Record keys to a movie file at PATH, for reproducing a session
+      --replay <PATH>   Play back a movie file, reporting frames where it desyncs.
+                        Quirks, seed and speed come from the movie
+  -h, --help            Print this help
+";
+
+pub const DEFAULT_IPF: u32 = 10;
+pub const DEFAULT_SCALE: u32 = 16;
+pub const DEFAULT_TONE: f32 = 440.0;
+pub const DEFAULT_VOLUME: u32 = 25;
+
+#[derive(Debug, PartialEq)]
+pub struct Options {
+    pub rom_path: String,
+    pub instructions_per_second: u32,
+    pub scale: u32,
+    pub quirks: Quirks,
+    pub seed: Option<u64>,
+    pub palette: Palette,
+    pub tone: f32,
+    //0 to 1
+    pub volume: f32,
+    pub waveform: Waveform,
+    pub breakpoints: Vec<(u16, Option<Condition>)>,
+    pub watchpoints: Vec<Watchpoint>,
+    pub fullscreen: bool,
+    pub headless: bool,
+    pub frames: Option<u64>,
+    pub trace: Option<String>,
+    pub trace_format: TraceFormat,
+    pub movie: Option<MovieFile>,
+}
+
+//--record and --replay, only one at a time
+#[derive(Debug, PartialEq)]
+pub enum MovieFile {
+    Record(String),
+    Replay(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Run(Options),
+    Help,
+}
+
+//args should not include the program name
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Command, String> {
+    let mut args = args.into_iter();
+    let mut rom_path = None;
+    let mut ipf: Option<u32> = None;
+    let mut hz: Option<u32> = None;
+    let mut scale = DEFAULT_SCALE;
+    let mut quirks = Quirks::default();
+    let mut seed = None;
+    let mut palette = DEFAULT_PALETTE;
+    let mut tone = DEFAULT_TONE;
+    let mut volume = DEFAULT_VOLUME;
+    let mut waveform = Waveform::Square;
+    let mut breakpoints = Vec::new();
+    let mut watchpoints = Vec::new();
+    let mut fullscreen = false;
+    let mut headless = false;
+    let mut frames = None;
+    let mut trace = None;
+    let mut trace_format = TraceFormat::default();
+    let mut movie = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--ipf" => ipf = Some(parse_number(&arg, args.next())?),
+            "--hz" => hz = Some(parse_number(&arg, args.next())?),
+            "--scale" => scale = parse_number(&arg, args.next())?,
+            "--quirks" => quirks = value(&arg, args.next())?.parse()?,
+            "--seed" => seed = Some(parse_number(&arg, args.next())?),
+            "--palette" => palette = parse_palette(&value(&arg, args.next())?)?,
+            "--tone" => tone = parse_number(&arg, args.next())?,
+            "--volume" => volume = parse_number(&arg, args.next())?,
+            "--waveform" => waveform = value(&arg, args.next())?.parse()?,
+            "--break" => breakpoints.push(parse_breakpoint(&value(&arg, args.next())?)?),
+            "--watch" => watchpoints.push(value(&arg, args.next())?.parse()?),
+            "--fullscreen" => fullscreen = true,
+            "--headless" => headless = true,
+            "--frames" => frames = Some(parse_number(&arg, args.next())?),
+            "--trace" => trace = Some(value(&arg, args.next())?),
+            "--trace-format" => trace_format = value(&arg, args.next())?.parse()?,
+            "--record" | "--replay" if movie.is_some() => {
+                return Err("use either --record or --replay, and only once".to_string())
+            }
+            "--record" => movie = Some(MovieFile::Record(value(&arg, args.next())?)),
+            "--replay" => movie = Some(MovieFile::Replay(value(&arg, args.next())?)),
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+            _ if rom_path.is_some() => return Err(format!("unexpected argument '{}'", arg)),
+            _ => rom_path = Some(arg),
+        }
+    }
+
+    let instructions_per_second = match (ipf, hz) {
+        (Some(_), Some(_)) => return Err("use either --ipf or --hz, not both".to_string()),
+        (Some(ipf), None) => ipf.saturating_mul(TIMER_HZ),
+        (None, Some(hz)) => hz,
+        (None, None) => DEFAULT_IPF * TIMER_HZ,
+    };
+    if instructions_per_second == 0 {
+        return Err("speed must be at least 1 instruction per second".to_string());
+    }
+    if scale == 0 {
+        return Err("--scale must be at least 1".to_string());
+    }
+    if volume > 100 {
+        return Err("--volume must be between 0 and 100".to_string());
+    }
+    if !(tone.is_finite() && tone > 0.0) {
+        return Err("--tone must be a positive frequency".to_string());
+    }
+    //there are no keys to record without a window
+    if matches!(movie, Some(MovieFile::Record(_))) && headless {
+        return Err("--record needs a window, it can't be used with --headless".to_string());
+    }
+
+    Ok(Command::Run(Options {
+        rom_path: rom_path.ok_or("missing ROM path")?,
+        instructions_per_second,
+        scale,
+        quirks,
+        seed,
+        palette,
+        tone,
+        volume: volume as f32 / 100.0,
+        waveform,
+        breakpoints,
+        watchpoints,
+        fullscreen,
+        headless,
+        frames,
+        trace,
+        trace_format,
+        movie,
+    }))
+}
+
+fn value(option: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("{} needs a value", option))
+}
+
+fn parse_number<T: std::str::FromStr>(option: &str, raw: Option<String>) -> Result<T, String> {
+    let raw = value(option, raw)?;
+    raw.parse()
+        .map_err(|_| format!("invalid value '{}' for {}", raw, option))
+}
+
+//address with an optional condition after a comma
+fn parse_breakpoint(raw: &str) -> Result<(u16, Option<Condition>), String> {
+    match raw.split_once(',') {
+        Some((addr, condition)) => Ok((parse_value(addr.trim())?, Some(condition.parse()?))),
+        None => Ok((parse_value(raw.trim())?, None)),
+    }
+}
+
+//2 to 4 colors, missing ones keep their defaults
+fn parse_palette(raw: &str) -> Result<Palette, String> {
+    let colors: Vec<&str> = raw.split(',').collect();
+    if colors.len() < 2 || colors.len() > 4 {
+        return Err(format!("--palette needs 2 to 4 colors, got '{}'", raw));
+    }
+    let mut palette = DEFAULT_PALETTE;
+    for (slot, color) in palette.iter_mut().zip(colors) {
+        let hex = color.trim_start_matches('#');
+        let rgb = u32::from_str_radix(hex, 16)
+            .ok()
+            .filter(|_| hex.len() == 6)
+            .ok_or_else(|| format!("invalid color '{}', expected rrggbb", color))?;
+        *slot = ((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8);
+    }
+    Ok(palette)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Command, String> {
+        parse_args(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn test_defaults() {
+        assert_eq!(
+            Ok(Command::Run(Options {
+                rom_path: "roms/PONG2".to_string(),
+                instructions_per_second: DEFAULT_IPF * TIMER_HZ,
+                scale: DEFAULT_SCALE,
+                quirks: Quirks::default(),
+                seed: None,
+                palette: DEFAULT_PALETTE,
+                tone: DEFAULT_TONE,
+                volume: 0.25,
+                waveform: Waveform::Square,
+                breakpoints: Vec::new(),
+                watchpoints: Vec::new(),
+                fullscreen: false,
+                headless: false,
+                frames: None,
+                trace: None,
+                trace_format: TraceFormat::Text,
+                movie: None,
+            })),
+            parse(&["roms/PONG2"])
+        );
+    }
+
+    #[test]
+    fn test_options() {
+        let Ok(Command::Run(options)) = parse(&[
+            "--hz",
+            "700",
+            "--quirks",
+            "schip",
+            "--seed",
+            "5",
+            "--palette",
+            "#112233,ffffff",
+            "--volume",
+            "50",
+            "--waveform",
+            "sine",
+            "--break",
+            "0x2A4",
+            "--break",
+            "0x300,V3 == 0x10",
+            "--watch",
+            "0x300-0x30F,rw",
+            "--headless",
+            "--trace",
+            "trace.bin",
+            "--trace-format",
+            "binary",
+            "--replay",
+            "bug.movie",
+            "rom.ch8",
+        ]) else {
+            panic!("expected options");
+        };
+        assert_eq!(700, options.instructions_per_second);
+        assert_eq!(Quirks::SUPER_CHIP, options.quirks);
+        assert_eq!(Some(5), options.seed);
+        assert_eq!((0x11, 0x22, 0x33), options.palette[0]);
+        assert_eq!(DEFAULT_PALETTE[2], options.palette[2]);
+        assert_eq!(0.5, options.volume);
+        assert_eq!(Waveform::Sine, options.waveform);
+        assert_eq!((0x2A4, None), options.breakpoints[0]);
+        assert_eq!(
+            (0x300, Some("V3 == 16".parse().unwrap())),
+            options.breakpoints[1]
+        );
+        assert_eq!(Ok(options.watchpoints[0]), "0x300-0x30f,wr".parse());
+        assert!(options.headless);
+        assert_eq!(Some("trace.bin".to_string()), options.trace);
+        assert_eq!(TraceFormat::Binary, options.trace_format);
+        assert_eq!(
+            Some(MovieFile::Replay("bug.movie".to_string())),
+            options.movie
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(Ok(Command::Help), parse(&["rom", "--help"]));
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["rom", "--ipf"]).is_err());
+        assert!(parse(&["rom", "--ipf", "fast"]).is_err());
+        assert!(parse(&["rom", "--hz", "0"]).is_err());
+        assert!(parse(&["rom", "--ipf", "5", "--hz", "600"]).is_err());
+        assert!(parse(&["rom", "--bogus"]).is_err());
+        assert!(parse(&["rom", "--volume", "101"]).is_err());
+        assert!(parse(&["rom", "--tone", "0"]).is_err());
+        assert!(parse(&["rom", "--palette", "000000"]).is_err());
+        assert!(parse(&["rom", "--break", "0x300,V3"]).is_err());
+        assert!(parse(&["rom", "--trace-format", "csv"]).is_err());
+        assert!(parse(&["rom", "--record", "a", "--replay", "b"]).is_err());
+        assert!(parse(&["rom", "--record", "a", "--headless"]).is_err());
+        assert!(parse(&["a", "b"]).is_err());
+    }
+}
@@ -1,98 +1,423 @@
+mod audio;
+mod cli;
+
+use audio::{Audio, AudioSettings};
 use chip8::chip::Chip8;
-use chip8::chip::{DISPLAY_MAX_X, DISPLAY_MAX_Y};
+use chip8::chip::{Frame, DISPLAY_MAX_X, DISPLAY_MAX_Y};
+use chip8::debug::Register;
+use chip8::machine::{FrameSink, Input, InputSource, Machine, MachineEvent, MovieMode, Step};
+use chip8::movie::{Movie, Player, Recorder, DEFAULT_CHECKSUM_INTERVAL};
+use chip8::rewind::RewindBuffer;
+use chip8::scheduler::TIMER_HZ;
+use chip8::trace::Tracer;
+use cli::{Command, MovieFile};
 use sdl2::keyboard::Keycode;
 use sdl2::pixels::Color;
 use sdl2::render::Canvas;
 use sdl2::video::Window;
+use sdl2::EventPump;
 use sdl2::{event::Event, rect::Rect};
 use std::fs::File;
-use std::io::Read;
+use std::io::{BufWriter, Read};
+use std::process;
 
-const SCALE: u32 = 15;
-const WINDOW_HEIGHT: u32 = DISPLAY_MAX_Y as u32 * SCALE;
-const WINDOW_WIDTH: u32 = DISPLAY_MAX_X as u32 * SCALE;
-const TICKS_PER_FRAME: u8 = 10;
+const SAVE_SLOTS: [Keycode; 4] = [Keycode::F1, Keycode::F2, Keycode::F3, Keycode::F4];
+//how far back holding backspace can go
+const REWIND_SECONDS: usize = 30;
+const REWIND_MAX_BYTES: usize = 64 * 1024 * 1024;
+//debugger keys
+const PAUSE_KEY: Keycode = Keycode::F6;
+const STEP_KEY: Keycode = Keycode::F7;
+const STEP_OVER_KEY: Keycode = Keycode::F8;
+const STEP_OUT_KEY: Keycode = Keycode::F10;
+const TITLE: &str = "Chip-8 Emulator";
 
 fn main() {
-    let file_path = "./roms/PONG2";
-    let file_buffer = Box::new(get_file_buffer(file_path));
+    let options = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(Command::Run(options)) => options,
+        Ok(Command::Help) => {
+            print!("{}", cli::USAGE);
+            return;
+        }
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, cli::USAGE);
+            process::exit(2);
+        }
+    };
+    let file_path = options.rom_path.as_str();
+    let file_buffer = match get_file_buffer(file_path) {
+        Ok(buffer) => buffer,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", file_path, e);
+            process::exit(1);
+        }
+    };
 
-    let mut chip = Chip8::new();
-    chip.load_rom(file_buffer.into_boxed_slice());
+    let (mut chip, movie) = match &options.movie {
+        //the movie knows how the chip was set up
+        Some(MovieFile::Replay(path)) => {
+            let movie = read_movie(path);
+            match movie.start(&file_buffer) {
+                Ok(chip) => (chip, Some(MovieMode::Playing(Player::new(movie)))),
+                Err(e) => {
+                    eprintln!("Failed to replay {}: {}", path, e);
+                    process::exit(1);
+                }
+            }
+        }
+        _ => {
+            let mut chip = match options.seed {
+                Some(seed) => Chip8::with_seed(options.quirks, seed),
+                None => Chip8::new(options.quirks),
+            };
+            if let Err(e) = chip.load_rom(&file_buffer) {
+                eprintln!("Failed to load {}: {}", file_path, e);
+                process::exit(1);
+            }
+            let recorder = matches!(options.movie, Some(MovieFile::Record(_))).then(|| {
+                Recorder::new(
+                    &chip,
+                    &file_buffer,
+                    options.instructions_per_second.div_ceil(TIMER_HZ),
+                    DEFAULT_CHECKSUM_INTERVAL,
+                )
+            });
+            (chip, recorder.map(MovieMode::Recording))
+        }
+    };
+    for (addr, condition) in &options.breakpoints {
+        chip.add_breakpoint(*addr, *condition);
+    }
+    for watchpoint in &options.watchpoints {
+        chip.add_watchpoint(*watchpoint);
+    }
+    if let Some(path) = &options.trace {
+        match File::create(path) {
+            Ok(file) => chip.set_tracer(Tracer::new(BufWriter::new(file), options.trace_format)),
+            Err(e) => {
+                eprintln!("Failed to create {}: {}", path, e);
+                process::exit(1);
+            }
+        }
+    }
+
+    let mut machine = Machine::new(chip, options.instructions_per_second);
+    machine.set_max_frames(options.frames);
+    if let Some(movie) = movie {
+        machine.set_movie(movie);
+    }
+
+    if options.headless {
+        run_headless(machine);
+        return;
+    }
+
+    //one snapshot per frame, played back while backspace is held
+    machine.set_rewind(RewindBuffer::new(
+        REWIND_SECONDS * TIMER_HZ as usize,
+        REWIND_MAX_BYTES,
+    ));
 
     //setup sdl2
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
-    let window = video_subsystem
-        .window("Chip-8 Emulator", WINDOW_WIDTH, WINDOW_HEIGHT)
-        .position_centered()
-        .opengl()
-        .build()
-        .unwrap();
+    let mut window = video_subsystem.window(
+        TITLE,
+        DISPLAY_MAX_X as u32 * options.scale,
+        DISPLAY_MAX_Y as u32 * options.scale,
+    );
+    window.position_centered().opengl();
+    if options.fullscreen {
+        window.fullscreen_desktop();
+    }
+    let window = window.build().unwrap();
+
+    //carry on silently if there is no audio device
+    let settings = AudioSettings {
+        frequency: options.tone,
+        volume: options.volume,
+        waveform: options.waveform,
+    };
+    let mut audio = match sdl_context
+        .audio()
+        .and_then(|subsystem| Audio::open(&subsystem, settings))
+    {
+        Ok(audio) => Some(audio),
+        Err(e) => {
+            eprintln!("Audio disabled: {}", e);
+            None
+        }
+    };
 
     //get canvas
     let mut canvas = window.into_canvas().present_vsync().build().unwrap();
     canvas.clear();
     canvas.present();
 
-    //game loop and event check
-    let mut event_pump = sdl_context.event_pump().unwrap();
-    'gameloop: loop {
-        for evt in event_pump.poll_iter() {
-            match evt {
+    let mut input = SdlInput {
+        event_pump: sdl_context.event_pump().unwrap(),
+        rom_path: file_path,
+        slot: 1,
+    };
+    //vsync only paces presenting, emulation speed comes from the wall clock
+    let mut screen = SdlScreen {
+        canvas,
+        palette: options.palette.map(|(r, g, b)| Color::RGB(r, g, b)),
+    };
+    if let Err(e) = machine.run(&mut input, &mut screen, &mut audio) {
+        eprintln!("Emulation stopped: {}", e);
+    }
+    finish_trace(machine.chip_mut());
+    if let (Some(MovieMode::Recording(recorder)), Some(MovieFile::Record(path))) =
+        (machine.take_movie(), &options.movie)
+    {
+        let frames = recorder.frames();
+        match std::fs::write(path, recorder.finish().to_string()) {
+            Ok(()) => println!("Recorded {} frames to {}", frames, path),
+            Err(e) => eprintln!("Failed to write {}: {}", path, e),
+        }
+    }
+}
+
+//Esc quits, F1-F4 pick the save slot, F5 saves and F9 loads, backspace rewinds.
+//F6 pauses and resumes, while paused F7 steps, F8 steps over and F10 steps out.
+//Keys outside the keypad restart the rom
+struct SdlInput<'a> {
+    event_pump: EventPump,
+    rom_path: &'a str,
+    slot: usize,
+}
+
+impl InputSource for SdlInput<'_> {
+    fn poll(&mut self, chip: &Chip8) -> Vec<Input> {
+        let mut inputs = Vec::new();
+        for evt in self.event_pump.poll_iter() {
+            let input = match evt {
                 Event::Quit { .. }
                 | Event::KeyDown {
                     keycode: Some(Keycode::Escape),
                     ..
+                } => Input::Quit,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F5),
+                    ..
                 } => {
-                    break 'gameloop;
+                    save_state(chip, self.rom_path, self.slot);
+                    continue;
                 }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F9),
+                    ..
+                } => match load_state(self.rom_path, self.slot) {
+                    Some(state) => Input::LoadState(state),
+                    None => continue,
+                },
+                Event::KeyDown {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => Input::Rewind(true),
+                Event::KeyUp {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => Input::Rewind(false),
+                Event::KeyDown {
+                    keycode: Some(PAUSE_KEY),
+                    ..
+                } => Input::Pause,
+                Event::KeyDown {
+                    keycode: Some(STEP_KEY),
+                    ..
+                } => Input::Step(Step::Into),
+                Event::KeyDown {
+                    keycode: Some(STEP_OVER_KEY),
+                    ..
+                } => Input::Step(Step::Over),
+                Event::KeyDown {
+                    keycode: Some(STEP_OUT_KEY),
+                    ..
+                } => Input::Step(Step::Out),
                 Event::KeyDown {
                     keycode: Some(key), ..
-                } => {
-                    if let Some(k) = key2btn(key) {
-                        chip.key_press(k);
-                    } else {
-                        chip.restart();
-                    }
+                } if SAVE_SLOTS.contains(&key) => {
+                    self.slot = SAVE_SLOTS.iter().position(|k| *k == key).unwrap() + 1;
+                    println!("Save slot {}", self.slot);
+                    continue;
                 }
+                Event::KeyDown {
+                    keycode: Some(key), ..
+                } => match key2btn(key) {
+                    Some(k) => Input::Key {
+                        key: k,
+                        pressed: true,
+                    },
+                    None => Input::Restart,
+                },
                 Event::KeyUp {
                     keycode: Some(key), ..
-                } => {
-                    if let Some(k) = key2btn(key) {
-                        chip.key_release(k);
-                    }
-                }
-                _ => (),
+                } => match key2btn(key) {
+                    Some(k) => Input::Key {
+                        key: k,
+                        pressed: false,
+                    },
+                    None => continue,
+                },
+                _ => continue,
+            };
+            inputs.push(input);
+        }
+        inputs
+    }
+}
+
+struct SdlScreen {
+    canvas: Canvas<Window>,
+    palette: [Color; 4],
+}
+
+impl FrameSink for SdlScreen {
+    fn present(&mut self, frame: &Frame) {
+        draw_screen(frame, &mut self.canvas, &self.palette);
+    }
+
+    fn event(&mut self, chip: &Chip8, event: &MachineEvent) {
+        print_event(chip, event);
+        let paused = match event {
+            MachineEvent::Resumed => false,
+            MachineEvent::Paused
+            | MachineEvent::Breakpoint(_)
+            | MachineEvent::Stopped(_)
+            | MachineEvent::Watchpoint(_) => true,
+            _ => return,
+        };
+        let title = if paused {
+            format!("{} - Paused", TITLE)
+        } else {
+            TITLE.to_string()
+        };
+        self.canvas.window_mut().set_title(&title).unwrap();
+    }
+}
+
+//prints what the machine reports, desyncs are counted for the exit status
+#[derive(Default)]
+struct Console {
+    desyncs: usize,
+}
+
+impl FrameSink for Console {
+    fn present(&mut self, _frame: &Frame) {}
+
+    fn event(&mut self, chip: &Chip8, event: &MachineEvent) {
+        if let MachineEvent::Desync(_) = event {
+            self.desyncs += 1;
+        }
+        print_event(chip, event);
+    }
+}
+
+//runs the rom without any sdl as fast as possible, but with the same instructions per frame
+//as in real time. Runs until the rom exits, faults, runs out of frames, hits a breakpoint
+//or a replay finishes
+fn run_headless(mut machine: Machine) {
+    machine.set_realtime(false);
+    let mut console = Console::default();
+    let result = machine.run(&mut (), &mut console, &mut ());
+    finish_trace(machine.chip_mut());
+    match result {
+        Ok(_) => println!("Ran {} frames", machine.frames()),
+        Err(e) => {
+            eprintln!("Emulation stopped: {}", e);
+            process::exit(1);
+        }
+    }
+    if console.desyncs > 0 {
+        process::exit(1);
+    }
+}
+
+fn print_event(chip: &Chip8, event: &MachineEvent) {
+    match event {
+        MachineEvent::Paused => print_debug_state(chip, "Paused"),
+        MachineEvent::Resumed => (),
+        MachineEvent::Breakpoint(_) => print_debug_state(chip, "Breakpoint"),
+        MachineEvent::Stopped(_) => print_debug_state(chip, "Stopped"),
+        MachineEvent::Watchpoint(hits) => {
+            for hit in hits {
+                println!("Watchpoint {}", hit);
             }
+            print_debug_state(chip, "Stopped");
         }
+        MachineEvent::StateLoaded => println!("Loaded state"),
+        MachineEvent::StateRejected(e) => eprintln!("Failed to load state: {}", e),
+        MachineEvent::Desync(desync) => println!("{}", desync),
+        MachineEvent::ReplayFinished => println!("Replay finished"),
+    }
+}
 
-        for _ in 0..=TICKS_PER_FRAME {
-            chip.tick();
+fn read_movie(path: &str) -> Movie {
+    let movie = std::fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| text.parse::<Movie>().map_err(|e| e.to_string()));
+    match movie {
+        Ok(movie) => movie,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", path, e);
+            process::exit(1);
         }
-        chip.tick_timers();
-        draw_screen(&chip, &mut canvas);
     }
 }
 
-fn draw_screen(chip: &Chip8, canvas: &mut Canvas<Window>) {
-    //draw color is black
-    canvas.set_draw_color(Color::RGB(0, 0, 0));
+//flushes the trace file, if there is one
+fn finish_trace(chip: &mut Chip8) {
+    if let Some(Err(e)) = chip.take_tracer().map(Tracer::finish) {
+        eprintln!("Failed to write trace: {}", e);
+    }
+}
+
+//one line of registers for the console, e.g. when a breakpoint is hit
+fn print_debug_state(chip: &Chip8, reason: &str) {
+    let pc = chip.register(Register::Pc);
+    let registers: Vec<String> = (0..16)
+        .map(|x| format!("{}={:02X}", Register::V(x), chip.register(Register::V(x))))
+        .collect();
+    println!(
+        "{} at {:#05X} ({:04X}): {} I={:03X} SP={} DT={} ST={}",
+        reason,
+        pc,
+        chip.opcode_at(pc),
+        registers.join(" "),
+        chip.register(Register::I),
+        chip.register(Register::Sp),
+        chip.register(Register::DelayTimer),
+        chip.register(Register::SoundTimer),
+    );
+}
+
+fn draw_screen(frame: &Frame, canvas: &mut Canvas<Window>, palette: &[Color; 4]) {
+    //draw color is background
+    canvas.set_draw_color(palette[0]);
     canvas.clear();
 
-    let display_buffer = chip.get_display();
+    //hires frames are drawn with smaller pixels in the same window
+    let (window_width, window_height) = canvas.output_size().unwrap();
+    let pixel_width = window_width / frame.width as u32;
+    let pixel_height = window_height / frame.height as u32;
 
-    //set white
-    canvas.set_draw_color(Color::RGB(255, 255, 255));
-    for (i, pixel) in display_buffer.iter().enumerate() {
-        if *pixel {
+    for (i, pixel) in frame.pixels.iter().enumerate() {
+        if *pixel != 0 {
+            canvas.set_draw_color(palette[*pixel as usize]);
             //index % mod max x gives you the remainder disregarding the row, which would be the x
-            let x = (i % DISPLAY_MAX_X) as u32;
+            let x = (i % frame.width) as u32;
             // index / max x gives you which row
-            let y = (i / DISPLAY_MAX_X) as u32;
+            let y = (i / frame.width) as u32;
 
             //draw rectangle with scale
-            let rect = Rect::new((x * SCALE) as i32, (y * SCALE) as i32, SCALE, SCALE);
+            let rect = Rect::new(
+                (x * pixel_width) as i32,
+                (y * pixel_height) as i32,
+                pixel_width,
+                pixel_height,
+            );
             canvas.fill_rect(rect).unwrap();
         }
     }
@@ -121,9 +446,37 @@ fn key2btn(key: Keycode) -> Option<u8> {
     }
 }
 
-fn get_file_buffer(path: &str) -> Vec<u8> {
-    let mut file = File::open(path).unwrap();
+//save states live next to the rom, e.g. roms/PONG2.state1
+fn state_path(rom_path: &str, slot: usize) -> String {
+    format!("{}.state{}", rom_path, slot)
+}
+
+fn save_state(chip: &Chip8, rom_path: &str, slot: usize) {
+    let path = state_path(rom_path, slot);
+    match std::fs::write(&path, chip.save_state()) {
+        Ok(()) => println!("Saved state to {}", path),
+        Err(e) => eprintln!("Failed to save state to {}: {}", path, e),
+    }
+}
+
+//the machine loads it, so a state that doesn't make sense is reported there
+fn load_state(rom_path: &str, slot: usize) -> Option<Vec<u8>> {
+    let path = state_path(rom_path, slot);
+    match std::fs::read(&path) {
+        Ok(state) => {
+            println!("Loading state from {}", path);
+            Some(state)
+        }
+        Err(e) => {
+            eprintln!("Failed to load state from {}: {}", path, e);
+            None
+        }
+    }
+}
+
+fn get_file_buffer(path: &str) -> std::io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
     let mut buffer = Vec::new();
-    file.read_to_end(&mut buffer).unwrap();
-    buffer
+    file.read_to_end(&mut buffer)?;
+    Ok(buffer)
 }